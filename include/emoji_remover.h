@@ -0,0 +1,48 @@
+/*
+ * C interface to the emoji-remover cleaning engine. Link against the
+ * libemoji_remover static or shared library built by `cargo build --lib`.
+ */
+
+#ifndef EMOJI_REMOVER_H
+#define EMOJI_REMOVER_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define EMOJI_REMOVER_OK 0
+/* A required pointer was null. */
+#define EMOJI_REMOVER_NULL_ARGUMENT 1
+/* The source, language or a marker is not valid UTF-8. */
+#define EMOJI_REMOVER_INVALID_UTF8 2
+/* A marker is empty, so it would match every comment. */
+#define EMOJI_REMOVER_EMPTY_MARKER 3
+
+/*
+ * Removes the marked comments from the source_len bytes at source, written in
+ * language, the extension of its files (e.g. "rs" or "py"). A comment is
+ * marked by any of the marker_count strings at markers, or by the default
+ * marker when marker_count is 0, in which case markers may be NULL. On
+ * success, *output points to the cleaned text, *output_len bytes followed by
+ * a NUL, and *removed holds the number of comments removed if removed is not
+ * NULL. Returns one of the EMOJI_REMOVER_* codes.
+ */
+int emoji_remover_clean(const char *source,
+                        size_t source_len,
+                        const char *language,
+                        const char *const *markers,
+                        size_t marker_count,
+                        char **output,
+                        size_t *output_len,
+                        size_t *removed);
+
+/* Releases text returned by emoji_remover_clean. NULL is ignored. */
+void emoji_remover_free(char *output, size_t output_len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* EMOJI_REMOVER_H */
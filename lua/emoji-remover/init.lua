@@ -17,19 +17,8 @@ local function get_binary_path()
 	return bin_path
 end
 
-function M.clean(opts)
-	opts = opts or {}
-	local bin = get_binary_path()
-
-	if vim.fn.executable(bin) == 0 then
-		vim.notify("Emoji Remover binary not found. Did you run 'cargo build --release'?", vim.log.levels.ERROR)
-		return
-	end
-
-	-- Save all buffers before running external tools to avoid conflicts
-	vim.cmd("wall")
-
-	-- Prepare arguments
+-- Build the command line shared by every entry point
+local function build_args(bin, opts)
 	local args = { bin }
 
 	-- Add includes if provided via setup or command
@@ -48,6 +37,32 @@ function M.clean(opts)
 		end
 	end
 
+	-- Add markers if provided; each needs its own flag
+	if opts.marker and #opts.marker > 0 then
+		for _, marker in ipairs(opts.marker) do
+			table.insert(args, "--marker")
+			table.insert(args, marker)
+		end
+	end
+
+	return args
+end
+
+function M.clean(opts)
+	opts = opts or {}
+	local bin = get_binary_path()
+
+	if vim.fn.executable(bin) == 0 then
+		vim.notify("Emoji Remover binary not found. Did you run 'cargo build --release'?", vim.log.levels.ERROR)
+		return
+	end
+
+	-- Save all buffers before running external tools to avoid conflicts
+	vim.cmd("wall")
+
+	-- Prepare arguments
+	local args = build_args(bin, opts)
+
 	vim.notify("Running Emoji Remover...", vim.log.levels.INFO)
 
 	-- Run the binary asynchronously
@@ -82,4 +97,104 @@ function M.clean(opts)
 	})
 end
 
+-- Load every marked comment into the quickfix list without modifying files
+function M.quickfix(opts)
+	opts = opts or {}
+	local bin = get_binary_path()
+
+	if vim.fn.executable(bin) == 0 then
+		vim.notify("Emoji Remover binary not found. Did you run 'cargo build --release'?", vim.log.levels.ERROR)
+		return
+	end
+
+	vim.cmd("wall")
+
+	local args = build_args(bin, opts)
+	table.insert(args, "--check")
+	table.insert(args, "--format")
+	table.insert(args, "quickfix")
+
+	local lines = {}
+	vim.fn.jobstart(args, {
+		stdout_buffered = true,
+		on_stdout = function(_, data)
+			if data then
+				for _, line in ipairs(data) do
+					if line ~= "" then
+						table.insert(lines, line)
+					end
+				end
+			end
+		end,
+		on_exit = function(_, code)
+			-- Exit code 2 means marked comments were found
+			if code ~= 0 and code ~= 2 then
+				vim.notify("Emoji Remover failed with exit code: " .. code, vim.log.levels.ERROR)
+				return
+			end
+			vim.fn.setqflist({}, " ", { title = "Marked comments", lines = lines, efm = "%f:%l:%c: %m" })
+			if #lines == 0 then
+				vim.notify("No marked comments found.", vim.log.levels.INFO)
+			else
+				vim.cmd("copen")
+			end
+		end,
+	})
+end
+
+-- Channel of the `--rpc` job that cleans buffers, started on first use
+local rpc_channel = nil
+
+local function get_rpc_channel(opts)
+	if rpc_channel then
+		return rpc_channel
+	end
+	local bin = get_binary_path()
+
+	if vim.fn.executable(bin) == 0 then
+		vim.notify("Emoji Remover binary not found. Did you run 'cargo build --release'?", vim.log.levels.ERROR)
+		return nil
+	end
+
+	local args = build_args(bin, opts)
+	table.insert(args, "--rpc")
+
+	local channel = vim.fn.jobstart(args, {
+		rpc = true,
+		on_exit = function()
+			rpc_channel = nil
+		end,
+	})
+	if channel <= 0 then
+		vim.notify("Could not start Emoji Remover.", vim.log.levels.ERROR)
+		return nil
+	end
+	rpc_channel = channel
+	return channel
+end
+
+-- Remove marked comments from the current buffer, saved or not, without temp files
+function M.clean_buffer(opts)
+	opts = opts or {}
+	local channel = get_rpc_channel(opts)
+	if not channel then
+		return
+	end
+
+	local bufnr = vim.api.nvim_get_current_buf()
+	local lines = vim.api.nvim_buf_get_lines(bufnr, 0, -1, false)
+	local ok, result = pcall(vim.rpcrequest, channel, "clean_buffer", lines, vim.bo[bufnr].filetype)
+	if not ok then
+		vim.notify("Emoji Remover failed: " .. tostring(result), vim.log.levels.ERROR)
+		return
+	end
+
+	-- Replace only the changed lines so marks and undo history stay precise
+	for _, removal in ipairs(result.removals) do
+		local first, last = removal.line, removal.end_line
+		vim.api.nvim_buf_set_lines(bufnr, first - 1, last, false, vim.list_slice(result.lines, first, last))
+	end
+	vim.notify("Removed " .. #result.removals .. " marked comments.", vim.log.levels.INFO)
+end
+
 return M
@@ -0,0 +1,33 @@
+// Node.js access to the emoji-remover cleaning engine.
+//
+// Loads the shared library built with `cargo build --release --lib --features napi`
+// as an addon, from $EMOJI_REMOVER_LIB or the crate's target directory, so files
+// are cleaned in-process:
+//
+//   const { clean } = require("./node");
+//   const { output, changes } = clean(text, "ts");
+
+"use strict";
+
+const fs = require("fs");
+const path = require("path");
+
+const names = {
+  win32: "emoji_remover.dll",
+  darwin: "libemoji_remover.dylib",
+};
+const name = names[process.platform] || "libemoji_remover.so";
+
+function locate() {
+  if (process.env.EMOJI_REMOVER_LIB) {
+    return process.env.EMOJI_REMOVER_LIB;
+  }
+  const target = path.join(__dirname, "..", "target");
+  const candidates = ["release", "debug"].map((profile) => path.join(target, profile, name));
+  return candidates.find((candidate) => fs.existsSync(candidate)) || name;
+}
+
+const addon = { exports: {} };
+process.dlopen(addon, locate());
+
+module.exports = addon.exports;
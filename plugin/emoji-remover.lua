@@ -11,3 +11,11 @@ vim.api.nvim_create_user_command("EmojiClean", function(opts)
 	-- For now, we just run the default configuration
 	emoji.clean({})
 end, {})
+
+vim.api.nvim_create_user_command("EmojiQuickfix", function()
+	emoji.quickfix({})
+end, {})
+
+vim.api.nvim_create_user_command("EmojiCleanBuffer", function()
+	emoji.clean_buffer({})
+end, {})
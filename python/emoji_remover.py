@@ -0,0 +1,97 @@
+"""Python access to the emoji-remover cleaning engine.
+
+Calls the C interface of the shared library built by ``cargo build --lib``,
+so Python tools use the same comment parser as the command. The library is
+loaded from ``$EMOJI_REMOVER_LIB`` if set, otherwise from the crate's
+``target/release`` or ``target/debug`` directory.
+"""
+
+import ctypes
+import os
+import sys
+from pathlib import Path
+
+_OK = 0
+_ERRORS = {
+    1: "a required argument was missing",
+    2: "text, language or a marker is not valid UTF-8",
+    3: "markers must not be empty",
+}
+
+
+def _library_name():
+    if sys.platform == "win32":
+        return "emoji_remover.dll"
+    if sys.platform == "darwin":
+        return "libemoji_remover.dylib"
+    return "libemoji_remover.so"
+
+
+def _load():
+    path = os.environ.get("EMOJI_REMOVER_LIB")
+    if path is None:
+        target = Path(__file__).resolve().parent.parent / "target"
+        candidates = [target / profile / _library_name() for profile in ("release", "debug")]
+        path = next((str(c) for c in candidates if c.exists()), _library_name())
+    lib = ctypes.CDLL(path)
+    lib.emoji_remover_clean.argtypes = [
+        ctypes.c_char_p,
+        ctypes.c_size_t,
+        ctypes.c_char_p,
+        ctypes.POINTER(ctypes.c_char_p),
+        ctypes.c_size_t,
+        ctypes.POINTER(ctypes.c_void_p),
+        ctypes.POINTER(ctypes.c_size_t),
+        ctypes.POINTER(ctypes.c_size_t),
+    ]
+    lib.emoji_remover_clean.restype = ctypes.c_int
+    lib.emoji_remover_free.argtypes = [ctypes.c_void_p, ctypes.c_size_t]
+    lib.emoji_remover_free.restype = None
+    return lib
+
+
+_lib = _load()
+
+
+def clean_text(text, language, markers=None):
+    """Removes marked comments from ``text``, written in ``language``: the
+    extension of its files, like ``"py"``. ``markers`` lists the markers to
+    look for, or uses the default one when ``None``. Returns the cleaned text
+    and the number of comments removed."""
+    source = text.encode("utf-8")
+    encoded = [marker.encode("utf-8") for marker in markers or []]
+    marker_array = (ctypes.c_char_p * len(encoded))(*encoded)
+    output = ctypes.c_void_p()
+    output_len = ctypes.c_size_t()
+    removed = ctypes.c_size_t()
+    status = _lib.emoji_remover_clean(
+        source,
+        len(source),
+        language.encode("utf-8"),
+        marker_array,
+        len(encoded),
+        ctypes.byref(output),
+        ctypes.byref(output_len),
+        ctypes.byref(removed),
+    )
+    if status != _OK:
+        raise ValueError(_ERRORS.get(status, f"error code {status}"))
+    try:
+        cleaned = ctypes.string_at(output, output_len.value).decode("utf-8")
+    finally:
+        _lib.emoji_remover_free(output, output_len.value)
+    return cleaned, removed.value
+
+
+def clean_path(path, markers=None, write=True):
+    """Cleans the UTF-8 file at ``path``, with the comment syntax of its
+    extension, rewriting it unless ``write`` is false or nothing was removed.
+    Returns the number of comments removed."""
+    path = Path(path)
+    with open(path, encoding="utf-8", newline="") as f:
+        text = f.read()
+    cleaned, removed = clean_text(text, path.suffix.lstrip("."), markers)
+    if write and removed:
+        with open(path, "w", encoding="utf-8", newline="") as f:
+            f.write(cleaned)
+    return removed
@@ -0,0 +1,77 @@
+//! Replaces files on disk without ever leaving them half written.
+
+use log::warn;
+use std::fs::{self, File, FileTimes, Metadata, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::process;
+
+/// Writes `contents` to a temporary file next to `path`, syncs it and renames
+/// it over `path`, so an interrupted run leaves either the old or the new
+/// file, never a truncated one. Symlinks are followed, and the file keeps its
+/// permissions and owner, and with `keep_mtime` its modification time.
+pub fn write_atomic(path: &Path, contents: &[u8], keep_mtime: bool) -> io::Result<()> {
+    write_atomic_with(path, keep_mtime, |out| out.write_all(contents))
+}
+
+/// Like `write_atomic`, with the new contents produced by `fill` so they
+/// need not be held in memory.
+pub fn write_atomic_with(
+    path: &Path,
+    keep_mtime: bool,
+    fill: impl FnOnce(&mut dyn Write) -> io::Result<()>,
+) -> io::Result<()> {
+    let target = if fs::symlink_metadata(path)?.file_type().is_symlink() {
+        fs::canonicalize(path)?
+    } else {
+        path.to_path_buf()
+    };
+    let metadata = fs::metadata(&target)?;
+    let dir = target.parent().unwrap_or(Path::new("."));
+    let mut temp_name = std::ffi::OsString::from(".");
+    temp_name.push(target.file_name().unwrap_or_default());
+    temp_name.push(format!(".emoji-remover-{}.tmp", process::id()));
+    let temp = dir.join(temp_name);
+
+    let result = (|| {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp)?;
+        let mut out = BufWriter::new(&mut file);
+        fill(&mut out)?;
+        out.flush()?;
+        drop(out);
+        file.set_permissions(metadata.permissions())?;
+        preserve_owner(&file, &metadata, &target);
+        if keep_mtime {
+            let times = FileTimes::new()
+                .set_accessed(metadata.accessed()?)
+                .set_modified(metadata.modified()?);
+            file.set_times(times)?;
+        }
+        file.sync_all()?;
+        fs::rename(&temp, &target)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+/// Hands the replacement file to the original's owner. Only root can give files
+/// away, so failing is expected otherwise and only warned about.
+#[cfg(unix)]
+fn preserve_owner(file: &File, original: &Metadata, path: &Path) {
+    use std::os::unix::fs::{MetadataExt, fchown};
+    let Ok(current) = file.metadata() else { return };
+    if (current.uid(), current.gid()) == (original.uid(), original.gid()) {
+        return;
+    }
+    if let Err(err) = fchown(file, Some(original.uid()), Some(original.gid())) {
+        warn!("Could not keep the owner of {}: {}", path.display(), err);
+    }
+}
+
+#[cfg(not(unix))]
+fn preserve_owner(_file: &File, _original: &Metadata, _path: &Path) {}
@@ -0,0 +1,318 @@
+//! Async access to a `Cleaner` for services that must not block their
+//! executor.
+//!
+//! File IO and cleaning run on a background thread, and results come back
+//! through futures that work with any executor: `Task` for a single file,
+//! and `Removals`, a stream of the removals made while cleaning a directory.
+//! A panic on the background thread, say in an `on_removal` callback, comes
+//! back as an error.
+
+#[cfg(feature = "walk")]
+use crate::Removal;
+use crate::{CleanResult, Cleaner};
+use std::any::Any;
+#[cfg(feature = "walk")]
+use std::collections::VecDeque;
+use std::future::Future;
+#[cfg(feature = "walk")]
+use std::future::poll_fn;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+/// The result of work running on a background thread.
+pub struct Task<T> {
+    shared: Arc<Mutex<TaskState<T>>>,
+}
+
+struct TaskState<T> {
+    result: Option<T>,
+    waker: Option<Waker>,
+}
+
+impl<T: Send + 'static> Task<io::Result<T>> {
+    fn spawn(work: impl FnOnce() -> io::Result<T> + Send + 'static) -> Task<io::Result<T>> {
+        let shared = Arc::new(Mutex::new(TaskState {
+            result: None,
+            waker: None,
+        }));
+        let state = Arc::clone(&shared);
+        thread::spawn(move || {
+            let result = catch_panic(work);
+            let mut state = lock(&state);
+            state.result = Some(result);
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        });
+        Task { shared }
+    }
+}
+
+impl<T> Future for Task<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<T> {
+        let mut state = lock(&self.shared);
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Removals made while a directory is cleaned, as they happen. An error ends
+/// the stream.
+#[cfg(feature = "walk")]
+pub struct Removals {
+    shared: Arc<Mutex<Queue>>,
+}
+
+#[cfg(feature = "walk")]
+#[derive(Default)]
+struct Queue {
+    items: VecDeque<io::Result<Removal>>,
+    done: bool,
+    waker: Option<Waker>,
+}
+
+#[cfg(feature = "walk")]
+impl Queue {
+    fn push(&mut self, item: io::Result<Removal>) {
+        self.items.push_back(item);
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+#[cfg(feature = "walk")]
+impl Removals {
+    /// The next removal, or `None` once the whole directory was cleaned.
+    pub async fn next(&mut self) -> Option<io::Result<Removal>> {
+        poll_fn(|cx| {
+            let mut queue = lock(&self.shared);
+            if let Some(item) = queue.items.pop_front() {
+                return Poll::Ready(Some(item));
+            }
+            if queue.done {
+                return Poll::Ready(None);
+            }
+            queue.waker = Some(cx.waker().clone());
+            Poll::Pending
+        })
+        .await
+    }
+}
+
+impl Cleaner {
+    /// Like `clean_file`, reading and writing the file on a background thread.
+    pub fn clean_file_async(
+        self: &Arc<Self>,
+        path: impl Into<PathBuf>,
+    ) -> Task<io::Result<CleanResult>> {
+        let cleaner = Arc::clone(self);
+        let path = path.into();
+        Task::spawn(move || cleaner.clean_file(&path))
+    }
+
+    /// Like `clean_dir`, cleaning on a background thread and streaming each
+    /// removal as its file is done.
+    #[cfg(feature = "walk")]
+    pub fn clean_dir_async(self: &Arc<Self>, dir: impl Into<PathBuf>) -> Removals {
+        let cleaner = Arc::clone(self);
+        let dir = dir.into();
+        let shared = Arc::new(Mutex::new(Queue::default()));
+        let queue = Arc::clone(&shared);
+        thread::spawn(move || {
+            let walked = catch_panic(|| {
+                cleaner.for_each_cleaned(&dir, |path, result| {
+                    let mut queue = lock(&queue);
+                    for change in &result.changes {
+                        queue.push(Ok(change.removal(Some(&path))));
+                    }
+                })
+            });
+            let mut queue = lock(&queue);
+            if let Err(err) = walked {
+                queue.push(Err(err));
+            }
+            queue.done = true;
+            if let Some(waker) = queue.waker.take() {
+                waker.wake();
+            }
+        });
+        Removals { shared }
+    }
+}
+
+/// Runs `work`, turning a panic into an error so whoever awaits the result
+/// is not left waiting.
+fn catch_panic<T>(work: impl FnOnce() -> io::Result<T>) -> io::Result<T> {
+    panic::catch_unwind(AssertUnwindSafe(work)).unwrap_or_else(|payload| {
+        Err(io::Error::other(format!(
+            "cleaning panicked: {}",
+            panic_message(payload.as_ref())
+        )))
+    })
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown cause"
+    }
+}
+
+/// Locks `mutex`, carrying on if another thread panicked while holding it.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Decision;
+    use std::fs;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::task::Wake;
+
+    struct Unpark(thread::Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    /// Polls `future` on this thread until it is ready.
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = std::pin::pin!(future);
+        let waker = Waker::from(Arc::new(Unpark(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+            thread::park();
+        }
+    }
+
+    /// A fresh directory holding `files`, removed when dropped.
+    struct Scratch(PathBuf);
+
+    impl Scratch {
+        fn new(files: &[(&str, &str)]) -> Scratch {
+            static NEXT: AtomicUsize = AtomicUsize::new(0);
+            let dir = std::env::temp_dir().join(format!(
+                "emoji-remover-background-{}-{}",
+                std::process::id(),
+                NEXT.fetch_add(1, Ordering::SeqCst)
+            ));
+            fs::create_dir_all(&dir).unwrap();
+            for (name, content) in files {
+                fs::write(dir.join(name), content).unwrap();
+            }
+            Scratch(dir)
+        }
+
+        fn path(&self, name: &str) -> PathBuf {
+            self.0.join(name)
+        }
+    }
+
+    impl Drop for Scratch {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn panicking() -> Arc<Cleaner> {
+        let cleaner = Cleaner::builder().on_removal(|_| -> Decision { panic!("no decision") });
+        Arc::new(cleaner.build().unwrap())
+    }
+
+    const MARKED: &str = "a(); // ‼️ gone\n";
+
+    #[test]
+    fn clean_file_async_cleans() {
+        let scratch = Scratch::new(&[("a.rs", MARKED)]);
+        let cleaner = Arc::new(Cleaner::builder().build().unwrap());
+        let result = block_on(cleaner.clean_file_async(scratch.path("a.rs"))).unwrap();
+        assert_eq!(result.changes.len(), 1);
+        assert_eq!(fs::read_to_string(scratch.path("a.rs")).unwrap(), "a();\n");
+    }
+
+    #[test]
+    fn clean_file_async_reports_errors() {
+        let scratch = Scratch::new(&[]);
+        let cleaner = Arc::new(Cleaner::builder().build().unwrap());
+        let err = block_on(cleaner.clean_file_async(scratch.path("missing.rs"))).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn clean_file_async_reports_panics() {
+        let scratch = Scratch::new(&[("a.rs", MARKED)]);
+        let err = block_on(panicking().clean_file_async(scratch.path("a.rs"))).unwrap_err();
+        assert_eq!(err.to_string(), "cleaning panicked: no decision");
+        assert_eq!(fs::read_to_string(scratch.path("a.rs")).unwrap(), MARKED);
+    }
+
+    #[cfg(feature = "walk")]
+    fn collect(mut removals: Removals) -> Vec<io::Result<Removal>> {
+        block_on(async {
+            let mut items = Vec::new();
+            while let Some(item) = removals.next().await {
+                items.push(item);
+            }
+            items
+        })
+    }
+
+    #[cfg(feature = "walk")]
+    #[test]
+    fn clean_dir_async_streams_removals() {
+        let scratch = Scratch::new(&[("a.rs", MARKED), ("b.rs", "b();\n")]);
+        let cleaner = Arc::new(Cleaner::builder().build().unwrap());
+        let items = collect(cleaner.clean_dir_async(&scratch.0));
+        assert_eq!(items.len(), 1);
+        let removal = items[0].as_ref().unwrap();
+        assert_eq!(removal.removed_text, "// ‼️ gone");
+        assert_eq!(
+            removal.file.as_deref(),
+            Some(scratch.path("a.rs").as_path())
+        );
+    }
+
+    #[cfg(feature = "walk")]
+    #[test]
+    fn clean_dir_async_ends_with_errors() {
+        let scratch = Scratch::new(&[]);
+        let cleaner = Arc::new(Cleaner::builder().build().unwrap());
+        let items = collect(cleaner.clean_dir_async(scratch.path("missing")));
+        assert_eq!(items.len(), 1);
+        assert_eq!(
+            items[0].as_ref().unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+
+        let scratch = Scratch::new(&[("a.rs", MARKED)]);
+        let items = collect(panicking().clean_dir_async(&scratch.0));
+        assert_eq!(items.len(), 1);
+        let err = items[0].as_ref().unwrap_err();
+        assert_eq!(err.to_string(), "cleaning panicked: no decision");
+    }
+}
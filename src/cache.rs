@@ -0,0 +1,114 @@
+//! Remembers which files held no marked comments, so later runs can skip them
+//! without reading them for as long as they stay unchanged.
+//!
+//! The cache lives at `<git dir>/emoji-remover/cache`. After a header naming
+//! the version that wrote it, it holds one tab-separated record per clean
+//! file: path, size, modification time in nanoseconds since the epoch and a
+//! fingerprint of the settings the file was checked with.
+
+use crate::AppError;
+use std::collections::HashMap;
+use std::fs::{self, Metadata};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+const HEADER: &str = concat!("emoji-remover cache ", env!("CARGO_PKG_VERSION"));
+
+pub fn location(git_dir: &Path) -> PathBuf {
+    git_dir.join("emoji-remover").join("cache")
+}
+
+/// What a file looked like when it was checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stamp {
+    size: u64,
+    modified: u128,
+    fingerprint: u64,
+}
+
+impl Stamp {
+    /// Returns `None` when the platform reports no modification time.
+    pub fn new(metadata: &Metadata, fingerprint: u64) -> Option<Stamp> {
+        let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(Stamp {
+            size: metadata.len(),
+            modified: modified.as_nanos(),
+            fingerprint,
+        })
+    }
+}
+
+pub struct Cache {
+    path: PathBuf,
+    clean: HashMap<String, Stamp>,
+    changed: bool,
+}
+
+impl Cache {
+    /// Loads the cache of `git_dir`. A missing, unreadable or outdated cache
+    /// starts out empty.
+    pub fn load(git_dir: &Path) -> Cache {
+        let path = location(git_dir);
+        let text = fs::read_to_string(&path).unwrap_or_default();
+        let mut lines = text.lines();
+        let clean = if lines.next() == Some(HEADER) {
+            lines.filter_map(parse_record).collect()
+        } else {
+            HashMap::new()
+        };
+        Cache {
+            path,
+            clean,
+            changed: false,
+        }
+    }
+
+    /// Whether `label` was found clean when it last looked like `stamp`.
+    pub fn is_clean(&self, label: &str, stamp: Stamp) -> bool {
+        self.clean.get(label) == Some(&stamp)
+    }
+
+    pub fn mark_clean(&mut self, label: &str, stamp: Stamp) {
+        // Such paths cannot be stored in a record; they are simply rescanned
+        if label.contains(['\t', '\n', '\r']) {
+            return;
+        }
+        if self.clean.insert(label.to_string(), stamp) != Some(stamp) {
+            self.changed = true;
+        }
+    }
+
+    /// Writes the cache back if anything was added to it.
+    pub fn save(&self) -> Result<(), AppError> {
+        if !self.changed {
+            return Ok(());
+        }
+        let mut text = format!("{}\n", HEADER);
+        for (label, stamp) in &self.clean {
+            text.push_str(&format!(
+                "{}\t{}\t{}\t{}\n",
+                label, stamp.size, stamp.modified, stamp.fingerprint
+            ));
+        }
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).map_err(|e| AppError::FileWrite(dir.to_path_buf(), e))?;
+        }
+        fs::write(&self.path, text).map_err(|e| AppError::FileWrite(self.path.clone(), e))
+    }
+}
+
+fn parse_record(record: &str) -> Option<(String, Stamp)> {
+    let mut fields = record.split('\t');
+    let (label, size, modified, fingerprint) = (
+        fields.next()?,
+        fields.next()?,
+        fields.next()?,
+        fields.next()?,
+    );
+    let stamp = Stamp {
+        size: size.parse().ok()?,
+        modified: modified.parse().ok()?,
+        fingerprint: fingerprint.parse().ok()?,
+    };
+    Some((label.to_string(), stamp))
+}
@@ -0,0 +1,818 @@
+//! Finding marked comments in source text and removing them.
+
+use crate::markers::Markers;
+use crate::syntax::{Carry, Syntax};
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Write};
+use std::ops::{Range, RangeInclusive};
+use std::path::{Path, PathBuf};
+
+/// What to remove from source passed to `clean_source`.
+pub struct CleanOptions {
+    pub markers: Markers,
+}
+
+impl Default for CleanOptions {
+    /// Removes comments marked with the default marker.
+    fn default() -> CleanOptions {
+        CleanOptions {
+            markers: Markers::default(),
+        }
+    }
+}
+
+/// Source with its marked comments removed.
+#[derive(Debug, Clone)]
+pub struct CleanResult {
+    pub output: String,
+    /// The comments removed, in order.
+    pub changes: Vec<LineChange>,
+}
+
+/// Removes the marked comments from `content`, written in `language`: the
+/// extension of its files, like `"rs"` or `"py"`, which decides the comment
+/// syntax. Line endings and a leading byte order mark are kept.
+pub fn clean_source(content: &str, language: &str, options: &CleanOptions) -> CleanResult {
+    let changes = find_changes(split_bom(content).1, language, &options.markers);
+    CleanResult::new(content, changes)
+}
+
+/// Cleans the lines read from `reader` as they come, writing them to
+/// `writer`, so input of any size can be cleaned from pipes, sockets or
+/// buffers. Returns the changes made. Input that is not UTF-8 fails with
+/// `io::ErrorKind::InvalidData`, after the lines before it were written.
+pub fn clean_reader(
+    reader: impl BufRead,
+    writer: impl Write,
+    language: &str,
+    options: &CleanOptions,
+) -> io::Result<Vec<LineChange>> {
+    clean_lines(reader, writer, language, &options.markers, |_| true)
+}
+
+/// Streams `reader` to `writer` with the marked comments removed, except for
+/// the changes `keep` rejects; it may also modify them.
+pub(crate) fn clean_lines(
+    mut reader: impl BufRead,
+    mut writer: impl Write,
+    language: &str,
+    markers: &Markers,
+    mut keep: impl FnMut(&mut LineChange) -> bool,
+) -> io::Result<Vec<LineChange>> {
+    let mut scanner = CommentScanner::new(language, markers);
+    let mut changes = Vec::new();
+    // Lines not written yet, with the length of their text before the ending
+    let mut unwritten = VecDeque::new();
+    let mut written_changes = 0;
+    let mut buf = Vec::new();
+    let mut line_number = 0;
+    while reader.read_until(b'\n', &mut buf)? > 0 {
+        line_number += 1;
+        let (mut text, ending) = split_ending(&buf);
+        if line_number == 1
+            && let Some(rest) = text.strip_prefix(UTF8_BOM)
+        {
+            writer.write_all(UTF8_BOM)?;
+            text = rest;
+        }
+        let line = match std::str::from_utf8(text) {
+            Ok(line) => line,
+            Err(err) => {
+                write_lines(
+                    &mut writer,
+                    &mut unwritten,
+                    &changes,
+                    &mut written_changes,
+                    None,
+                )?;
+                return Err(io::Error::new(io::ErrorKind::InvalidData, err));
+            }
+        };
+        let settled = scanner.next_line(line_number, line);
+        changes.extend(
+            settled
+                .into_iter()
+                .filter_map(|mut change| keep(&mut change).then_some(change)),
+        );
+        unwritten.push_back((line_number, [text, ending].concat(), text.len()));
+        write_lines(
+            &mut writer,
+            &mut unwritten,
+            &changes,
+            &mut written_changes,
+            scanner.held_since(),
+        )?;
+        buf.clear();
+    }
+    let settled = scanner.finish();
+    changes.extend(
+        settled
+            .into_iter()
+            .filter_map(|mut change| keep(&mut change).then_some(change)),
+    );
+    write_lines(
+        &mut writer,
+        &mut unwritten,
+        &changes,
+        &mut written_changes,
+        None,
+    )?;
+    writer.flush()?;
+    Ok(changes)
+}
+
+/// Writes the lines in `unwritten` before line `until`, or all of them, with
+/// `changes` from `written_changes` on applied.
+fn write_lines(
+    mut writer: impl Write,
+    unwritten: &mut VecDeque<(usize, Vec<u8>, usize)>,
+    changes: &[LineChange],
+    written_changes: &mut usize,
+    until: Option<usize>,
+) -> io::Result<()> {
+    while let Some((line_number, line, text_len)) = unwritten.pop_front() {
+        if until.is_some_and(|until| line_number >= until) {
+            unwritten.push_front((line_number, line, text_len));
+            break;
+        }
+        let pending = &changes[*written_changes..];
+        match line_edits(pending).first() {
+            Some(edit) if edit.line_number <= line_number => {
+                let cleaned = edit
+                    .cleaned
+                    .split('\n')
+                    .nth(line_number - edit.line_number)
+                    .unwrap_or_default();
+                writer.write_all(cleaned.as_bytes())?;
+                if line_number == edit.end_line() {
+                    *written_changes += pending
+                        .iter()
+                        .take_while(|change| change.line_number <= line_number)
+                        .count();
+                }
+            }
+            _ => writer.write_all(&line[..text_len])?,
+        }
+        writer.write_all(&line[text_len..])?;
+    }
+    Ok(())
+}
+
+impl CleanResult {
+    /// Applies `changes`, found in `content` after its byte order mark.
+    pub(crate) fn new(content: &str, changes: Vec<LineChange>) -> CleanResult {
+        let (bom, text) = split_bom(content);
+        let output = if changes.is_empty() {
+            content.to_string()
+        } else {
+            format!("{}{}", bom, apply_changes(text, &changes))
+        };
+        CleanResult { output, changes }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommentKind {
+    Line,
+    Block,
+    /// A block comment that is never closed, running to the end of the source.
+    UnclosedBlock,
+}
+
+impl CommentKind {
+    pub fn reason(self) -> &'static str {
+        match self {
+            CommentKind::Line => "line comment contains marker",
+            CommentKind::Block => "block comment contains marker",
+            CommentKind::UnclosedBlock => "unterminated block comment contains marker",
+        }
+    }
+
+    pub fn rule_id(self) -> &'static str {
+        match self {
+            CommentKind::Line => "marked-line-comment",
+            CommentKind::Block => "marked-block-comment",
+            CommentKind::UnclosedBlock => "marked-unclosed-block-comment",
+        }
+    }
+
+    /// Short name used in machine-readable output.
+    pub fn name(self) -> &'static str {
+        match self {
+            CommentKind::Line => "line",
+            CommentKind::Block => "block",
+            CommentKind::UnclosedBlock => "unclosed-block",
+        }
+    }
+}
+
+/// A marked comment that is removed, located precisely. Reports, the
+/// library's callbacks and the editor integrations all describe removals
+/// with this.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Removal {
+    /// The file holding the comment, or `None` for source passed in directly.
+    pub file: Option<PathBuf>,
+    /// 1-based line number.
+    pub line: usize,
+    /// The line the comment ends on, `line` unless it spans lines.
+    pub end_line: usize,
+    /// Byte offsets of the comment: where it starts within `line` and where
+    /// it ends within `end_line`.
+    pub byte_range: Range<usize>,
+    /// 1-based columns of the comment counted in characters, end exclusive,
+    /// within `line` and `end_line` like `byte_range`.
+    pub col_range: Range<usize>,
+    pub removed_text: String,
+    pub comment_kind: CommentKind,
+}
+
+/// A marked comment, and what the lines holding it become once it is
+/// removed. A block comment spanning lines is a single change, whose
+/// `original`, `cleaned` and `removed` join its lines with `\n`; `cleaned`
+/// keeps as many lines as `original`, those emptied included.
+#[derive(Debug, Clone)]
+pub struct LineChange {
+    /// The line the comment starts on.
+    pub line_number: usize,
+    /// 1-based byte column where the removed comment starts.
+    pub column: usize,
+    pub original: String,
+    pub cleaned: String,
+    pub removed: String,
+    pub kind: CommentKind,
+}
+
+impl LineChange {
+    /// The removal of the comment at `span` in `line`, numbered
+    /// `line_number`. A comment spanning lines has them all in `line`,
+    /// joined by `\n`.
+    pub fn new(line_number: usize, line: &str, span: Range<usize>, kind: CommentKind) -> Self {
+        let prefix = &line[..span.start];
+        let suffix = &line[span.end..];
+        let breaks = "\n".repeat(line[span.clone()].matches('\n').count());
+        // If the comment ends its line, trim. Otherwise splice it out.
+        let cleaned = if suffix.trim().is_empty() {
+            format!("{}{}", prefix.trim_end(), breaks)
+        } else if breaks.is_empty() {
+            format!("{}{}", prefix, suffix)
+        } else {
+            format!("{}{}{}", prefix.trim_end(), breaks, suffix)
+        };
+        LineChange {
+            line_number,
+            column: span.start + 1,
+            original: line.to_string(),
+            cleaned,
+            removed: line[span].to_string(),
+            kind,
+        }
+    }
+
+    /// Byte span of the removed comment within `original`.
+    pub fn span(&self) -> Range<usize> {
+        self.column - 1..self.column - 1 + self.removed.len()
+    }
+
+    /// The line the comment ends on.
+    pub fn end_line(&self) -> usize {
+        self.line_number + self.original.matches('\n').count()
+    }
+
+    /// The numbers of the lines the comment is on.
+    pub fn line_numbers(&self) -> RangeInclusive<usize> {
+        self.line_number..=self.end_line()
+    }
+
+    /// Each line the comment is on, numbered, as it was and as it becomes.
+    pub fn lines(&self) -> impl Iterator<Item = (usize, &str, &str)> {
+        self.original
+            .split('\n')
+            .zip(self.cleaned.split('\n'))
+            .enumerate()
+            .map(|(idx, (original, cleaned))| (self.line_number + idx, original, cleaned))
+    }
+
+    /// The removal this change makes, in `file`.
+    pub fn removal(&self, file: Option<&Path>) -> Removal {
+        let byte_range = self.span();
+        let start = self.original[..byte_range.start].chars().count() + 1;
+        // On a comment's last line, the columns count from the line's start
+        let (end_byte, end_col) = match self.removed.rfind('\n') {
+            Some(idx) => {
+                let last = &self.removed[idx + 1..];
+                (last.len(), last.chars().count() + 1)
+            }
+            None => (byte_range.end, start + self.removed.chars().count()),
+        };
+        Removal {
+            file: file.map(Path::to_path_buf),
+            line: self.line_number,
+            end_line: self.end_line(),
+            col_range: start..end_col,
+            byte_range: byte_range.start..end_byte,
+            removed_text: self.removed.clone(),
+            comment_kind: self.kind,
+        }
+    }
+
+    /// The bytes of `original` this change replaces, the comment and the
+    /// blanks trimmed with it, and what replaces them in `cleaned`.
+    fn edit(&self) -> (Range<usize>, &str) {
+        let span = self.span();
+        let start: usize = self.original[..span.start]
+            .chars()
+            .zip(self.cleaned.chars())
+            .take_while(|(a, b)| a == b)
+            .map(|(a, _)| a.len_utf8())
+            .sum();
+        let kept: usize = self.original[span.end..]
+            .chars()
+            .rev()
+            .zip(self.cleaned[start..].chars().rev())
+            .take_while(|(a, b)| a == b)
+            .map(|(a, _)| a.len_utf8())
+            .sum();
+        (
+            start..self.original.len() - kept,
+            &self.cleaned[start..self.cleaned.len() - kept],
+        )
+    }
+
+    /// Puts `replacement` where the comment was instead of removing it. The
+    /// lines of a comment spanning lines are kept, emptied.
+    pub fn replace_comment(&mut self, replacement: &str) {
+        let span = self.span();
+        self.cleaned = format!(
+            "{}{}{}{}",
+            &self.original[..span.start],
+            replacement,
+            "\n".repeat(self.removed.matches('\n').count()),
+            &self.original[span.end..]
+        );
+    }
+}
+
+/// The lines of one or more changes that share lines, as they were and as
+/// they become once all of them are applied, joined by `\n` like a
+/// change's. Several marked comments on one line change it once.
+#[derive(Debug, Clone)]
+pub struct LineEdit<'a> {
+    pub line_number: usize,
+    pub original: Cow<'a, str>,
+    pub cleaned: Cow<'a, str>,
+}
+
+impl LineEdit<'_> {
+    /// Each line edited, numbered, as it was and as it becomes.
+    pub fn lines(&self) -> impl Iterator<Item = (usize, &str, &str)> {
+        self.original
+            .split('\n')
+            .zip(self.cleaned.split('\n'))
+            .enumerate()
+            .map(|(idx, (original, cleaned))| (self.line_number + idx, original, cleaned))
+    }
+
+    /// The last line edited.
+    pub fn end_line(&self) -> usize {
+        self.line_number + self.original.matches('\n').count()
+    }
+}
+
+impl<'a> From<&'a LineChange> for LineEdit<'a> {
+    fn from(change: &'a LineChange) -> LineEdit<'a> {
+        LineEdit {
+            line_number: change.line_number,
+            original: Cow::Borrowed(&change.original),
+            cleaned: Cow::Borrowed(&change.cleaned),
+        }
+    }
+}
+
+/// The edits that apply `changes`, one for each run of changes sharing
+/// lines. `changes` must be ordered by line, and found in one source.
+pub fn line_edits(changes: &[LineChange]) -> Vec<LineEdit<'_>> {
+    let mut edits = Vec::with_capacity(changes.len());
+    let mut idx = 0;
+    while idx < changes.len() {
+        let mut end = idx + 1;
+        let mut last = changes[idx].end_line();
+        while end < changes.len() && changes[end].line_number <= last {
+            last = last.max(changes[end].end_line());
+            end += 1;
+        }
+        edits.push(match &changes[idx..end] {
+            [change] => LineEdit::from(change),
+            run => merge(run, last),
+        });
+        idx = end;
+    }
+    edits
+}
+
+/// The one edit making all of `changes`, which share lines up to `last`.
+fn merge(changes: &[LineChange], last: usize) -> LineEdit<'static> {
+    let first = changes[0].line_number;
+    let mut lines = vec![""; last - first + 1];
+    for (number, original, _) in changes.iter().flat_map(LineChange::lines) {
+        lines[number - first] = original;
+    }
+    let original = lines.join("\n");
+    let mut line_start = 0;
+    let starts: Vec<usize> = lines
+        .iter()
+        .map(|line| {
+            let start = line_start;
+            line_start += line.len() + 1;
+            start
+        })
+        .collect();
+    let mut edits: Vec<(Range<usize>, &str)> = changes
+        .iter()
+        .map(|change| {
+            let (range, replacement) = change.edit();
+            let offset = starts[change.line_number - first];
+            (offset + range.start..offset + range.end, replacement)
+        })
+        .collect();
+    // Right to left, so the offsets of the edits left to make hold
+    edits.sort_by_key(|(range, _)| std::cmp::Reverse(range.start));
+    let mut cleaned = original.clone();
+    for (range, replacement) in edits {
+        cleaned.replace_range(range.clone(), replacement);
+        // A comment that ends its line once those after it are removed
+        let line_end = cleaned[range.start..]
+            .find('\n')
+            .map_or(cleaned.len(), |offset| range.start + offset);
+        if replacement.is_empty() && cleaned[range.start..line_end].trim().is_empty() {
+            let line_start = cleaned[..range.start].rfind('\n').map_or(0, |idx| idx + 1);
+            let kept = line_start + cleaned[line_start..range.start].trim_end().len();
+            cleaned.replace_range(kept..line_end, "");
+        }
+    }
+    LineEdit {
+        line_number: first,
+        original: Cow::Owned(original),
+        cleaned: Cow::Owned(cleaned),
+    }
+}
+
+/// Locates a marked comment on `line`, returning its byte span and kind.
+/// The line is taken on its own; use a `CommentScanner` for consecutive
+/// lines, so literals and comments that span lines are recognized.
+pub fn find_marked_comment(
+    line: &str,
+    ext: &str,
+    markers: &Markers,
+) -> Option<(Range<usize>, CommentKind)> {
+    let mut scanner = CommentScanner::new(ext, markers);
+    let mut changes = scanner.next_line(1, line);
+    changes.extend(scanner.finish());
+    changes.first().map(|change| (change.span(), change.kind))
+}
+
+/// Finds the marked comments in the lines of a source one line at a time,
+/// keeping track of what spans lines, such as JavaScript template literals
+/// and block comments.
+pub struct CommentScanner<'a> {
+    syntax: Syntax,
+    markers: &'a Markers,
+    carry: Carry,
+    /// The lines of the open block comment, with its span on each, held
+    /// until it closes and whether it is marked is known. `None` when no
+    /// block comment is open.
+    block: Option<Vec<(usize, String, Range<usize>)>>,
+    /// The changes on the first line of the open block comment, before it,
+    /// held back with the block so changes come out in order.
+    held: Vec<LineChange>,
+}
+
+impl<'a> CommentScanner<'a> {
+    /// A scanner for source written in `ext`, at its first line.
+    pub fn new(ext: &str, markers: &'a Markers) -> CommentScanner<'a> {
+        let syntax = Syntax::for_language(ext);
+        CommentScanner {
+            syntax: if markers.doc_comments() {
+                syntax
+            } else {
+                syntax.without_docstrings()
+            },
+            markers,
+            carry: Carry::default(),
+            block: None,
+            held: Vec::new(),
+        }
+    }
+
+    /// Takes `line`, numbered `line_number`, which follows the lines given
+    /// before, returning the changes it settles, in order: those on the line
+    /// itself, one for each marked comment, and those on the lines of a
+    /// block comment that closes on it. A line on which a block comment
+    /// opens and stays open is held back until the comment closes; see
+    /// `held_since`.
+    pub fn next_line(&mut self, line_number: usize, line: &str) -> Vec<LineChange> {
+        // The `#!` line of a script is kept whatever it holds
+        if line_number == 1 && line.starts_with("#!") {
+            return Vec::new();
+        }
+        let continued = self.carry.in_comment();
+        let mut comments = self
+            .syntax
+            .scan(line, &mut self.carry)
+            .into_iter()
+            .peekable();
+        let continues = self.carry.in_comment();
+        let mut changes = Vec::new();
+        if continued && let Some((span, _)) = comments.next() {
+            let runs_on = continues && comments.peek().is_none();
+            if let Some(block) = &mut self.block {
+                block.push((line_number, line.to_string(), span));
+            }
+            if runs_on {
+                return changes;
+            }
+            changes.extend(self.close_block(CommentKind::Block));
+        }
+        while let Some((span, kind)) = comments.next() {
+            // The last comment runs on to the next line
+            if continues && comments.peek().is_none() && span.end == line.len() {
+                self.block = Some(vec![(line_number, line.to_string(), span)]);
+                let on_line = changes
+                    .iter()
+                    .position(|c: &LineChange| c.line_number == line_number);
+                self.held = changes.split_off(on_line.unwrap_or(changes.len()));
+                break;
+            }
+            if self.removable(&line[span.clone()]) {
+                changes.push(LineChange::new(line_number, line, span, kind));
+            }
+        }
+        changes
+    }
+
+    /// The changes on the lines still held back, once the source has ended.
+    /// A block comment still open at the end runs to it.
+    pub fn finish(&mut self) -> Vec<LineChange> {
+        self.carry = Carry::default();
+        self.close_block(CommentKind::UnclosedBlock)
+    }
+
+    /// The number of the first line held back until the block comment open
+    /// on it closes, if any.
+    pub fn held_since(&self) -> Option<usize> {
+        self.block
+            .as_ref()
+            .and_then(|block| block.first())
+            .map(|(line_number, _, _)| *line_number)
+    }
+
+    /// Whether `comment` is marked, and not a doc comment or directive left
+    /// alone.
+    fn removable(&self, comment: &str) -> bool {
+        self.markers.matches(comment)
+            && (self.markers.doc_comments() || !self.syntax.is_doc_comment(comment))
+            && (self.markers.directives() || !self.syntax.is_directive(comment))
+    }
+
+    /// The changes held back with the open block comment, and its own if it
+    /// is marked.
+    fn close_block(&mut self, kind: CommentKind) -> Vec<LineChange> {
+        let mut changes = std::mem::take(&mut self.held);
+        let Some(block) = self.block.take() else {
+            return changes;
+        };
+        let text: Vec<&str> = block
+            .iter()
+            .map(|(_, line, span)| &line[span.clone()])
+            .collect();
+        if !self.removable(&text.join("\n")) {
+            return changes;
+        }
+        let lines: Vec<&str> = block.iter().map(|(_, line, _)| line.as_str()).collect();
+        let joined = lines.join("\n");
+        let (first_line, _, first_span) = &block[0];
+        let (_, last_line, last_span) = &block[block.len() - 1];
+        let span = first_span.start..joined.len() - last_line.len() + last_span.end;
+        changes.push(LineChange::new(*first_line, &joined, span, kind));
+        changes
+    }
+}
+
+/// Finds the marked comments in `content`, one change per comment, a block
+/// comment spanning lines included.
+pub fn find_changes(content: &str, ext: &str, markers: &Markers) -> Vec<LineChange> {
+    let mut scanner = CommentScanner::new(ext, markers);
+    let mut changes: Vec<LineChange> = content
+        .lines()
+        .enumerate()
+        .flat_map(|(idx, line)| scanner.next_line(idx + 1, line))
+        .collect();
+    changes.extend(scanner.finish());
+    changes
+}
+
+pub const UTF8_BOM: &[u8] = "\u{feff}".as_bytes();
+
+/// Separates a leading UTF-8 byte order mark from `text`, so it neither hides a
+/// comment on the first line nor gets lost when the file is rewritten.
+pub fn split_bom(text: &str) -> (&str, &str) {
+    match text.strip_prefix('\u{feff}') {
+        Some(rest) => (&text[..text.len() - rest.len()], rest),
+        None => ("", text),
+    }
+}
+
+/// Splits `content` into the same lines as `str::lines`, each paired with the
+/// line ending that followed it (`"\r\n"`, `"\n"` or `""` for a final line
+/// without one), so rewritten files keep their line endings exactly.
+pub fn split_lines(content: &str) -> impl Iterator<Item = (&str, &str)> {
+    content.split_inclusive('\n').map(|line| {
+        let text = line
+            .strip_suffix("\r\n")
+            .or_else(|| line.strip_suffix('\n'))
+            .unwrap_or(line);
+        (text, &line[text.len()..])
+    })
+}
+
+/// Rebuilds `content` with the given changes applied. `changes` must be ordered by line.
+pub fn apply_changes(content: &str, changes: &[LineChange]) -> String {
+    cleaned_pieces(content, changes).concat()
+}
+
+/// The pieces `content` consists of once `changes` are applied: the spans
+/// between changed lines, borrowed as they are, and the cleaned lines. Lines
+/// after the last change are not even split. `changes` must be ordered by line.
+pub fn cleaned_pieces<'a>(content: &'a str, changes: &'a [LineChange]) -> Vec<Cow<'a, str>> {
+    let mut pieces = Vec::with_capacity(changes.len() * 2 + 1);
+    let edits = line_edits(changes);
+    let mut pending = edits
+        .iter()
+        .flat_map(|edit| {
+            let cleaned: Vec<Cow<'a, str>> = match &edit.cleaned {
+                Cow::Borrowed(cleaned) => cleaned.split('\n').map(Cow::Borrowed).collect(),
+                Cow::Owned(cleaned) => cleaned
+                    .split('\n')
+                    .map(|line| Cow::Owned(line.to_string()))
+                    .collect(),
+            };
+            (edit.line_number..).zip(cleaned)
+        })
+        .peekable();
+    let (mut copied, mut offset) = (0, 0);
+    for (idx, (line, ending)) in split_lines(content).enumerate() {
+        if pending.peek().is_none() {
+            break;
+        }
+        if let Some((_, cleaned)) = pending.next_if(|(number, _)| *number == idx + 1) {
+            pieces.push(Cow::Borrowed(&content[copied..offset]));
+            pieces.push(cleaned);
+            copied = offset + line.len();
+        }
+        offset += line.len() + ending.len();
+    }
+    pieces.push(Cow::Borrowed(&content[copied..]));
+    pieces
+}
+
+/// Splits a line read from disk into its text and its line ending, like
+/// `split_lines` does for text in memory.
+pub fn split_ending(line: &[u8]) -> (&[u8], &[u8]) {
+    let text_len = line
+        .strip_suffix(b"\r\n")
+        .or_else(|| line.strip_suffix(b"\n"))
+        .unwrap_or(line)
+        .len();
+    line.split_at(text_len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLOCK: &str = "let a = 1; /* ‼️ start\nmiddle\nend */ let b = 2;\nlet c = 3;\n";
+
+    #[test]
+    fn block_spanning_lines_is_one_change() {
+        let changes = find_changes(BLOCK, "rs", &Markers::default());
+        assert_eq!(changes.len(), 1);
+        let change = &changes[0];
+        assert_eq!((change.line_number, change.end_line()), (1, 3));
+        assert_eq!(change.removed, "/* ‼️ start\nmiddle\nend */");
+        assert_eq!(change.cleaned, "let a = 1;\n\n let b = 2;");
+        assert_eq!(
+            apply_changes(BLOCK, &changes),
+            "let a = 1;\n\n let b = 2;\nlet c = 3;\n"
+        );
+    }
+
+    #[test]
+    fn block_removal_ends_on_last_line() {
+        let changes = find_changes(BLOCK, "rs", &Markers::default());
+        let removal = changes[0].removal(None);
+        assert_eq!((removal.line, removal.end_line), (1, 3));
+        let byte_range = (removal.byte_range.start, removal.byte_range.end);
+        let col_range = (removal.col_range.start, removal.col_range.end);
+        assert_eq!(byte_range, (11, 6));
+        assert_eq!(col_range, (12, 7));
+    }
+
+    #[test]
+    fn block_keeps_line_endings() {
+        let content = BLOCK.replace('\n', "\r\n");
+        let changes = find_changes(&content, "rs", &Markers::default());
+        assert_eq!(
+            apply_changes(&content, &changes),
+            "let a = 1;\r\n\r\n let b = 2;\r\nlet c = 3;\r\n"
+        );
+    }
+
+    #[test]
+    fn replaced_block_keeps_its_lines() {
+        let mut changes = find_changes(BLOCK, "rs", &Markers::default());
+        changes[0].replace_comment("/* done */");
+        assert_eq!(changes[0].cleaned, "let a = 1; /* done */\n\n let b = 2;");
+    }
+
+    #[test]
+    fn reader_writes_held_block_once_closed() {
+        let mut out = Vec::new();
+        let changes =
+            clean_reader(BLOCK.as_bytes(), &mut out, "rs", &CleanOptions::default()).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "let a = 1;\n\n let b = 2;\nlet c = 3;\n"
+        );
+    }
+
+    #[test]
+    fn unclosed_block_runs_to_the_end() {
+        let changes = find_changes("x\n/* ‼️\ny", "rs", &Markers::default());
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].kind, CommentKind::UnclosedBlock);
+        assert_eq!((changes[0].line_number, changes[0].end_line()), (2, 3));
+    }
+
+    #[test]
+    fn unmarked_block_is_kept() {
+        let content = "/* start\nmiddle\nend */\n";
+        assert!(find_changes(content, "rs", &Markers::default()).is_empty());
+    }
+
+    #[test]
+    fn marked_comment_after_unmarked_block() {
+        for ext in ["rs", "c"] {
+            let content = "let a = 1; /* x */ let b = 2; // ‼️ gone\n";
+            let changes = find_changes(content, ext, &Markers::default());
+            assert_eq!(changes.len(), 1);
+            assert_eq!(changes[0].removed, "// ‼️ gone");
+            assert_eq!(
+                apply_changes(content, &changes),
+                "let a = 1; /* x */ let b = 2;\n"
+            );
+        }
+    }
+
+    #[test]
+    fn every_marked_comment_on_a_line_is_removed() {
+        let content = "a(); /* ‼️ one */ b(); // ‼️ two\nc();\n";
+        let changes = find_changes(content, "rs", &Markers::default());
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].removed, "/* ‼️ one */");
+        assert_eq!(changes[1].removed, "// ‼️ two");
+        assert_eq!(apply_changes(content, &changes), "a();  b();\nc();\n");
+
+        let edits = line_edits(&changes);
+        assert_eq!(edits.len(), 1);
+        assert_eq!(edits[0].line_number, 1);
+        assert_eq!(edits[0].cleaned, "a();  b();");
+    }
+
+    #[test]
+    fn block_opening_after_a_comment() {
+        let content = "a(); /* ‼️ one */ b(); /* ‼️ two\nend */ c();\n";
+        let changes = find_changes(content, "rs", &Markers::default());
+        assert_eq!(changes.len(), 2);
+        assert_eq!((changes[1].line_number, changes[1].end_line()), (1, 2));
+        assert_eq!(apply_changes(content, &changes), "a();  b();\n c();\n");
+
+        let mut out = Vec::new();
+        clean_reader(content.as_bytes(), &mut out, "rs", &CleanOptions::default()).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "a();  b();\n c();\n");
+    }
+
+    #[test]
+    fn reader_applies_every_comment_on_a_line() {
+        let content = "x(); /* a */ y(); // ‼️ b\n/* ‼️ c */ z(); // ‼️ d\n";
+        let mut out = Vec::new();
+        let changes =
+            clean_reader(content.as_bytes(), &mut out, "rs", &CleanOptions::default()).unwrap();
+        assert_eq!(changes.len(), 3);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "x(); /* a */ y();\n z();\n"
+        );
+    }
+}
@@ -0,0 +1,361 @@
+//! Configuring a cleaner in code, for programs that embed the library
+//! instead of running the command.
+
+use crate::clean::clean_lines;
+use crate::markers::Markers;
+use crate::regex::RegexError;
+use crate::{
+    CleanOptions, CleanResult, LineChange, Removal, find_changes, language_for_file, split_bom,
+    write_atomic,
+};
+use glob::Pattern;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+#[cfg(feature = "walk")]
+use std::path::PathBuf;
+use std::sync::Arc;
+use thiserror::Error;
+#[cfg(feature = "walk")]
+use walkdir::WalkDir;
+
+#[derive(Debug, Error)]
+pub enum BuildError {
+    #[error("Invalid glob pattern: {0}")]
+    InvalidGlob(#[from] glob::PatternError),
+    #[error(transparent)]
+    InvalidRegex(#[from] RegexError),
+    /// An empty marker or marker regex, which would match every comment.
+    #[error("Markers must not be empty")]
+    EmptyMarker,
+}
+
+/// What an `on_removal` callback wants done with a marked comment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    Remove,
+    /// Leave the comment as it is.
+    Keep,
+    /// Put this text where the comment was. The other lines of a block
+    /// comment spanning lines are emptied.
+    Replace(String),
+}
+
+type OnRemoval = Arc<dyn Fn(&Removal) -> Decision + Send + Sync>;
+
+/// Options for a `Cleaner`, collected before it is built.
+#[derive(Clone, Default)]
+pub struct CleanerBuilder {
+    markers: Vec<String>,
+    marker_regex: Vec<String>,
+    all_emojis: bool,
+    include_doc_comments: bool,
+    include_directives: bool,
+    includes: Vec<String>,
+    excludes: Vec<String>,
+    languages: BTreeMap<String, String>,
+    dry_run: bool,
+    on_removal: Option<OnRemoval>,
+}
+
+impl CleanerBuilder {
+    /// Adds a literal marker. Without any, the default marker is used unless
+    /// a `marker_regex` is given. An empty marker fails the build.
+    pub fn marker(mut self, marker: impl Into<String>) -> Self {
+        self.markers.push(marker.into());
+        self
+    }
+
+    /// Adds a regular expression matching marked comments.
+    pub fn marker_regex(mut self, pattern: impl Into<String>) -> Self {
+        self.marker_regex.push(pattern.into());
+        self
+    }
+
+    /// Treat any emoji as a marker.
+    pub fn all_emojis(mut self, all_emojis: bool) -> Self {
+        self.all_emojis = all_emojis;
+        self
+    }
+
+    /// Also remove marked doc comments, such as Rust's `///` and `//!` or
+    /// Python docstrings, which are left alone by default.
+    pub fn include_doc_comments(mut self, include_doc_comments: bool) -> Self {
+        self.include_doc_comments = include_doc_comments;
+        self
+    }
+
+    /// Also remove marked directives, comments read by tools such as Go's
+    /// `//go:build` constraints, which are left alone by default.
+    pub fn include_directives(mut self, include_directives: bool) -> Self {
+        self.include_directives = include_directives;
+        self
+    }
+
+    /// Only clean files matching one of these globs. Without any, every file is cleaned.
+    pub fn include_glob(mut self, glob: impl Into<String>) -> Self {
+        self.includes.push(glob.into());
+        self
+    }
+
+    pub fn exclude_glob(mut self, glob: impl Into<String>) -> Self {
+        self.excludes.push(glob.into());
+        self
+    }
+
+    /// Cleans files with extension `ext` using the comment syntax of `syntax`,
+    /// like the `[languages]` config table.
+    pub fn language(mut self, ext: impl Into<String>, syntax: impl Into<String>) -> Self {
+        self.languages.insert(ext.into(), syntax.into());
+        self
+    }
+
+    /// Report what would be removed from files without rewriting them.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Calls `decide` for every marked comment found, to keep or replace it
+    /// instead of removing it. A block comment spanning lines is one call.
+    pub fn on_removal(
+        mut self,
+        decide: impl Fn(&Removal) -> Decision + Send + Sync + 'static,
+    ) -> Self {
+        self.on_removal = Some(Arc::new(decide));
+        self
+    }
+
+    pub fn build(self) -> Result<Cleaner, BuildError> {
+        let compile = |globs: &[String]| -> Result<Vec<Pattern>, BuildError> {
+            globs.iter().map(|glob| Ok(Pattern::new(glob)?)).collect()
+        };
+        if self
+            .markers
+            .iter()
+            .chain(&self.marker_regex)
+            .any(String::is_empty)
+        {
+            return Err(BuildError::EmptyMarker);
+        }
+        let markers = Markers::new(
+            (!self.markers.is_empty()).then_some(self.markers.as_slice()),
+            (!self.marker_regex.is_empty()).then_some(self.marker_regex.as_slice()),
+            self.all_emojis,
+        )?
+        .with_doc_comments(self.include_doc_comments)
+        .with_directives(self.include_directives);
+        Ok(Cleaner {
+            options: CleanOptions { markers },
+            includes: compile(&self.includes)?,
+            excludes: compile(&self.excludes)?,
+            languages: self.languages,
+            dry_run: self.dry_run,
+            on_removal: self.on_removal,
+        })
+    }
+}
+
+/// Removes marked comments from sources and files with a fixed set of options.
+pub struct Cleaner {
+    options: CleanOptions,
+    includes: Vec<Pattern>,
+    excludes: Vec<Pattern>,
+    languages: BTreeMap<String, String>,
+    dry_run: bool,
+    on_removal: Option<OnRemoval>,
+}
+
+impl Cleaner {
+    pub fn builder() -> CleanerBuilder {
+        CleanerBuilder::default()
+    }
+
+    /// Cleans `content` written in `language`, the extension of its files.
+    pub fn clean_source(&self, content: &str, language: &str) -> CleanResult {
+        self.clean(content, language, None)
+    }
+
+    /// Cleans the lines read from `reader` as they come, writing them to
+    /// `writer`; see `clean_reader`.
+    pub fn clean_reader(
+        &self,
+        reader: impl BufRead,
+        writer: impl Write,
+        language: &str,
+    ) -> io::Result<Vec<LineChange>> {
+        clean_lines(
+            reader,
+            writer,
+            self.syntax(language),
+            &self.options.markers,
+            |change| self.decide(None, change),
+        )
+    }
+
+    fn clean(&self, content: &str, language: &str, file: Option<&Path>) -> CleanResult {
+        let syntax = self.syntax(language);
+        let mut changes = find_changes(split_bom(content).1, syntax, &self.options.markers);
+        changes.retain_mut(|change| self.decide(file, change));
+        CleanResult::new(content, changes)
+    }
+
+    fn syntax<'a>(&'a self, language: &'a str) -> &'a str {
+        self.languages
+            .get(language)
+            .map_or(language, String::as_str)
+    }
+
+    /// Asks the `on_removal` callback about `change`, applying a replacement.
+    /// Returns whether the change is kept.
+    fn decide(&self, file: Option<&Path>, change: &mut LineChange) -> bool {
+        let Some(on_removal) = &self.on_removal else {
+            return true;
+        };
+        match on_removal(&change.removal(file)) {
+            Decision::Remove => true,
+            Decision::Keep => false,
+            Decision::Replace(replacement) => {
+                change.replace_comment(&replacement);
+                true
+            }
+        }
+    }
+
+    /// Whether `relative`, a path with forward slashes, matches the include
+    /// globs and none of the exclude globs.
+    pub fn is_included(&self, relative: &str) -> bool {
+        !self
+            .excludes
+            .iter()
+            .any(|pattern| pattern.matches(relative))
+            && (self.includes.is_empty()
+                || self
+                    .includes
+                    .iter()
+                    .any(|pattern| pattern.matches(relative)))
+    }
+
+    /// Cleans the UTF-8 file at `path`, rewriting it when anything was
+    /// removed unless this is a dry run. Files without an extension, or with
+    /// an ambiguous one such as `.h`, get the syntax their modeline or `#!`
+    /// line names. Include and exclude globs are not checked. The file is
+    /// replaced with `write_atomic`, so it is never left half written.
+    pub fn clean_file(&self, path: &Path) -> io::Result<CleanResult> {
+        let content = fs::read_to_string(path)?;
+        let ext = path.extension().and_then(|s| s.to_str()).unwrap_or("");
+        let language = language_for_file(ext, &content);
+        let result = self.clean(&content, &language, Some(path));
+        if !self.dry_run && !result.changes.is_empty() {
+            write_atomic(path, result.output.as_bytes(), false)?;
+        }
+        Ok(result)
+    }
+
+    /// Cleans every included file under `dir`, skipping `.git` directories,
+    /// and returns the files that had marked comments. Globs are matched
+    /// against paths relative to `dir`. Files that are not UTF-8 are skipped.
+    #[cfg(feature = "walk")]
+    pub fn clean_dir(&self, dir: &Path) -> io::Result<Vec<(PathBuf, CleanResult)>> {
+        let mut cleaned = Vec::new();
+        self.for_each_cleaned(dir, |path, result| cleaned.push((path, result)))?;
+        Ok(cleaned)
+    }
+
+    /// Like `clean_dir`, handing each file with marked comments to `each` as
+    /// soon as it is cleaned.
+    #[cfg(feature = "walk")]
+    pub(crate) fn for_each_cleaned(
+        &self,
+        dir: &Path,
+        mut each: impl FnMut(PathBuf, CleanResult),
+    ) -> io::Result<()> {
+        let walker = WalkDir::new(dir)
+            .into_iter()
+            .filter_entry(|entry| entry.file_name() != ".git");
+        for entry in walker {
+            let entry = entry?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let relative = entry.path().strip_prefix(dir).unwrap_or(entry.path());
+            let relative = relative.to_string_lossy().replace('\\', "/");
+            if !self.is_included(&relative) {
+                continue;
+            }
+            let result = match self.clean_file(entry.path()) {
+                Ok(result) => result,
+                Err(err) if err.kind() == io::ErrorKind::InvalidData => continue,
+                Err(err) => return Err(err),
+            };
+            if !result.changes.is_empty() {
+                each(entry.into_path(), result);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const BLOCK: &str = "a /* ‼️ one\ntwo\nthree */ b\nc\n";
+
+    fn cleaner(decision: Decision, calls: Arc<AtomicUsize>) -> Cleaner {
+        Cleaner::builder()
+            .on_removal(move |removal| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                assert_eq!((removal.line, removal.end_line), (1, 3));
+                decision.clone()
+            })
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn empty_marker_fails_the_build() {
+        let build = |builder: CleanerBuilder| builder.build().err();
+        assert!(matches!(
+            build(Cleaner::builder().marker("a").marker("")),
+            Some(BuildError::EmptyMarker)
+        ));
+        assert!(matches!(
+            build(Cleaner::builder().marker_regex("")),
+            Some(BuildError::EmptyMarker)
+        ));
+        assert!(build(Cleaner::builder().marker("a")).is_none());
+    }
+
+    #[test]
+    fn keeps_block_whole() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let result = cleaner(Decision::Keep, calls.clone()).clean_source(BLOCK, "rs");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(result.output, BLOCK);
+        assert!(result.changes.is_empty());
+    }
+
+    #[test]
+    fn replaces_block_once() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let decision = Decision::Replace("/* done */".to_string());
+        let result = cleaner(decision, calls.clone()).clean_source(BLOCK, "rs");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(result.output, "a /* done */\n\n b\nc\n");
+    }
+
+    #[test]
+    fn reader_asks_once_per_block() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut out = Vec::new();
+        let changes = cleaner(Decision::Keep, calls.clone())
+            .clean_reader(BLOCK.as_bytes(), &mut out, "rs")
+            .unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert!(changes.is_empty());
+        assert_eq!(String::from_utf8(out).unwrap(), BLOCK);
+    }
+}
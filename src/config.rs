@@ -0,0 +1,433 @@
+use crate::AppError;
+use crate::toml::{self, Value};
+use emoji_remover::Markers;
+use glob::Pattern;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use thiserror::Error;
+
+/// Name of the configuration file looked up at the repository root and in each subdirectory.
+pub const CONFIG_FILE_NAME: &str = ".emoji-remover.toml";
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("Failed to read config file {0}: {1}")]
+    Read(PathBuf, #[source] io::Error),
+    #[error("{0}:{1}: {2}")]
+    Parse(PathBuf, usize, String),
+    #[error("{0}: {1}")]
+    Invalid(PathBuf, String),
+    #[error("Invalid value for environment variable {0}: {1}")]
+    Env(String, String),
+}
+
+/// Prefix of the environment variables that mirror config-file keys.
+pub const ENV_PREFIX: &str = "EMOJI_REMOVER_";
+
+/// Options that can come from a config file or the command line. `None` means
+/// "not set here", so a later layer can fill it in.
+#[derive(Debug, Default, Clone)]
+pub struct Config {
+    pub include: Option<Vec<String>>,
+    pub exclude: Option<Vec<String>>,
+    pub marker: Option<Vec<String>>,
+    pub marker_regex: Option<Vec<String>>,
+    pub all_emojis: Option<bool>,
+    /// Whether marked doc comments are removed too.
+    pub include_doc_comments: Option<bool>,
+    /// Whether marked directives are removed too.
+    pub include_directives: Option<bool>,
+    /// Maps a file extension to the extension whose comment syntax it uses.
+    pub languages: BTreeMap<String, String>,
+}
+
+impl Config {
+    /// Loads `path`, returning `None` when the file does not exist.
+    pub fn load(path: &Path) -> Result<Option<Config>, ConfigError> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(ConfigError::Read(path.to_path_buf(), e)),
+        };
+        Config::parse(&text, path).map(Some)
+    }
+
+    pub fn parse(text: &str, path: &Path) -> Result<Config, ConfigError> {
+        let document = toml::parse(text)
+            .map_err(|e| ConfigError::Parse(path.to_path_buf(), e.line, e.message))?;
+        Config::from_document(document).map_err(|e| ConfigError::Invalid(path.to_path_buf(), e))
+    }
+
+    fn from_document(document: toml::Document) -> Result<Config, String> {
+        let mut config = Config::default();
+        for (table, entries) in document {
+            match table.as_str() {
+                "" => {
+                    for (key, value) in entries {
+                        match key.as_str() {
+                            "include" => config.include = Some(string_list(&key, value)?),
+                            "exclude" => config.exclude = Some(string_list(&key, value)?),
+                            "marker" => config.marker = Some(non_empty_list(&key, value)?),
+                            "marker_regex" => {
+                                config.marker_regex = Some(non_empty_list(&key, value)?)
+                            }
+                            "all_emojis" => match value {
+                                Value::Boolean(b) => config.all_emojis = Some(b),
+                                other => return Err(type_error(&key, "boolean", &other)),
+                            },
+                            "include_doc_comments" => match value {
+                                Value::Boolean(b) => config.include_doc_comments = Some(b),
+                                other => return Err(type_error(&key, "boolean", &other)),
+                            },
+                            "include_directives" => match value {
+                                Value::Boolean(b) => config.include_directives = Some(b),
+                                other => return Err(type_error(&key, "boolean", &other)),
+                            },
+                            _ => return Err(format!("unknown key '{}'", key)),
+                        }
+                    }
+                }
+                "languages" => {
+                    for (ext, value) in entries {
+                        match value {
+                            Value::String(syntax) => {
+                                config.languages.insert(ext, syntax);
+                            }
+                            other => {
+                                let key = format!("languages.{}", ext);
+                                return Err(type_error(&key, "string", &other));
+                            }
+                        }
+                    }
+                }
+                _ => return Err(format!("unknown table [{}]", table)),
+            }
+        }
+        Ok(config)
+    }
+
+    /// Reads the `EMOJI_REMOVER_<KEY>` variable for every config-file key.
+    /// List values are comma-separated, except `MARKER_REGEX` which holds a single
+    /// pattern since regexes often contain commas. `LANGUAGES` takes `ext=syntax` pairs.
+    pub fn from_env() -> Result<Config, ConfigError> {
+        Config::from_vars(|key| std::env::var(format!("{}{}", ENV_PREFIX, key)).ok())
+    }
+
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Config, ConfigError> {
+        let list = |key: &str| {
+            var(key).map(|value| {
+                value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|item| !item.is_empty())
+                    .map(String::from)
+                    .collect::<Vec<_>>()
+            })
+        };
+        let invalid = |key: &str, message: String| {
+            ConfigError::Env(format!("{}{}", ENV_PREFIX, key), message)
+        };
+
+        let boolean = |key: &str| match var(key).as_deref().map(str::trim) {
+            None => Ok(None),
+            Some("1" | "true" | "yes" | "on") => Ok(Some(true)),
+            Some("0" | "false" | "no" | "off" | "") => Ok(Some(false)),
+            Some(other) => Err(invalid(
+                key,
+                format!("expected a boolean, found '{}'", other),
+            )),
+        };
+        let mut languages = BTreeMap::new();
+        for pair in list("LANGUAGES").unwrap_or_default() {
+            match pair.split_once('=') {
+                Some((ext, syntax)) => {
+                    languages.insert(ext.trim().to_string(), syntax.trim().to_string());
+                }
+                None => {
+                    return Err(invalid(
+                        "LANGUAGES",
+                        format!("expected ext=syntax, found '{}'", pair),
+                    ));
+                }
+            }
+        }
+        Ok(Config {
+            include: list("INCLUDE"),
+            exclude: list("EXCLUDE"),
+            marker: list("MARKER"),
+            marker_regex: var("MARKER_REGEX")
+                .filter(|p| !p.is_empty())
+                .map(|p| vec![p]),
+            all_emojis: boolean("ALL_EMOJIS")?,
+            include_doc_comments: boolean("INCLUDE_DOC_COMMENTS")?,
+            include_directives: boolean("INCLUDE_DIRECTIVES")?,
+            languages,
+        })
+    }
+
+    /// Layers `overrides` on top of `self`: every option set in `overrides` wins.
+    pub fn merge(&mut self, overrides: Config) {
+        if overrides.include.is_some() {
+            self.include = overrides.include;
+        }
+        if overrides.exclude.is_some() {
+            self.exclude = overrides.exclude;
+        }
+        if overrides.marker.is_some() {
+            self.marker = overrides.marker;
+        }
+        if overrides.marker_regex.is_some() {
+            self.marker_regex = overrides.marker_regex;
+        }
+        if overrides.all_emojis.is_some() {
+            self.all_emojis = overrides.all_emojis;
+        }
+        if overrides.include_doc_comments.is_some() {
+            self.include_doc_comments = overrides.include_doc_comments;
+        }
+        if overrides.include_directives.is_some() {
+            self.include_directives = overrides.include_directives;
+        }
+        self.languages.extend(overrides.languages);
+    }
+}
+
+fn type_error(key: &str, expected: &str, found: &Value) -> String {
+    format!(
+        "'{}' must be a {}, found {}",
+        key,
+        expected,
+        found.type_name()
+    )
+}
+
+/// Accepts either a single string or an array of strings.
+fn string_list(key: &str, value: Value) -> Result<Vec<String>, String> {
+    match value {
+        Value::String(s) => Ok(vec![s]),
+        Value::Array(items) => items
+            .into_iter()
+            .map(|item| match item {
+                Value::String(s) => Ok(s),
+                other => Err(type_error(key, "list of strings", &other)),
+            })
+            .collect(),
+        other => Err(type_error(key, "list of strings", &other)),
+    }
+}
+
+/// A `string_list` without empty strings, which would match every comment.
+fn non_empty_list(key: &str, value: Value) -> Result<Vec<String>, String> {
+    let list = string_list(key, value)?;
+    if list.iter().any(String::is_empty) {
+        return Err(format!("'{}' must not contain an empty string", key));
+    }
+    Ok(list)
+}
+
+/// Include globs used when neither the config nor the command line sets any.
+pub const DEFAULT_INCLUDES: &[&str] = &[
+    "*.rs", "*.toml", "*.py", "*.jsx", "*.tsx", "*.html", "*.css", "*.js", "*.ts", "*.lua",
+    "*.vim", "*.sh", "*.bash", "*.zsh", "*.go", "*.c", "*.h", "*.cc", "*.cpp", "*.hpp", "*.java",
+    "*.kt", "*.kts", "*.scala", "*.cs", "*.rb", "*.php", "*.swift", "*.sql", "*.hs", "*.ex",
+    "*.exs", "*.erl", "*.hrl", "*.ml", "*.mli", "*.fs", "*.fsi", "*.fsx", "*.m",
+];
+
+/// The effective, compiled options for one directory.
+pub struct Settings {
+    pub includes: Vec<Pattern>,
+    pub excludes: Vec<Pattern>,
+    /// Directories whose whole contents are excluded, taken from exclude
+    /// globs ending in `/**`.
+    excluded_dirs: Vec<Pattern>,
+    pub markers: Markers,
+    /// Extension aliases from the `[languages]` config table.
+    pub languages: BTreeMap<String, String>,
+}
+
+impl Settings {
+    fn new(config: &Config) -> Result<Settings, AppError> {
+        let compile = |globs: &[String]| -> Result<Vec<Pattern>, AppError> {
+            globs
+                .iter()
+                .map(|s| Pattern::new(s).map_err(AppError::InvalidGlob))
+                .collect()
+        };
+        let includes = match &config.include {
+            Some(globs) => compile(globs)?,
+            None => compile(
+                &DEFAULT_INCLUDES
+                    .iter()
+                    .map(|s| s.to_string())
+                    .collect::<Vec<_>>(),
+            )?,
+        };
+        let excludes = config.exclude.as_deref().unwrap_or_default();
+        let dir_excludes: Vec<String> = excludes
+            .iter()
+            .filter_map(|glob| glob.strip_suffix("/**"))
+            .map(String::from)
+            .collect();
+        Ok(Settings {
+            includes,
+            excludes: compile(excludes)?,
+            excluded_dirs: compile(&dir_excludes)?,
+            markers: Markers::new(
+                config.marker.as_deref(),
+                config.marker_regex.as_deref(),
+                config.all_emojis.unwrap_or(false),
+            )?
+            .with_doc_comments(config.include_doc_comments.unwrap_or(false))
+            .with_directives(config.include_directives.unwrap_or(false)),
+            languages: config.languages.clone(),
+        })
+    }
+
+    /// Whether every path under `dir`, relative to the repository root, is
+    /// excluded, so walking can skip the directory.
+    pub fn excludes_dir(&self, dir: &str) -> bool {
+        self.excluded_dirs
+            .iter()
+            .any(|pattern| pattern.matches(dir))
+    }
+
+    /// The extension whose comment syntax applies to files with extension `ext`.
+    pub fn syntax_for<'a>(&'a self, ext: &'a str) -> &'a str {
+        self.languages.get(ext).map_or(ext, String::as_str)
+    }
+}
+
+/// Resolves the settings for each directory by layering every
+/// `.emoji-remover.toml` from the repository root down to that directory, with
+/// environment and command-line overrides applied last. Glob patterns are always matched against
+/// paths relative to the repository root, whichever file defines them.
+pub struct ConfigResolver {
+    root: Option<PathBuf>,
+    overrides: Config,
+    /// Config files merged from the root down to each directory, before CLI overrides.
+    layered: HashMap<PathBuf, Config>,
+    settings: HashMap<PathBuf, Rc<Settings>>,
+}
+
+impl ConfigResolver {
+    pub fn new(root: Option<PathBuf>, overrides: Config) -> Self {
+        ConfigResolver {
+            root,
+            overrides,
+            layered: HashMap::new(),
+            settings: HashMap::new(),
+        }
+    }
+
+    /// Settings for files directly inside `dir`. Directories outside the
+    /// repository, and every directory when running without one, get the
+    /// root-level settings.
+    pub fn settings_for(&mut self, dir: &Path) -> Result<Rc<Settings>, AppError> {
+        let key = match &self.root {
+            Some(root) if dir.starts_with(root) => dir.to_path_buf(),
+            Some(root) => root.clone(),
+            None => PathBuf::new(),
+        };
+        if let Some(settings) = self.settings.get(&key) {
+            return Ok(Rc::clone(settings));
+        }
+        let mut config = match &self.root {
+            Some(_) => self.layered(&key)?.clone(),
+            None => Config::default(),
+        };
+        config.merge(self.overrides.clone());
+        let settings = Rc::new(Settings::new(&config)?);
+        self.settings.insert(key, Rc::clone(&settings));
+        Ok(settings)
+    }
+
+    fn layered(&mut self, dir: &Path) -> Result<&Config, ConfigError> {
+        if !self.layered.contains_key(dir) {
+            let is_root = self.root.as_deref() == Some(dir);
+            let mut config = match dir.parent() {
+                Some(parent) if !is_root => self.layered(parent)?.clone(),
+                _ => Config::default(),
+            };
+            if let Some(local) = Config::load(&dir.join(CONFIG_FILE_NAME))? {
+                config.merge(local);
+            }
+            self.layered.insert(dir.to_path_buf(), config);
+        }
+        Ok(&self.layered[dir])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_pairs(pairs: &[(&str, &str)]) -> Result<Config, ConfigError> {
+        let vars: HashMap<String, String> = pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        Config::from_vars(|key| vars.get(key).cloned())
+    }
+
+    #[test]
+    fn env_lists_and_booleans() {
+        let config = from_pairs(&[
+            ("INCLUDE", "*.rs, src/**,"),
+            ("MARKER_REGEX", "TODO(a,b)"),
+            ("ALL_EMOJIS", "yes"),
+            ("INCLUDE_DIRECTIVES", "0"),
+            ("LANGUAGES", "h=c, tmpl = html"),
+        ])
+        .unwrap();
+        assert_eq!(config.include.unwrap(), ["*.rs", "src/**"]);
+        assert_eq!(config.exclude, None);
+        assert_eq!(config.marker_regex.unwrap(), ["TODO(a,b)"]);
+        assert_eq!(config.all_emojis, Some(true));
+        assert_eq!(config.include_directives, Some(false));
+        assert_eq!(config.include_doc_comments, None);
+        assert_eq!(config.languages["h"], "c");
+        assert_eq!(config.languages["tmpl"], "html");
+    }
+
+    #[test]
+    fn env_invalid_values() {
+        let err = from_pairs(&[("ALL_EMOJIS", "maybe")]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid value for environment variable EMOJI_REMOVER_ALL_EMOJIS: \
+             expected a boolean, found 'maybe'"
+        );
+        assert!(from_pairs(&[("LANGUAGES", "h")]).is_err());
+    }
+
+    #[test]
+    fn merge_prefers_overrides() {
+        let mut config = from_pairs(&[("MARKER", "a"), ("ALL_EMOJIS", "true")]).unwrap();
+        config.merge(from_pairs(&[("MARKER", "b")]).unwrap());
+        assert_eq!(config.marker.unwrap(), ["b"]);
+        assert_eq!(config.all_emojis, Some(true));
+    }
+
+    #[test]
+    fn empty_markers_are_rejected() {
+        let path = Path::new(CONFIG_FILE_NAME);
+        for text in [
+            "marker = \"\"",
+            "marker = [\"a\", \"\"]",
+            "marker_regex = \"\"",
+        ] {
+            let err = Config::parse(text, path).unwrap_err();
+            assert!(
+                err.to_string()
+                    .ends_with("must not contain an empty string"),
+                "{}",
+                err
+            );
+        }
+        let config = Config::parse("marker = [\"a\", \"b\"]", path).unwrap();
+        assert_eq!(config.marker.unwrap(), ["a", "b"]);
+    }
+}
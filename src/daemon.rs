@@ -0,0 +1,180 @@
+//! `--daemon`: stays resident and cleans files on request over a Unix domain
+//! socket, so editors cleaning on every save skip repository discovery, the
+//! walk and loading the cache each time.
+//!
+//! Clients send one JSON request per line and get one JSON answer per line:
+//!
+//! ```text
+//! {"id": 1, "method": "clean", "path": "src/main.rs"}
+//! {"id":1,"result":{"path":"src/main.rs","found":true,"written":true,"removals":[...]}}
+//! ```
+//!
+//! `clean` takes a path relative to the repository root, or absolute, and
+//! handles the file as a run of the command line would, in the daemon's mode.
+//! `refresh` lists the files to process again, which `clean` also does when
+//! it is given a file it does not know. `shutdown` stops the daemon.
+//! Connections are served one at a time.
+
+use crate::json::{self, Value};
+use crate::report::{OutputFormat, json_string, removal_fields};
+use crate::{AppError, Session, process_file};
+use log::{debug, info, warn};
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+
+/// Where the daemon listens unless `--socket` says otherwise.
+pub fn location(git_dir: &Path) -> PathBuf {
+    git_dir.join("emoji-remover").join("daemon.sock")
+}
+
+struct Daemon<F> {
+    files: HashSet<PathBuf>,
+    list_files: F,
+}
+
+/// Answers requests on `socket` until a client asks for `shutdown`. `files`
+/// are the files to process, as listed at startup by `list_files`.
+pub fn serve(
+    socket: &Path,
+    files: Vec<PathBuf>,
+    session: &mut Session,
+    list_files: impl FnMut(&mut Session) -> Result<Vec<PathBuf>, AppError>,
+) -> io::Result<()> {
+    if UnixStream::connect(socket).is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            format!("another daemon is listening on {}", socket.display()),
+        ));
+    }
+    // Left behind by a daemon that did not shut down cleanly
+    if socket.exists() {
+        fs::remove_file(socket)?;
+    }
+    if let Some(dir) = socket.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let listener = UnixListener::bind(socket)?;
+    info!("Listening on {}", socket.display());
+
+    // Findings are collected as for buffered formats, so answers can list them
+    session.format = OutputFormat::Json;
+    let mut daemon = Daemon {
+        files: files.into_iter().collect(),
+        list_files,
+    };
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                warn!("Could not accept a connection: {}", err);
+                continue;
+            }
+        };
+        match daemon.converse(session, stream) {
+            Ok(true) => break,
+            Ok(false) => {}
+            Err(err) => debug!("Connection closed: {}", err),
+        }
+    }
+    fs::remove_file(socket)
+}
+
+impl<F> Daemon<F>
+where
+    F: FnMut(&mut Session) -> Result<Vec<PathBuf>, AppError>,
+{
+    /// Answers requests on `stream` until it closes, returning whether the
+    /// client asked to shut down.
+    fn converse(&mut self, session: &mut Session, stream: UnixStream) -> io::Result<bool> {
+        let mut writer = stream.try_clone()?;
+        for line in BufReader::new(stream).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (answer, shutdown) = self.answer(session, &line);
+            writeln!(writer, "{}", answer)?;
+            writer.flush()?;
+            if shutdown {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// The answer to the request on `line`, and whether it asked to shut down.
+    fn answer(&mut self, session: &mut Session, line: &str) -> (String, bool) {
+        let request = match json::parse(line) {
+            Ok(request) => request,
+            Err(err) => return (error_answer(&Value::Null, &err), false),
+        };
+        let id = request.get("id");
+        let result = match request.get("method").as_str() {
+            Some("clean") => match request.get("path").as_str() {
+                Some(path) => self.clean(session, Path::new(path)),
+                None => Err("clean needs a path".to_string()),
+            },
+            Some("refresh") => self.refresh(session).map(|()| "null".to_string()),
+            Some("shutdown") => return (format!("{{\"id\":{},\"result\":null}}", id), true),
+            Some(method) => Err(format!("Unknown method: {}", method)),
+            None => Err("Missing method".to_string()),
+        };
+        let answer = match result {
+            Ok(result) => format!("{{\"id\":{},\"result\":{}}}", id, result),
+            Err(message) => error_answer(id, &message),
+        };
+        (answer, false)
+    }
+
+    fn refresh(&mut self, session: &mut Session) -> Result<(), String> {
+        let files = (self.list_files)(session).map_err(|err| err.to_string())?;
+        self.files = files.into_iter().collect();
+        Ok(())
+    }
+
+    fn clean(&mut self, session: &mut Session, path: &Path) -> Result<String, String> {
+        let path = session.root.join(path);
+        let path = fs::canonicalize(&path).unwrap_or(path);
+        if !self.files.contains(&path) {
+            // The file may have been created since the files were listed
+            self.refresh(session)?;
+            if !self.files.contains(&path) {
+                return Err(format!(
+                    "{} is not a file emoji-remover processes",
+                    path.display()
+                ));
+            }
+        }
+        // Each request is a run of its own for `emoji-remover undo`
+        if let Some(journal) = &mut session.journal {
+            journal.restart();
+        }
+        session.reports.clear();
+        let found = process_file(&path, session).map_err(|err| err.to_string())?;
+        if let Some(cache) = &session.cache
+            && let Err(err) = cache.save()
+        {
+            warn!("Could not save the cache: {}", err);
+        }
+        let report = session.reports.pop();
+        let removals: Vec<String> = report
+            .iter()
+            .flat_map(|report| &report.changes)
+            .map(|change| format!("{{{}}}", removal_fields(&change.removal(None))))
+            .collect();
+        Ok(format!(
+            "{{\"path\":{},\"found\":{},\"written\":{},\"removals\":[{}]}}",
+            json_string(&session.label(&path)),
+            found,
+            report.is_some_and(|report| report.written),
+            removals.join(",")
+        ))
+    }
+}
+
+fn error_answer(id: &Value, message: &str) -> String {
+    format!("{{\"id\":{},\"error\":{}}}", id, json_string(message))
+}
@@ -0,0 +1,72 @@
+use emoji_remover::{LineChange, LineEdit, line_edits, split_lines};
+
+/// Lines of unchanged context shown around each change, matching `diff -u`.
+const CONTEXT: usize = 3;
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const CYAN: &str = "\x1b[36m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// Renders the given line changes as a unified diff that `git apply` accepts.
+/// `changes` must be ordered by line. Cleaning never adds or removes lines, so
+/// every hunk has the same length on both sides. Carriage returns of CRLF lines
+/// are kept so the patch applies to the file as it is.
+pub fn unified_diff(path: &str, content: &str, changes: &[LineChange], color: bool) -> String {
+    let lines: Vec<(&str, &str)> = split_lines(content).collect();
+    let missing_final_newline = !content.ends_with('\n');
+    let paint = |code: &str, text: &str| {
+        if color {
+            format!("{}{}{}\n", code, text, RESET)
+        } else {
+            format!("{}\n", text)
+        }
+    };
+
+    let mut out = String::new();
+    out.push_str(&paint(BOLD, &format!("--- a/{}", path)));
+    out.push_str(&paint(BOLD, &format!("+++ b/{}", path)));
+
+    // Hunks are made of lines, whichever comment they belong to
+    let edits = line_edits(changes);
+    let changes: Vec<(usize, &str, &str)> = edits.iter().flat_map(LineEdit::lines).collect();
+    let mut idx = 0;
+    while idx < changes.len() {
+        // Grow the hunk while the next change falls within the trailing context
+        let mut end = idx;
+        while end + 1 < changes.len() && changes[end + 1].0 <= changes[end].0 + 2 * CONTEXT + 1 {
+            end += 1;
+        }
+        let hunk = &changes[idx..=end];
+        let first = hunk[0].0.saturating_sub(CONTEXT).max(1);
+        let last = (hunk[hunk.len() - 1].0 + CONTEXT).min(lines.len());
+        let len = last - first + 1;
+        out.push_str(&paint(
+            CYAN,
+            &format!("@@ -{},{} +{},{} @@", first, len, first, len),
+        ));
+
+        let mut pending = hunk.iter().peekable();
+        for number in first..=last {
+            let no_newline = missing_final_newline && number == lines.len();
+            let (line, ending) = lines[number - 1];
+            let cr = if ending == "\r\n" { "\r" } else { "" };
+            match pending.next_if(|(line_number, _, _)| *line_number == number) {
+                Some((_, original, cleaned)) => {
+                    out.push_str(&paint(RED, &format!("-{}{}", original, cr)));
+                    if no_newline {
+                        out.push_str("\\ No newline at end of file\n");
+                    }
+                    out.push_str(&paint(GREEN, &format!("+{}{}", cleaned, cr)));
+                }
+                None => out.push_str(&format!(" {}{}\n", line, cr)),
+            }
+            if no_newline {
+                out.push_str("\\ No newline at end of file\n");
+            }
+        }
+        idx = end + 1;
+    }
+    out
+}
@@ -0,0 +1,194 @@
+//! Decoding of files that are not UTF-8, and encoding the cleaned text back.
+//!
+//! UTF-16 is recognized by its byte order mark. Other legacy files are only
+//! decoded when a fallback encoding is chosen with `--fallback-encoding`, and
+//! files with a few invalid UTF-8 sequences with `--lossy-utf8`.
+
+use clap::ValueEnum;
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Encoding {
+    #[value(skip)]
+    Utf8,
+    #[value(skip)]
+    Utf16Le,
+    #[value(skip)]
+    Utf16Be,
+    /// Windows-1252, the usual encoding of legacy Western European files.
+    /// Also reads Latin-1
+    #[value(name = "windows-1252", alias = "latin1")]
+    Windows1252,
+}
+
+/// Characters of Windows-1252 bytes 0x80 to 0x9F. The five bytes it leaves
+/// undefined map to the C1 control of the same value, so they round-trip.
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '\u{20AC}', '\u{81}', '\u{201A}', '\u{192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{2C6}', '\u{2030}', '\u{160}', '\u{2039}', '\u{152}', '\u{8D}', '\u{17D}', '\u{8F}',
+    '\u{90}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{2DC}', '\u{2122}', '\u{161}', '\u{203A}', '\u{153}', '\u{9D}', '\u{17E}', '\u{178}',
+];
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf16Le => "UTF-16LE",
+            Encoding::Utf16Be => "UTF-16BE",
+            Encoding::Windows1252 => "Windows-1252",
+        })
+    }
+}
+
+impl Encoding {
+    /// Decodes `bytes`, trying UTF-16 when they start with its byte order
+    /// mark, then UTF-8, then `fallback`. A UTF-16 byte order mark is kept as
+    /// a leading U+FEFF so it is written back. UTF-8 is borrowed rather than
+    /// copied. Returns `None` when no encoding fits.
+    pub fn decode(bytes: &[u8], fallback: Option<Encoding>) -> Option<(Encoding, Cow<'_, str>)> {
+        let utf16 = match bytes {
+            [0xFF, 0xFE, ..] => Some(Encoding::Utf16Le),
+            [0xFE, 0xFF, ..] => Some(Encoding::Utf16Be),
+            _ => None,
+        };
+        if let Some(encoding) = utf16
+            && let Some(text) = decode_utf16(bytes, encoding == Encoding::Utf16Be)
+        {
+            return Some((encoding, Cow::Owned(text)));
+        }
+        if let Ok(text) = std::str::from_utf8(bytes) {
+            return Some((Encoding::Utf8, Cow::Borrowed(text)));
+        }
+        match fallback? {
+            Encoding::Windows1252 => {
+                let text = bytes
+                    .iter()
+                    .map(|&b| match b {
+                        0x80..=0x9F => WINDOWS_1252_HIGH[usize::from(b - 0x80)],
+                        _ => char::from(b),
+                    })
+                    .collect();
+                Some((Encoding::Windows1252, Cow::Owned(text)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Encodes `text` back, or returns `None` when it has characters this
+    /// encoding cannot represent.
+    pub fn encode(self, text: &str) -> Option<Vec<u8>> {
+        match self {
+            Encoding::Utf8 => Some(text.as_bytes().to_vec()),
+            Encoding::Utf16Le => Some(text.encode_utf16().flat_map(u16::to_le_bytes).collect()),
+            Encoding::Utf16Be => Some(text.encode_utf16().flat_map(u16::to_be_bytes).collect()),
+            Encoding::Windows1252 => text
+                .chars()
+                .map(|c| match u8::try_from(c) {
+                    Ok(b) if !(0x80..=0x9F).contains(&b) => Some(b),
+                    _ => WINDOWS_1252_HIGH
+                        .iter()
+                        .position(|&high| high == c)
+                        .map(|i| 0x80 + i as u8),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// How many leading bytes are inspected to tell binary files apart, as git does.
+pub const SNIFF_LEN: usize = 8000;
+
+/// Whether `head`, the start of a file, looks binary: it holds a NUL byte and
+/// is not UTF-16, where NUL bytes are part of ordinary text.
+pub fn looks_binary(head: &[u8]) -> bool {
+    !matches!(head, [0xFF, 0xFE, ..] | [0xFE, 0xFF, ..]) && head.contains(&0)
+}
+
+/// How files are decoded, as chosen on the command line.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Decoder {
+    pub fallback: Option<Encoding>,
+    /// Read UTF-8 with invalid sequences, passing the lines that contain
+    /// them through untouched.
+    pub lossy: bool,
+}
+
+/// The text of a file and what is needed to write it back.
+pub struct Decoded<'a> {
+    pub encoding: Encoding,
+    pub text: Cow<'a, str>,
+    /// Lines (1-based) holding invalid UTF-8, which are shown with
+    /// replacement characters and must not be changed.
+    pub invalid_lines: HashSet<usize>,
+}
+
+impl Decoder {
+    /// Decodes `bytes`, returning `None` when no allowed encoding fits.
+    pub fn decode<'a>(&self, bytes: &'a [u8]) -> Option<Decoded<'a>> {
+        if let Some((encoding, text)) = Encoding::decode(bytes, self.fallback) {
+            return Some(Decoded {
+                encoding,
+                text,
+                invalid_lines: HashSet::new(),
+            });
+        }
+        if !self.lossy {
+            return None;
+        }
+        let invalid_lines = bytes
+            .split_inclusive(|&b| b == b'\n')
+            .enumerate()
+            .filter(|(_, line)| std::str::from_utf8(line).is_err())
+            .map(|(idx, _)| idx + 1)
+            .collect();
+        Some(Decoded {
+            encoding: Encoding::Utf8,
+            text: String::from_utf8_lossy(bytes),
+            invalid_lines,
+        })
+    }
+}
+
+impl Decoded<'_> {
+    /// Whether the text is the file's bytes as they are, so cleaned text can
+    /// be written out without encoding it.
+    pub fn is_verbatim(&self) -> bool {
+        self.encoding == Encoding::Utf8 && self.invalid_lines.is_empty()
+    }
+
+    /// Encodes `output`, the cleaned text with the same lines as the decoded
+    /// one, taking invalid lines from `original` as they were.
+    pub fn encode(&self, original: &[u8], output: &str) -> Option<Vec<u8>> {
+        if self.invalid_lines.is_empty() {
+            return self.encoding.encode(output);
+        }
+        let mut bytes = Vec::with_capacity(original.len());
+        let raw_lines = original.split_inclusive(|&b| b == b'\n');
+        for (idx, (raw, line)) in raw_lines.zip(output.split_inclusive('\n')).enumerate() {
+            if self.invalid_lines.contains(&(idx + 1)) {
+                bytes.extend_from_slice(raw);
+            } else {
+                bytes.extend_from_slice(line.as_bytes());
+            }
+        }
+        Some(bytes)
+    }
+}
+
+fn decode_utf16(bytes: &[u8], big_endian: bool) -> Option<String> {
+    if !bytes.len().is_multiple_of(2) {
+        return None;
+    }
+    let units = bytes.chunks_exact(2).map(|pair| {
+        let pair = [pair[0], pair[1]];
+        if big_endian {
+            u16::from_be_bytes(pair)
+        } else {
+            u16::from_le_bytes(pair)
+        }
+    });
+    char::decode_utf16(units).collect::<Result<_, _>>().ok()
+}
@@ -0,0 +1,155 @@
+//! C interface to the cleaning engine, declared in `include/emoji_remover.h`.
+//!
+//! Text crosses the boundary as pointer and length pairs. Cleaned text is
+//! allocated here and must be released with `emoji_remover_free`.
+
+use crate::markers::Markers;
+use crate::{CleanOptions, clean_source};
+use std::ffi::{CStr, c_char};
+use std::{ptr, slice};
+
+pub const EMOJI_REMOVER_OK: i32 = 0;
+/// A required pointer was null.
+pub const EMOJI_REMOVER_NULL_ARGUMENT: i32 = 1;
+/// The source, language or a marker is not valid UTF-8.
+pub const EMOJI_REMOVER_INVALID_UTF8: i32 = 2;
+/// A marker is empty, so it would match every comment.
+pub const EMOJI_REMOVER_EMPTY_MARKER: i32 = 3;
+
+/// Removes the marked comments from the `source_len` bytes at `source`,
+/// written in `language`, the extension of its files. A comment is marked by
+/// any of the `marker_count` strings at `markers`, or by the default marker
+/// when there are none. On success, `*output` points to the cleaned text,
+/// `*output_len` bytes followed by a NUL, and `*removed` holds the number of
+/// comments removed if `removed` is not null.
+///
+/// # Safety
+///
+/// `source` must point to `source_len` readable bytes, `language` to a
+/// NUL-terminated string, `markers` to `marker_count` NUL-terminated strings
+/// unless the count is 0, and `output`, `output_len` and a non-null
+/// `removed` to writable locations.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn emoji_remover_clean(
+    source: *const c_char,
+    source_len: usize,
+    language: *const c_char,
+    markers: *const *const c_char,
+    marker_count: usize,
+    output: *mut *mut c_char,
+    output_len: *mut usize,
+    removed: *mut usize,
+) -> i32 {
+    if source.is_null()
+        || language.is_null()
+        || (markers.is_null() && marker_count > 0)
+        || output.is_null()
+        || output_len.is_null()
+    {
+        return EMOJI_REMOVER_NULL_ARGUMENT;
+    }
+    let source = unsafe { slice::from_raw_parts(source.cast::<u8>(), source_len) };
+    let Ok(source) = std::str::from_utf8(source) else {
+        return EMOJI_REMOVER_INVALID_UTF8;
+    };
+    let Ok(language) = unsafe { CStr::from_ptr(language) }.to_str() else {
+        return EMOJI_REMOVER_INVALID_UTF8;
+    };
+    let markers = if marker_count == 0 {
+        Markers::default()
+    } else {
+        let mut literals = Vec::with_capacity(marker_count);
+        for &marker in unsafe { slice::from_raw_parts(markers, marker_count) } {
+            if marker.is_null() {
+                return EMOJI_REMOVER_NULL_ARGUMENT;
+            }
+            let Ok(marker) = unsafe { CStr::from_ptr(marker) }.to_str() else {
+                return EMOJI_REMOVER_INVALID_UTF8;
+            };
+            if marker.is_empty() {
+                return EMOJI_REMOVER_EMPTY_MARKER;
+            }
+            literals.push(marker.to_string());
+        }
+        Markers::new(Some(&literals), None, false).expect("no patterns to compile")
+    };
+    let result = clean_source(source, language, &CleanOptions { markers });
+
+    let len = result.output.len();
+    let mut bytes = result.output.into_bytes();
+    bytes.push(0);
+    let bytes = Box::into_raw(bytes.into_boxed_slice());
+    unsafe {
+        *output = bytes.cast::<c_char>();
+        *output_len = len;
+        if !removed.is_null() {
+            *removed = result.changes.len();
+        }
+    }
+    EMOJI_REMOVER_OK
+}
+
+/// Releases text returned by `emoji_remover_clean`. Null is ignored.
+///
+/// # Safety
+///
+/// `output` and `output_len` must be exactly as returned by
+/// `emoji_remover_clean`, and the text must not be used or freed afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn emoji_remover_free(output: *mut c_char, output_len: usize) {
+    if output.is_null() {
+        return;
+    }
+    let bytes = ptr::slice_from_raw_parts_mut(output.cast::<u8>(), output_len + 1);
+    drop(unsafe { Box::from_raw(bytes) });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clean(source: &str, markers: &[&CStr]) -> Result<(String, usize), i32> {
+        let markers: Vec<*const c_char> = markers.iter().map(|marker| marker.as_ptr()).collect();
+        let (mut output, mut output_len, mut removed) = (ptr::null_mut(), 0, 0);
+        let status = unsafe {
+            emoji_remover_clean(
+                source.as_ptr().cast(),
+                source.len(),
+                c"rs".as_ptr(),
+                markers.as_ptr(),
+                markers.len(),
+                &mut output,
+                &mut output_len,
+                &mut removed,
+            )
+        };
+        if status != EMOJI_REMOVER_OK {
+            return Err(status);
+        }
+        let bytes = unsafe { slice::from_raw_parts(output.cast::<u8>(), output_len) };
+        let cleaned = String::from_utf8(bytes.to_vec()).unwrap();
+        unsafe { emoji_remover_free(output, output_len) };
+        Ok((cleaned, removed))
+    }
+
+    #[test]
+    fn cleans_with_every_marker() {
+        let source = "a(); // x one\nb(); // y two\nc(); // ‼️ three\n";
+        assert_eq!(
+            clean(source, &[c"x", c"y"]),
+            Ok(("a();\nb();\nc(); // ‼️ three\n".to_string(), 2))
+        );
+        assert_eq!(
+            clean(source, &[]),
+            Ok(("a(); // x one\nb(); // y two\nc();\n".to_string(), 1))
+        );
+    }
+
+    #[test]
+    fn empty_marker_is_an_error() {
+        assert_eq!(
+            clean("a(); // x\n", &[c"x", c""]),
+            Err(EMOJI_REMOVER_EMPTY_MARKER)
+        );
+    }
+}
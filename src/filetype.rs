@@ -0,0 +1,176 @@
+//! Editor filetype names, `#!` lines and modelines, for sources whose file
+//! extension is missing or does not tell their comment syntax.
+
+/// Neovim filetypes and the extension whose comment syntax they use.
+const FILETYPES: &[(&str, &str)] = &[
+    ("rust", "rs"),
+    ("python", "py"),
+    ("javascript", "js"),
+    ("javascriptreact", "jsx"),
+    ("typescript", "ts"),
+    ("typescriptreact", "tsx"),
+    ("html", "html"),
+    ("css", "css"),
+    ("toml", "toml"),
+    ("bash", "sh"),
+    ("zsh", "sh"),
+    ("ruby", "rb"),
+    ("perl", "pl"),
+    ("kotlin", "kt"),
+    ("haskell", "hs"),
+    ("elixir", "ex"),
+    ("erlang", "erl"),
+    ("ocaml", "ml"),
+    ("fsharp", "fs"),
+    ("matlab", "m"),
+    ("octave", "m"),
+];
+
+/// The language, as a file extension, of sources with Neovim filetype
+/// `filetype`. Unknown filetypes are taken to be extensions already.
+pub fn language_for_filetype(filetype: &str) -> &str {
+    FILETYPES
+        .iter()
+        .find(|(name, _)| *name == filetype)
+        .map_or(filetype, |(_, ext)| ext)
+}
+
+/// Interpreters named on `#!` lines and the extension whose comment syntax
+/// their scripts use.
+const INTERPRETERS: &[(&str, &str)] = &[
+    ("python", "py"),
+    ("pypy", "py"),
+    ("sh", "sh"),
+    ("bash", "sh"),
+    ("dash", "sh"),
+    ("ksh", "sh"),
+    ("zsh", "sh"),
+    ("fish", "sh"),
+    ("node", "js"),
+    ("nodejs", "js"),
+    ("deno", "ts"),
+    ("bun", "js"),
+    ("ts-node", "ts"),
+    ("ruby", "rb"),
+    ("perl", "pl"),
+    ("lua", "lua"),
+    ("rust-script", "rs"),
+];
+
+/// The language, as a file extension, of a script whose first line is the
+/// `#!` line in `content`, e.g. `py` for `#!/usr/bin/env python3`.
+pub fn language_for_shebang(content: &str) -> Option<&'static str> {
+    let line = content.lines().next()?.strip_prefix("#!")?;
+    let mut words = line.split_whitespace();
+    let mut program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        // Skip options such as -S and variable assignments
+        program = words.find(|word| !word.starts_with('-') && !word.contains('='))?;
+    }
+    // Versioned names such as python3.12
+    let name = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    INTERPRETERS
+        .iter()
+        .find(|(interpreter, _)| *interpreter == name)
+        .map(|(_, ext)| *ext)
+}
+
+/// Emacs major modes and the extension whose comment syntax they use, where
+/// they differ from Neovim filetypes.
+const EMACS_MODES: &[(&str, &str)] = &[
+    ("c++", "cpp"),
+    ("shell-script", "sh"),
+    ("js", "js"),
+    ("js2", "js"),
+    ("cperl", "pl"),
+];
+
+/// Extensions that several languages share, or that files of any language
+/// may carry, so a modeline or `#!` line tells the syntax better.
+const AMBIGUOUS_EXTENSIONS: &[&str] = &["", "h", "inc", "tpl", "in"];
+
+/// How many lines at either end of a file Vim looks at for modelines.
+const MODELINE_LINES: usize = 5;
+
+/// Whether files with extension `ext` ("" for none) are identified by their
+/// content rather than by the extension.
+pub fn is_ambiguous_extension(ext: &str) -> bool {
+    AMBIGUOUS_EXTENSIONS.contains(&ext)
+}
+
+/// The language, as a file extension, of a file with extension `ext` ("" for
+/// none) starting or ending with `content`. Ambiguous extensions give way to
+/// a Vim or Emacs modeline, then to a `#!` line.
+pub fn language_for_file(ext: &str, content: &str) -> String {
+    if !is_ambiguous_extension(ext) {
+        return ext.to_string();
+    }
+    language_for_modeline(content)
+        .or_else(|| language_for_shebang(content).map(String::from))
+        .unwrap_or_else(|| ext.to_string())
+}
+
+/// The language, as a file extension, set by a Vim modeline such as
+/// `# vim: ft=python` in the first or last lines of `content`, or by an Emacs
+/// `-*- mode: c -*-` line at its top.
+pub fn language_for_modeline(content: &str) -> Option<String> {
+    let lines: Vec<&str> = content.lines().collect();
+    let head = &lines[..lines.len().min(MODELINE_LINES)];
+    let tail = &lines[lines.len().saturating_sub(MODELINE_LINES)..];
+    let vim = head.iter().chain(tail).find_map(|line| vim_filetype(line));
+    if let Some(filetype) = vim {
+        return Some(language_for_filetype(filetype).to_string());
+    }
+    // Emacs reads the first line, or the second after a `#!` line
+    let emacs_lines = if lines.first().is_some_and(|line| line.starts_with("#!")) {
+        2
+    } else {
+        1
+    };
+    let mode = lines
+        .iter()
+        .take(emacs_lines)
+        .find_map(|line| emacs_mode(line))?;
+    let ext = EMACS_MODES
+        .iter()
+        .find(|(name, _)| *name == mode)
+        .map_or_else(|| language_for_filetype(&mode), |(_, ext)| ext);
+    Some(ext.to_string())
+}
+
+/// The filetype a Vim modeline on `line` sets, in either the `vim: ft=c` or
+/// the `vim: set ft=c:` form.
+fn vim_filetype(line: &str) -> Option<&str> {
+    let options = ["vim:", "vi:", "ex:"].iter().find_map(|marker| {
+        let idx = line.find(marker)?;
+        let preceded_by_space = line[..idx]
+            .chars()
+            .next_back()
+            .is_none_or(char::is_whitespace);
+        preceded_by_space.then(|| &line[idx + marker.len()..])
+    })?;
+    options
+        .split([' ', '\t', ':'])
+        .filter_map(|option| option.split_once('='))
+        .find(|(name, _)| matches!(*name, "ft" | "filetype" | "syn" | "syntax"))
+        .map(|(_, value)| value)
+        .filter(|value| !value.is_empty())
+}
+
+/// The major mode an Emacs `-*- ... -*-` line on `line` sets, lowercased.
+fn emacs_mode(line: &str) -> Option<String> {
+    let start = line.find("-*-")? + 3;
+    let end = start + line[start..].find("-*-")?;
+    let variables = line[start..end].trim();
+    let mode = if variables.contains(':') {
+        variables.split(';').find_map(|variable| {
+            let (name, value) = variable.split_once(':')?;
+            name.trim()
+                .eq_ignore_ascii_case("mode")
+                .then(|| value.trim())
+        })?
+    } else {
+        variables
+    };
+    (!mode.is_empty()).then(|| mode.to_ascii_lowercase())
+}
@@ -0,0 +1,558 @@
+//! Git queries that narrow a run down to the files being worked on.
+
+use emoji_remover::regex::Regex;
+use git2::{
+    AttrCheckFlags, AttrValue, BlameOptions, Delta, Diff, DiffOptions, ErrorCode, Index,
+    IndexEntry, IndexTime, Oid, Repository, Signature, Status, StatusOptions, Tree,
+};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// The repository being processed and, with `--recurse-submodules`, every
+/// checked-out submodule below it, each applying its own ignore rules and
+/// attributes to its own files.
+pub struct Workspace {
+    /// Repositories keyed by working tree, deepest first.
+    repos: Vec<(PathBuf, Repository)>,
+    /// Nested working trees that are not processed, with the reason why.
+    skipped: Vec<(PathBuf, &'static str)>,
+}
+
+impl Workspace {
+    pub fn open(root: &Path, recurse_submodules: bool) -> Result<Workspace, git2::Error> {
+        let mut workspace = Workspace {
+            repos: Vec::new(),
+            skipped: Vec::new(),
+        };
+        let repo = Repository::open(root)?;
+        // Linked worktrees checked out inside this one belong to other branches
+        for name in repo.worktrees()?.iter().flatten() {
+            let worktree = repo.find_worktree(name)?;
+            if let Ok(path) = std::fs::canonicalize(worktree.path())
+                && path != root
+                && path.starts_with(root)
+            {
+                workspace.skipped.push((path, "linked worktree"));
+            }
+        }
+        workspace.add(root.to_path_buf(), repo, recurse_submodules)?;
+        workspace
+            .repos
+            .sort_by_key(|(root, _)| std::cmp::Reverse(root.components().count()));
+        Ok(workspace)
+    }
+
+    fn add(
+        &mut self,
+        root: PathBuf,
+        repo: Repository,
+        recurse_submodules: bool,
+    ) -> Result<(), git2::Error> {
+        for submodule in repo.submodules()? {
+            let path = root.join(submodule.path());
+            if !recurse_submodules {
+                self.skipped.push((path, "submodule"));
+                continue;
+            }
+            // Submodules that are not checked out have nothing to clean
+            match submodule.open() {
+                Ok(sub_repo) => self.add(path, sub_repo, true)?,
+                Err(_) => self.skipped.push((path, "submodule")),
+            }
+        }
+        self.repos.push((root, repo));
+        Ok(())
+    }
+
+    /// The repository at the root of the workspace.
+    pub fn root_repo(&self) -> &Repository {
+        &self
+            .repos
+            .last()
+            .expect("workspace has a root repository")
+            .1
+    }
+
+    /// Like [`listed_paths`] across every repository in the workspace, relative
+    /// to the root working tree.
+    pub fn listed_paths(&self) -> Result<HashSet<PathBuf>, git2::Error> {
+        let workspace_root = &self
+            .repos
+            .last()
+            .expect("workspace has a root repository")
+            .0;
+        let mut paths = HashSet::new();
+        for (root, repo) in &self.repos {
+            let prefix = root.strip_prefix(workspace_root).unwrap_or(Path::new(""));
+            paths.extend(
+                listed_paths(repo)?
+                    .into_iter()
+                    .map(|path| prefix.join(path)),
+            );
+        }
+        Ok(paths)
+    }
+
+    /// Why `dir` is not processed, if it is the working tree of a submodule
+    /// (without `--recurse-submodules`) or of a nested linked worktree.
+    pub fn skip_reason(&self, dir: &Path) -> Option<&'static str> {
+        self.skipped
+            .iter()
+            .find(|(skipped, _)| skipped == dir)
+            .map(|(_, reason)| *reason)
+    }
+
+    /// Whether the directory `dir` is ignored by the repository it belongs to,
+    /// so walking can leave it out without visiting its contents. The rules
+    /// are libgit2's, not the `ignore` crate's: `.ignore` files are not read,
+    /// and global excludes and `.git/info/exclude` apply only as libgit2
+    /// applies them.
+    pub fn ignores_dir(&self, dir: &Path) -> bool {
+        let Some((repo, relative)) = self.owner(dir) else {
+            return false;
+        };
+        // The trailing separator lets patterns such as `build/` match
+        !relative.as_os_str().is_empty() && repo.is_path_ignored(relative.join("")).unwrap_or(false)
+    }
+
+    /// The repository `path` belongs to, and `path` relative to its working tree.
+    pub fn owner<'p>(&self, path: &'p Path) -> Option<(&Repository, &'p Path)> {
+        self.repos.iter().find_map(|(root, repo)| {
+            path.strip_prefix(root)
+                .ok()
+                .map(|relative| (repo, relative))
+        })
+    }
+}
+
+/// The tree of `HEAD`, or `None` before the first commit.
+fn head_tree(repo: &Repository) -> Result<Option<Tree<'_>>, git2::Error> {
+    match repo.head() {
+        Ok(head) => head.peel_to_tree().map(Some),
+        Err(e) if matches!(e.code(), ErrorCode::UnbornBranch | ErrorCode::NotFound) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Workdir-relative paths on the new side of `diff`, leaving out deletions.
+fn new_paths(diff: &Diff) -> HashSet<PathBuf> {
+    diff.deltas()
+        .filter(|delta| delta.status() != Delta::Deleted)
+        .filter_map(|delta| delta.new_file().path().map(Path::to_path_buf))
+        .collect()
+}
+
+/// Which changes a run is limited to.
+#[derive(Debug, Clone)]
+pub enum Selection {
+    /// Files whose staged content differs from `HEAD`.
+    Staged,
+    /// Files with staged or unstaged changes relative to `HEAD`.
+    Modified,
+    /// Files that differ between a revision and the working tree.
+    Since(String),
+}
+
+impl Selection {
+    /// The tree changes are measured against, or `None` before the first commit.
+    fn base<'r>(&self, repo: &'r Repository) -> Result<Option<Tree<'r>>, git2::Error> {
+        match self {
+            Selection::Staged | Selection::Modified => head_tree(repo),
+            Selection::Since(rev) => repo.revparse_single(rev)?.peel_to_tree().map(Some),
+        }
+    }
+
+    /// Workdir-relative paths of the selected files. Except with `Staged`, this
+    /// includes untracked files that are not ignored.
+    pub fn paths(&self, repo: &Repository) -> Result<HashSet<PathBuf>, git2::Error> {
+        let base = self.base(repo)?;
+        let diff = match self {
+            Selection::Staged => repo.diff_tree_to_index(base.as_ref(), None, None)?,
+            Selection::Modified | Selection::Since(_) => {
+                let mut options = DiffOptions::new();
+                options.include_untracked(true).recurse_untracked_dirs(true);
+                repo.diff_tree_to_workdir_with_index(base.as_ref(), Some(&mut options))?
+            }
+        };
+        Ok(new_paths(&diff))
+    }
+
+    /// Line numbers in each file that were added or changed relative to the
+    /// base: in its staged content with `Staged`, in its working copy
+    /// otherwise. Every line of an untracked file counts as added.
+    pub fn added_lines(&self, repo: &Repository) -> Result<AddedLines, git2::Error> {
+        let base = self.base(repo)?;
+        let mut options = DiffOptions::new();
+        options
+            .include_untracked(true)
+            .recurse_untracked_dirs(true)
+            .show_untracked_content(true)
+            .context_lines(0);
+        let diff = match self {
+            Selection::Staged => {
+                repo.diff_tree_to_index(base.as_ref(), None, Some(&mut options))?
+            }
+            Selection::Modified | Selection::Since(_) => {
+                repo.diff_tree_to_workdir_with_index(base.as_ref(), Some(&mut options))?
+            }
+        };
+        let mut added = AddedLines::new();
+        diff.foreach(
+            &mut |_, _| true,
+            None,
+            None,
+            Some(&mut |delta, _, line| {
+                if line.origin() == '+'
+                    && let (Some(path), Some(number)) = (delta.new_file().path(), line.new_lineno())
+                {
+                    added
+                        .entry(path.to_path_buf())
+                        .or_default()
+                        .insert(number as usize);
+                }
+                true
+            }),
+        )?;
+        Ok(added)
+    }
+}
+
+/// Added line numbers (1-based) keyed by workdir-relative path.
+pub type AddedLines = HashMap<PathBuf, HashSet<usize>>;
+
+/// File mode git uses for submodule entries in the index.
+const GITLINK_MODE: u32 = 0o160000;
+
+/// Tracked files plus untracked files that are not ignored, like
+/// `git ls-files --cached --others --exclude-standard`.
+pub fn listed_paths(repo: &Repository) -> Result<HashSet<PathBuf>, git2::Error> {
+    let mut paths: HashSet<PathBuf> = repo
+        .index()?
+        .iter()
+        .filter(|entry| entry.mode != GITLINK_MODE)
+        .filter_map(|entry| String::from_utf8(entry.path).ok().map(PathBuf::from))
+        .collect();
+    let mut options = StatusOptions::new();
+    options
+        .include_untracked(true)
+        .recurse_untracked_dirs(true)
+        .exclude_submodules(true);
+    for entry in repo.statuses(Some(&mut options))?.iter() {
+        if entry.status().contains(Status::WT_NEW)
+            && let Some(path) = entry.path()
+        {
+            paths.insert(PathBuf::from(path));
+        }
+    }
+    Ok(paths)
+}
+
+/// Why `.gitattributes` rules out cleaning `relative`, if they do: files marked
+/// `linguist-generated`, `binary` or `-diff` are not hand-written source.
+pub fn attribute_skip_reason(
+    repo: &Repository,
+    relative: &Path,
+) -> Result<Option<&'static str>, git2::Error> {
+    let attr = |name: &str| -> Result<AttrValue<'_>, git2::Error> {
+        let value = repo.get_attr(relative, name, AttrCheckFlags::default())?;
+        Ok(AttrValue::from_string(value))
+    };
+    if matches!(
+        attr("linguist-generated")?,
+        AttrValue::True | AttrValue::String("true")
+    ) {
+        return Ok(Some("marked linguist-generated in .gitattributes"));
+    }
+    if attr("binary")? == AttrValue::True {
+        return Ok(Some("marked binary in .gitattributes"));
+    }
+    if attr("diff")? == AttrValue::False {
+        return Ok(Some("marked -diff in .gitattributes"));
+    }
+    Ok(None)
+}
+
+/// Tracked files whose working copy differs from the index.
+fn unstaged_paths(repo: &Repository) -> Result<HashSet<PathBuf>, git2::Error> {
+    let diff = repo.diff_index_to_workdir(None, None)?;
+    Ok(new_paths(&diff))
+}
+
+/// What `--staged` runs check: the content of files as staged, rather than
+/// their working copies.
+pub struct StagedFiles {
+    repo: Repository,
+    /// Files that also have unstaged edits, whose working copy is not what
+    /// would be committed.
+    partially_staged: HashSet<PathBuf>,
+}
+
+impl StagedFiles {
+    /// Must be opened before any file is cleaned, so partially staged files
+    /// are told apart from the edits the cleanup itself makes.
+    pub fn open(root: &Path) -> Result<StagedFiles, git2::Error> {
+        let repo = Repository::open(root)?;
+        Ok(StagedFiles {
+            partially_staged: unstaged_paths(&repo)?,
+            repo,
+        })
+    }
+
+    /// Whether the working copy of `relative` differs from its staged content.
+    pub fn is_partially_staged(&self, relative: &Path) -> bool {
+        self.partially_staged.contains(relative)
+    }
+
+    /// The staged content of `relative`, like `git show :<path>`, or `None`
+    /// when it is not in the index.
+    pub fn content(&self, relative: &Path) -> Result<Option<Vec<u8>>, git2::Error> {
+        let Some(entry) = self.repo.index()?.get_path(relative, 0) else {
+            return Ok(None);
+        };
+        Ok(Some(self.repo.find_blob(entry.id)?.content().to_vec()))
+    }
+}
+
+/// Adds cleaned files back to the index so the cleanup lands in the pending
+/// commit. Files with unstaged edits must not be cleaned, as staging their
+/// working copy would sweep those edits into the commit.
+pub struct Restager {
+    index: Index,
+    changed: bool,
+    // Kept alive for the index, which refers back to it
+    _repo: Repository,
+}
+
+impl Restager {
+    pub fn open(root: &Path) -> Result<Restager, git2::Error> {
+        let repo = Repository::open(root)?;
+        Ok(Restager {
+            index: repo.index()?,
+            changed: false,
+            _repo: repo,
+        })
+    }
+
+    /// Stages the working copy of `relative`.
+    pub fn stage(&mut self, relative: &Path) -> Result<(), git2::Error> {
+        self.index.add_path(relative)?;
+        self.changed = true;
+        Ok(())
+    }
+
+    /// Writes the index back to disk if anything was staged.
+    pub fn write(&mut self) -> Result<(), git2::Error> {
+        if self.changed {
+            self.index.write()?;
+            self.changed = false;
+        }
+        Ok(())
+    }
+}
+
+/// Selects lines by who last changed them, according to `git blame`.
+pub struct AuthorFilter {
+    pattern: Regex,
+}
+
+impl AuthorFilter {
+    pub fn new(pattern: Regex) -> AuthorFilter {
+        AuthorFilter { pattern }
+    }
+
+    fn matches(&self, name: Option<&str>, email: Option<&str>) -> bool {
+        let author = format!("{} <{}>", name.unwrap_or(""), email.unwrap_or(""));
+        self.pattern.is_match(&author)
+    }
+
+    /// Line numbers in `content`, the working copy of `path`, whose author
+    /// (`Name <email>`) matches the pattern. Lines that are not committed yet
+    /// count as written by the configured git user.
+    pub fn matching_lines(
+        &self,
+        path: &Path,
+        content: &[u8],
+    ) -> Result<HashSet<usize>, git2::Error> {
+        let dir = path.parent().unwrap_or(Path::new("."));
+        let repo = Repository::discover(dir)?;
+        let local = repo.signature().ok();
+        let local_matches = local
+            .as_ref()
+            .is_some_and(|sig| self.matches(sig.name(), sig.email()));
+        let line_count =
+            content.split(|&b| b == b'\n').count() - usize::from(content.ends_with(b"\n"));
+        let all_lines = || (1..=line_count).collect();
+
+        let workdir = repo
+            .workdir()
+            .and_then(|workdir| std::fs::canonicalize(workdir).ok());
+        let Some(relative) = workdir.as_deref().and_then(|w| path.strip_prefix(w).ok()) else {
+            return Ok(HashSet::new());
+        };
+        let mut options = BlameOptions::new();
+        options.use_mailmap(true);
+        let committed = match repo.blame_file(relative, Some(&mut options)) {
+            Ok(blame) => blame,
+            // Untracked files, or no commits yet: every line is uncommitted
+            Err(e) if matches!(e.code(), ErrorCode::NotFound | ErrorCode::UnbornBranch) => {
+                return Ok(if local_matches {
+                    all_lines()
+                } else {
+                    HashSet::new()
+                });
+            }
+            Err(e) => return Err(e),
+        };
+        let blame = committed.blame_buffer(content)?;
+
+        let mut lines = HashSet::new();
+        for hunk in blame.iter() {
+            // Uncommitted hunks have no signature to read
+            let matched = if hunk.final_commit_id().is_zero() {
+                local_matches
+            } else {
+                let signature = hunk.final_signature();
+                self.matches(signature.name(), signature.email())
+            };
+            if matched {
+                let start = hunk.final_start_line();
+                lines.extend(start..start + hunk.lines_in_hunk());
+            }
+        }
+        Ok(lines)
+    }
+}
+
+/// Message used by `--commit` when none is given.
+pub const DEFAULT_COMMIT_MESSAGE: &str = "chore: remove marked comments";
+
+/// Commits cleaned files on top of `HEAD`. Only files that matched `HEAD`
+/// before cleaning are committed, so the commit holds nothing but the cleanup
+/// and other staged changes stay staged.
+pub struct Committer {
+    repo: Repository,
+    /// Files with changes of their own relative to `HEAD`.
+    dirty: HashSet<PathBuf>,
+    files: Vec<PathBuf>,
+}
+
+impl Committer {
+    /// Must be created before any file is cleaned.
+    pub fn open(root: &Path) -> Result<Committer, git2::Error> {
+        let repo = Repository::open(root)?;
+        Ok(Committer {
+            dirty: Selection::Modified.paths(&repo)?,
+            repo,
+            files: Vec::new(),
+        })
+    }
+
+    /// Queues the cleaned file `relative` for the commit. Returns `false` when
+    /// it had other changes, which would otherwise end up in the commit too.
+    pub fn add(&mut self, relative: &Path) -> bool {
+        if self.dirty.contains(relative) {
+            return false;
+        }
+        self.files.push(relative.to_path_buf());
+        true
+    }
+
+    /// Creates the commit, returning its id, or `None` if nothing was queued.
+    pub fn commit(&mut self, message: &str) -> Result<Option<Oid>, git2::Error> {
+        if self.files.is_empty() {
+            return Ok(None);
+        }
+        let head = self.repo.head()?.peel_to_commit()?;
+        let mut index = self.repo.index()?;
+        // Build the tree from HEAD so changes staged by the user are left out
+        let mut tree_index = Index::new()?;
+        tree_index.read_tree(&head.tree()?)?;
+        let workdir = self.repo.workdir().unwrap_or(Path::new("."));
+        for relative in &self.files {
+            let Some(mut entry) = tree_index.get_path(relative, 0) else {
+                continue;
+            };
+            let content = std::fs::read(workdir.join(relative))
+                .map_err(|e| git2::Error::from_str(&e.to_string()))?;
+            entry.id = self.repo.blob(&content)?;
+            entry.file_size = content.len() as u32;
+            tree_index.add(&entry)?;
+            index.add_path(relative)?;
+        }
+        let tree = self.repo.find_tree(tree_index.write_tree_to(&self.repo)?)?;
+        let signature = self.repo.signature()?;
+        let id = self.repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            message,
+            &tree,
+            &[&head],
+        )?;
+        index.write()?;
+        self.files.clear();
+        Ok(Some(id))
+    }
+}
+
+/// Ref pointing at the snapshot taken by `--stash-first`.
+pub const SNAPSHOT_REF: &str = "refs/emoji-remover/snapshot";
+
+/// Records the working tree, untracked files included, as a commit on top of
+/// `HEAD` and points [`SNAPSHOT_REF`] at it. The index and the working tree
+/// are left untouched.
+pub fn snapshot_worktree(repo: &Repository) -> Result<Oid, git2::Error> {
+    let head = repo.head()?.peel_to_commit()?;
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| git2::Error::from_str("cannot snapshot a bare repository"))?;
+    let mut index = Index::new()?;
+    index.read_tree(&head.tree()?)?;
+
+    let mut options = DiffOptions::new();
+    options.include_untracked(true).recurse_untracked_dirs(true);
+    let diff = repo.diff_tree_to_workdir_with_index(Some(&head.tree()?), Some(&mut options))?;
+    for delta in diff.deltas() {
+        let file = delta.new_file();
+        let Some(path) = file.path() else { continue };
+        if delta.status() == Delta::Deleted {
+            index.remove_path(path)?;
+            continue;
+        }
+        let id = repo.blob_path(&workdir.join(path))?;
+        index.add(&IndexEntry {
+            ctime: IndexTime::new(0, 0),
+            mtime: IndexTime::new(0, 0),
+            dev: 0,
+            ino: 0,
+            mode: u32::from(file.mode()),
+            uid: 0,
+            gid: 0,
+            file_size: repo.find_blob(id)?.size() as u32,
+            id,
+            flags: 0,
+            flags_extended: 0,
+            path: path.to_string_lossy().replace('\\', "/").into_bytes(),
+        })?;
+    }
+
+    let tree = repo.find_tree(index.write_tree_to(repo)?)?;
+    let signature = repo
+        .signature()
+        .or_else(|_| Signature::now("emoji-remover", "emoji-remover@localhost"))?;
+    let id = repo.commit(
+        None,
+        &signature,
+        &signature,
+        "emoji-remover: snapshot before cleaning",
+        &tree,
+        &[&head],
+    )?;
+    repo.reference(
+        SNAPSHOT_REF,
+        id,
+        true,
+        "emoji-remover: snapshot before cleaning",
+    )?;
+    Ok(id)
+}
@@ -0,0 +1,171 @@
+//! Installs emoji-remover as a git hook.
+
+use crate::AppError;
+use git2::Repository;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Delimiters of the block this tool manages, so existing hooks can be chained
+/// into and reinstalling replaces the block instead of duplicating it.
+const BEGIN: &str = "# >>> emoji-remover >>>";
+const END: &str = "# <<< emoji-remover <<<";
+
+/// The git hooks emoji-remover can be installed as.
+#[derive(Debug, Clone, Copy)]
+pub enum Hook {
+    /// Checks staged files, or with `fix` cleans and re-stages them.
+    PreCommit { fix: bool },
+    /// Strips markers from the commit message.
+    CommitMsg,
+}
+
+impl Hook {
+    fn file_name(self) -> &'static str {
+        match self {
+            Hook::PreCommit { .. } => "pre-commit",
+            Hook::CommitMsg => "commit-msg",
+        }
+    }
+
+    fn arguments(self) -> &'static str {
+        match self {
+            Hook::PreCommit { fix: true } => "--staged",
+            Hook::PreCommit { fix: false } => "--staged --check",
+            Hook::CommitMsg => "--commit-msg \"$1\"",
+        }
+    }
+}
+
+/// The hooks directory git runs hooks from, honoring `core.hooksPath`.
+fn hooks_dir(repo: &Repository) -> PathBuf {
+    let configured = repo
+        .config()
+        .and_then(|config| config.get_path("core.hooksPath"));
+    match (configured, repo.workdir()) {
+        (Ok(path), Some(workdir)) if path.is_relative() => workdir.join(path),
+        (Ok(path), _) => path,
+        // Linked worktrees share the hooks of the main repository
+        (Err(_), _) => repo.commondir().join("hooks"),
+    }
+}
+
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+/// Returns `script` with the managed block set to `block`, creating a new
+/// script when there is none. A new block goes before the existing body,
+/// right after its `#!` line, so an `exit` or `exec` there cannot skip it.
+fn with_block(script: Option<&str>, block: &str) -> String {
+    let Some(script) = script else {
+        return format!("#!/bin/sh\n{}", block);
+    };
+    if let (Some(start), Some(end)) = (script.find(BEGIN), script.find(END))
+        && start < end
+    {
+        let mut end = end + END.len();
+        if script[end..].starts_with('\n') {
+            end += 1;
+        }
+        return format!("{}{}{}", &script[..start], block, &script[end..]);
+    }
+    let body = match script.strip_prefix("#!") {
+        Some(rest) => rest.find('\n').map_or(script.len(), |idx| idx + 3),
+        None => 0,
+    };
+    let (shebang, rest) = script.split_at(body);
+    let separator = if shebang.is_empty() || shebang.ends_with('\n') {
+        ""
+    } else {
+        "\n"
+    };
+    format!("{}{}{}{}", shebang, separator, block, rest)
+}
+
+#[cfg(unix)]
+fn make_executable(path: &Path) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    let mut permissions = fs::metadata(path)?.permissions();
+    permissions.set_mode(permissions.mode() | 0o755);
+    fs::set_permissions(path, permissions)
+}
+
+#[cfg(not(unix))]
+fn make_executable(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Writes `hook` into the hooks directory of `repo`, or updates the block in an
+/// existing script. Returns the hook's path.
+pub fn install(repo: &Repository, hook: Hook) -> Result<PathBuf, AppError> {
+    let dir = hooks_dir(repo);
+    let path = dir.join(hook.file_name());
+    let existing = match fs::read_to_string(&path) {
+        Ok(text) => Some(text),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(AppError::FileRead(path, e)),
+    };
+
+    let exe = std::env::current_exe().map_err(AppError::CurrentExe)?;
+    let block = format!(
+        "{}\n{} {} || exit $?\n{}\n",
+        BEGIN,
+        shell_quote(&exe.to_string_lossy()),
+        hook.arguments(),
+        END
+    );
+
+    fs::create_dir_all(&dir).map_err(|e| AppError::FileWrite(dir.clone(), e))?;
+    fs::write(&path, with_block(existing.as_deref(), &block))
+        .map_err(|e| AppError::FileWrite(path.clone(), e))?;
+    make_executable(&path).map_err(|e| AppError::FileWrite(path.clone(), e))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLOCK: &str =
+        "# >>> emoji-remover >>>\nemoji-remover --staged || exit $?\n# <<< emoji-remover <<<\n";
+
+    #[test]
+    fn creates_script() {
+        assert_eq!(with_block(None, BLOCK), format!("#!/bin/sh\n{}", BLOCK));
+    }
+
+    #[test]
+    fn runs_before_existing_body() {
+        let script = "#!/bin/sh\nexec lint-staged\n";
+        assert_eq!(
+            with_block(Some(script), BLOCK),
+            format!("#!/bin/sh\n{}exec lint-staged\n", BLOCK)
+        );
+    }
+
+    #[test]
+    fn script_without_shebang() {
+        assert_eq!(
+            with_block(Some("make check\nexit 0\n"), BLOCK),
+            format!("{}make check\nexit 0\n", BLOCK)
+        );
+    }
+
+    #[test]
+    fn shebang_only() {
+        assert_eq!(
+            with_block(Some("#!/bin/sh"), BLOCK),
+            format!("#!/bin/sh\n{}", BLOCK)
+        );
+    }
+
+    #[test]
+    fn replaces_block_in_place() {
+        let old = "#!/bin/sh\n# >>> emoji-remover >>>\nold\n# <<< emoji-remover <<<\nexit 0\n";
+        assert_eq!(
+            with_block(Some(old), BLOCK),
+            format!("#!/bin/sh\n{}exit 0\n", BLOCK)
+        );
+    }
+}
@@ -0,0 +1,145 @@
+//! Records the removals of the last run that wrote files, so
+//! `emoji-remover undo` can put the comments back.
+//!
+//! The journal lives at `<git dir>/emoji-remover/journal` and holds one
+//! tab-separated record per edit: path, line number, original lines and
+//! cleaned lines, with tabs, newlines and backslashes escaped. A block
+//! comment spanning lines is one edit, and so are removals sharing a line.
+
+use crate::AppError;
+use emoji_remover::{LineChange, line_edits};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+pub fn location(git_dir: &Path) -> PathBuf {
+    git_dir.join("emoji-remover").join("journal")
+}
+
+fn escape(field: &str) -> String {
+    let mut out = String::with_capacity(field.len());
+    for c in field.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn unescape(field: &str) -> String {
+    let mut out = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+/// The journal of the current run. The previous run's journal is only
+/// replaced once this run writes its first file.
+pub struct Journal {
+    path: PathBuf,
+    file: Option<File>,
+}
+
+impl Journal {
+    pub fn new(git_dir: &Path) -> Journal {
+        Journal {
+            path: location(git_dir),
+            file: None,
+        }
+    }
+
+    /// Starts the journal of a new run, which replaces the current one once
+    /// it records its first file.
+    pub fn restart(&mut self) {
+        self.file = None;
+    }
+
+    /// Appends the removals just written to `label`.
+    pub fn record(&mut self, label: &str, changes: &[LineChange]) -> Result<(), AppError> {
+        let path = &self.path;
+        let write_error = |e: io::Error| AppError::FileWrite(path.clone(), e);
+        if self.file.is_none() {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir).map_err(write_error)?;
+            }
+            let file = OpenOptions::new()
+                .create(true)
+                .write(true)
+                .truncate(true)
+                .open(path)
+                .map_err(write_error)?;
+            self.file = Some(file);
+        }
+        let file = self.file.as_mut().expect("journal file was just opened");
+        let mut records = String::new();
+        for edit in line_edits(changes) {
+            records.push_str(&format!(
+                "{}\t{}\t{}\t{}\n",
+                escape(label),
+                edit.line_number,
+                escape(&edit.original),
+                escape(&edit.cleaned)
+            ));
+        }
+        file.write_all(records.as_bytes()).map_err(write_error)
+    }
+}
+
+/// One removal read back from the journal.
+pub struct Entry {
+    pub path: String,
+    pub line_number: usize,
+    pub original: String,
+    pub cleaned: String,
+}
+
+/// Reads the last run's journal, returning `None` when there is none.
+pub fn read(git_dir: &Path) -> Result<Option<Vec<Entry>>, AppError> {
+    let path = location(git_dir);
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(AppError::FileRead(path, e)),
+    };
+    let entries = text
+        .lines()
+        .filter_map(|record| {
+            let mut fields = record.split('\t');
+            let (path, line_number, original, cleaned) = (
+                fields.next()?,
+                fields.next()?,
+                fields.next()?,
+                fields.next()?,
+            );
+            Some(Entry {
+                path: unescape(path),
+                line_number: line_number.parse().ok()?,
+                original: unescape(original),
+                cleaned: unescape(cleaned),
+            })
+        })
+        .collect();
+    Ok(Some(entries))
+}
+
+/// Deletes the journal once its removals have been restored.
+pub fn remove(git_dir: &Path) -> Result<(), AppError> {
+    let path = location(git_dir);
+    fs::remove_file(&path).map_err(|e| AppError::FileWrite(path, e))
+}
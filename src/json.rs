@@ -0,0 +1,372 @@
+//! A JSON document model with a parser and a serializer, for the messages
+//! of `--lsp`. Reports are written directly with `report::json_string`.
+
+use crate::report::json_string;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    /// Members in the order they were given.
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// The member `key` of an object, or `Null` when there is none.
+    pub fn get(&self, key: &str) -> &Value {
+        match self {
+            Value::Object(members) => members
+                .iter()
+                .find(|(name, _)| name == key)
+                .map_or(&Value::Null, |(_, value)| value),
+            _ => &Value::Null,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_usize(&self) -> Option<usize> {
+        match self {
+            Value::Number(n) if *n >= 0.0 && n.fract() == 0.0 => Some(*n as usize),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> &[Value] {
+        match self {
+            Value::Array(items) => items,
+            _ => &[],
+        }
+    }
+}
+
+/// Builds an object from `members`.
+pub fn object<const N: usize>(members: [(&str, Value); N]) -> Value {
+    Value::Object(
+        members
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect(),
+    )
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Value {
+        Value::String(s.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Value {
+        Value::String(s)
+    }
+}
+
+impl From<usize> for Value {
+    fn from(n: usize) -> Value {
+        Value::Number(n as f64)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Value {
+        Value::Bool(b)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Null => f.write_str("null"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Number(n) if n.fract() == 0.0 && n.abs() < 1e15 => write!(f, "{}", *n as i64),
+            Value::Number(n) => write!(f, "{}", n),
+            Value::String(s) => f.write_str(&json_string(s)),
+            Value::Array(items) => {
+                f.write_str("[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                f.write_str("]")
+            }
+            Value::Object(members) => {
+                f.write_str("{")?;
+                for (i, (name, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{}:{}", json_string(name), value)?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+/// Parses `text` as a single JSON value.
+pub fn parse(text: &str) -> Result<Value, String> {
+    let mut parser = Parser { text, pos: 0 };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.pos < text.len() {
+        return Err(format!("Trailing characters at byte {}", parser.pos));
+    }
+    Ok(value)
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn rest(&self) -> &str {
+        &self.text[self.pos..]
+    }
+
+    fn next_char(&mut self) -> Option<char> {
+        let c = self.rest().chars().next()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start_matches([' ', '\t', '\n', '\r']).len();
+    }
+
+    fn error(&self, expected: &str) -> String {
+        format!("Expected {} at byte {}", expected, self.pos)
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        if self.rest().starts_with(token) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+        match self.rest().chars().next() {
+            Some('{') => self.object(),
+            Some('[') => self.array(),
+            Some('"') => Ok(Value::String(self.string()?)),
+            Some('-' | '0'..='9') => self.number(),
+            _ if self.eat("true") => Ok(Value::Bool(true)),
+            _ if self.eat("false") => Ok(Value::Bool(false)),
+            _ if self.eat("null") => Ok(Value::Null),
+            _ => Err(self.error("a value")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, String> {
+        self.pos += 1;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.eat("}") {
+            return Ok(Value::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            if !self.rest().starts_with('"') {
+                return Err(self.error("a member name"));
+            }
+            let name = self.string()?;
+            self.skip_whitespace();
+            if !self.eat(":") {
+                return Err(self.error("':'"));
+            }
+            members.push((name, self.value()?));
+            self.skip_whitespace();
+            if self.eat("}") {
+                return Ok(Value::Object(members));
+            }
+            if !self.eat(",") {
+                return Err(self.error("',' or '}'"));
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.pos += 1;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.eat("]") {
+            return Ok(Value::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_whitespace();
+            if self.eat("]") {
+                return Ok(Value::Array(items));
+            }
+            if !self.eat(",") {
+                return Err(self.error("',' or ']'"));
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| !matches!(c, '-' | '+' | '.' | 'e' | 'E' | '0'..='9'))
+            .unwrap_or(rest.len());
+        let number = rest[..len].parse().map_err(|_| self.error("a number"))?;
+        self.pos += len;
+        Ok(Value::Number(number))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.pos += 1;
+        let mut out = String::new();
+        loop {
+            let c = self.next_char().ok_or_else(|| self.error("'\"'"))?;
+            match c {
+                '"' => return Ok(out),
+                '\\' => {
+                    let escaped = self.next_char().ok_or_else(|| self.error("an escape"))?;
+                    match escaped {
+                        '"' | '\\' | '/' => out.push(escaped),
+                        'b' => out.push('\u{8}'),
+                        'f' => out.push('\u{c}'),
+                        'n' => out.push('\n'),
+                        'r' => out.push('\r'),
+                        't' => out.push('\t'),
+                        'u' => out.push(self.unicode_escape()?),
+                        _ => return Err(self.error("an escape")),
+                    }
+                }
+                c => out.push(c),
+            }
+        }
+    }
+
+    /// Reads the digits of a `\u` escape, combining surrogate pairs.
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.hex4()?;
+        let start = self.pos;
+        if (0xD800..0xDC00).contains(&high) && self.eat("\\u") {
+            let low = self.hex4()?;
+            if (0xDC00..0xE000).contains(&low) {
+                let code = 0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00);
+                return Ok(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+            }
+            // Not a low surrogate: leave the escape to be read on its own.
+            self.pos = start;
+        }
+        Ok(char::from_u32(high).unwrap_or(char::REPLACEMENT_CHARACTER))
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits = self
+            .rest()
+            .get(..4)
+            .ok_or_else(|| self.error("4 hex digits"))?;
+        if !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(self.error("4 hex digits"));
+        }
+        let code = u32::from_str_radix(digits, 16).map_err(|_| self.error("4 hex digits"))?;
+        self.pos += 4;
+        Ok(code)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_nested_values() {
+        let value = parse(r#" {"a": [1, -2.5e1, true, null], "b": {"c": "d"}, "e": []} "#).unwrap();
+        assert_eq!(
+            value.get("a").as_array(),
+            [
+                Value::Number(1.0),
+                Value::Number(-25.0),
+                Value::Bool(true),
+                Value::Null,
+            ]
+        );
+        assert_eq!(value.get("b").get("c").as_str(), Some("d"));
+        assert_eq!(value.get("e").as_array(), []);
+        assert_eq!(value.get("missing"), &Value::Null);
+        assert_eq!(value.get("a").get("x"), &Value::Null);
+    }
+
+    #[test]
+    fn string_escapes() {
+        let value = parse(r#""q\"\\\/\b\f\n\r\t \u00e9 \ud83d\ude00 é""#).unwrap();
+        assert_eq!(value.as_str(), Some("q\"\\/\u{8}\u{c}\n\r\t é 😀 é"));
+    }
+
+    #[test]
+    fn lone_surrogates() {
+        let value = parse(r#""\ud83d\u0041 \ude00""#).unwrap();
+        assert_eq!(value.as_str(), Some("\u{FFFD}A \u{FFFD}"));
+    }
+
+    #[test]
+    fn usize_values() {
+        assert_eq!(Value::Number(3.0).as_usize(), Some(3));
+        assert_eq!(Value::Number(-1.0).as_usize(), None);
+        assert_eq!(Value::Number(1.5).as_usize(), None);
+        assert_eq!(Value::from("3").as_usize(), None);
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(parse("[1,]").unwrap_err(), "Expected a value at byte 3");
+        assert_eq!(
+            parse("{1:2}").unwrap_err(),
+            "Expected a member name at byte 1"
+        );
+        assert_eq!(parse(r#"{"a" 1}"#).unwrap_err(), "Expected ':' at byte 5");
+        assert_eq!(parse("[1 2]").unwrap_err(), "Expected ',' or ']' at byte 3");
+        assert_eq!(parse(r#""open"#).unwrap_err(), "Expected '\"' at byte 5");
+        assert_eq!(
+            parse(r#""\x""#).unwrap_err(),
+            "Expected an escape at byte 3"
+        );
+        assert_eq!(
+            parse(r#""\u+041""#).unwrap_err(),
+            "Expected 4 hex digits at byte 3"
+        );
+        assert_eq!(
+            parse(r#""\u00""#).unwrap_err(),
+            "Expected 4 hex digits at byte 3"
+        );
+        assert_eq!(parse("-").unwrap_err(), "Expected a number at byte 0");
+        assert_eq!(parse("1 2").unwrap_err(), "Trailing characters at byte 2");
+    }
+
+    #[test]
+    fn round_trips() {
+        let value = object([
+            ("id", Value::from(7usize)),
+            ("ratio", Value::Number(0.5)),
+            ("text", Value::from("a\"b\n")),
+            ("list", Value::Array(vec![Value::Null, Value::from(false)])),
+        ]);
+        let text = value.to_string();
+        assert_eq!(
+            text,
+            r#"{"id":7,"ratio":0.5,"text":"a\"b\n","list":[null,false]}"#
+        );
+        assert_eq!(parse(&text).unwrap(), value);
+    }
+}
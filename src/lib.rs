@@ -0,0 +1,56 @@
+//! Removal of comments marked with an emoji or another marker from source
+//! code, as done by the `emoji-remover` command.
+//!
+//! ```
+//! use emoji_remover::{CleanOptions, clean_source};
+//!
+//! let result = clean_source("let x = 1; // ‼️ debug\n", "rs", &CleanOptions::default());
+//! assert_eq!(result.output, "let x = 1;\n");
+//! assert_eq!(result.changes.len(), 1);
+//! ```
+//!
+//! To clean files, or to change the markers, build a `Cleaner`:
+//!
+//! ```no_run
+//! use emoji_remover::Cleaner;
+//! use std::path::Path;
+//!
+//! let cleaner = Cleaner::builder()
+//!     .marker("‼️")
+//!     .include_glob("*.rs")
+//!     .dry_run(true)
+//!     .build()?;
+//! for (path, result) in cleaner.clean_dir(Path::new("src"))? {
+//!     println!("{}: {} comments", path.display(), result.changes.len());
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+mod atomic;
+mod background;
+mod clean;
+mod cleaner;
+mod emoji;
+pub mod ffi;
+mod filetype;
+pub mod markers;
+#[cfg(feature = "napi")]
+mod napi;
+pub mod regex;
+mod syntax;
+
+pub use atomic::{write_atomic, write_atomic_with};
+#[cfg(feature = "walk")]
+pub use background::Removals;
+pub use background::Task;
+pub use clean::{
+    CleanOptions, CleanResult, CommentKind, CommentScanner, LineChange, LineEdit, Removal,
+    UTF8_BOM, apply_changes, clean_reader, clean_source, cleaned_pieces, find_changes,
+    find_marked_comment, line_edits, split_bom, split_ending, split_lines,
+};
+pub use cleaner::{BuildError, Cleaner, CleanerBuilder, Decision};
+pub use filetype::{
+    is_ambiguous_extension, language_for_file, language_for_filetype, language_for_modeline,
+    language_for_shebang,
+};
+pub use markers::Markers;
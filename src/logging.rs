@@ -0,0 +1,43 @@
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+/// Writes log records to stderr. Informational messages are printed as-is so the
+/// default output stays unchanged; debug and trace records carry a level prefix.
+struct StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        crate::progress::clear();
+        match record.level() {
+            Level::Error | Level::Warn | Level::Info => eprintln!("{}", record.args()),
+            Level::Debug => eprintln!("[debug] {}", record.args()),
+            Level::Trace => eprintln!("[trace] {}", record.args()),
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+/// Maps `-q` and repeated `-v` flags to a level filter and installs the logger.
+pub fn init(verbose: u8, quiet: bool) {
+    let level = if quiet {
+        LevelFilter::Error
+    } else {
+        match verbose {
+            0 => LevelFilter::Info,
+            1 => LevelFilter::Debug,
+            _ => LevelFilter::Trace,
+        }
+    };
+    // Only fails if a logger is already installed, which would be a bug in main
+    log::set_logger(&LOGGER).expect("logger initialized twice");
+    log::set_max_level(level);
+}
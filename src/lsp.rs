@@ -0,0 +1,630 @@
+//! `--lsp`: a language server over stdin and stdout, so any editor speaking
+//! the Language Server Protocol can show and remove marked comments.
+//!
+//! Open documents get a warning diagnostic on every marked comment. Code
+//! actions remove the comment under the cursor or every one in the file, and
+//! the `emoji-remover.cleanWorkspace` command removes them from every file
+//! the command line would process, through a `workspace/applyEdit` request.
+//! Documents are synced whole, and positions count UTF-16 code units, a byte
+//! order mark included.
+
+use crate::AppError;
+use crate::config::ConfigResolver;
+use crate::json::{self, Value, object};
+use emoji_remover::markers::Markers;
+use emoji_remover::{
+    LineChange, LineEdit, find_changes, language_for_filetype, line_edits, split_bom, split_lines,
+};
+use log::{debug, error};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// The command that cleans every file in the workspace.
+const CLEAN_WORKSPACE: &str = "emoji-remover.cleanWorkspace";
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_PARAMS: i64 = -32602;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INTERNAL_ERROR: i64 = -32603;
+
+/// `DiagnosticSeverity.Warning`.
+const WARNING: usize = 2;
+/// `TextDocumentSyncKind.Full`.
+const FULL_SYNC: usize = 1;
+
+struct Document {
+    text: String,
+    language_id: String,
+}
+
+/// The marked comments of a document, and what it takes to place them in the
+/// text the client has.
+struct Marked {
+    changes: Vec<LineChange>,
+    /// UTF-16 code units of the byte order mark, which the client counts as
+    /// part of the first line.
+    bom: usize,
+    /// Whether each line ends with `\r\n`.
+    crlf: Vec<bool>,
+}
+
+struct Server<'a, F> {
+    resolver: &'a mut ConfigResolver,
+    list_files: F,
+    documents: HashMap<String, Document>,
+    output: io::StdoutLock<'static>,
+    next_request: usize,
+}
+
+/// Answers requests until the client sends `exit` or closes stdin.
+/// `list_files` lists the files `emoji-remover.cleanWorkspace` cleans.
+pub fn serve(
+    resolver: &mut ConfigResolver,
+    list_files: impl FnMut(&mut ConfigResolver) -> Result<Vec<PathBuf>, AppError>,
+) -> io::Result<()> {
+    let mut input = BufReader::new(io::stdin().lock());
+    let mut server = Server {
+        resolver,
+        list_files,
+        documents: HashMap::new(),
+        output: io::stdout().lock(),
+        next_request: 1,
+    };
+    while let Some(body) = read_message(&mut input)? {
+        let message = match json::parse(&body) {
+            Ok(message) => message,
+            Err(err) => {
+                server.respond(&Value::Null, Err((PARSE_ERROR, err)))?;
+                continue;
+            }
+        };
+        let id = message.get("id");
+        let params = message.get("params");
+        match message.get("method").as_str() {
+            Some("exit") => break,
+            Some(method) if *id != Value::Null => {
+                let result = server.request(method, params);
+                server.respond(id, result)?;
+            }
+            Some(method) => server.notification(method, params)?,
+            None => debug!("Ignoring LSP response to request {}", id),
+        }
+    }
+    Ok(())
+}
+
+/// Reads the body of one message, or `None` once `input` ends.
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.eq_ignore_ascii_case("Content-Length")
+        {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+    let length = length.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "LSP message without Content-Length",
+        )
+    })?;
+    let mut body = Vec::new();
+    input.take(length as u64).read_to_end(&mut body)?;
+    if body.len() < length {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "LSP message is not UTF-8"))
+}
+
+impl<F> Server<'_, F>
+where
+    F: FnMut(&mut ConfigResolver) -> Result<Vec<PathBuf>, AppError>,
+{
+    fn send(&mut self, message: Value) -> io::Result<()> {
+        let body = message.to_string();
+        write!(
+            self.output,
+            "Content-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )?;
+        self.output.flush()
+    }
+
+    fn respond(&mut self, id: &Value, result: Result<Value, (i64, String)>) -> io::Result<()> {
+        let outcome = match result {
+            Ok(result) => ("result", result),
+            Err((code, message)) => (
+                "error",
+                object([
+                    ("code", Value::Number(code as f64)),
+                    ("message", message.into()),
+                ]),
+            ),
+        };
+        self.send(object([
+            ("jsonrpc", "2.0".into()),
+            ("id", id.clone()),
+            outcome,
+        ]))
+    }
+
+    fn notify(&mut self, method: &str, params: Value) -> io::Result<()> {
+        self.send(object([
+            ("jsonrpc", "2.0".into()),
+            ("method", method.into()),
+            ("params", params),
+        ]))
+    }
+
+    fn request(&mut self, method: &str, params: &Value) -> Result<Value, (i64, String)> {
+        match method {
+            "initialize" => Ok(object([
+                (
+                    "capabilities",
+                    object([
+                        ("textDocumentSync", FULL_SYNC.into()),
+                        ("codeActionProvider", true.into()),
+                        (
+                            "executeCommandProvider",
+                            object([("commands", Value::Array(vec![CLEAN_WORKSPACE.into()]))]),
+                        ),
+                    ]),
+                ),
+                (
+                    "serverInfo",
+                    object([
+                        ("name", env!("CARGO_PKG_NAME").into()),
+                        ("version", env!("CARGO_PKG_VERSION").into()),
+                    ]),
+                ),
+            ])),
+            "shutdown" => Ok(Value::Null),
+            "textDocument/codeAction" => self.code_actions(params),
+            "workspace/executeCommand" => match params.get("command").as_str() {
+                Some(CLEAN_WORKSPACE) => self.clean_workspace(),
+                Some(command) => Err((INVALID_PARAMS, format!("Unknown command: {}", command))),
+                None => Err((INVALID_PARAMS, "Missing command".to_string())),
+            },
+            _ => Err((METHOD_NOT_FOUND, format!("Unsupported method: {}", method))),
+        }
+    }
+
+    fn notification(&mut self, method: &str, params: &Value) -> io::Result<()> {
+        let uri = params.get("textDocument").get("uri").as_str();
+        match (method, uri) {
+            ("textDocument/didOpen", Some(uri)) => {
+                let document = params.get("textDocument");
+                self.documents.insert(
+                    uri.to_string(),
+                    Document {
+                        text: document.get("text").as_str().unwrap_or("").to_string(),
+                        language_id: document
+                            .get("languageId")
+                            .as_str()
+                            .unwrap_or("")
+                            .to_string(),
+                    },
+                );
+                self.publish(uri)
+            }
+            ("textDocument/didChange", Some(uri)) => {
+                let text = params.get("contentChanges").as_array().last();
+                if let (Some(document), Some(text)) = (
+                    self.documents.get_mut(uri),
+                    text.and_then(|change| change.get("text").as_str()),
+                ) {
+                    document.text = text.to_string();
+                }
+                self.publish(uri)
+            }
+            ("textDocument/didClose", Some(uri)) => {
+                self.documents.remove(uri);
+                self.notify(
+                    "textDocument/publishDiagnostics",
+                    object([
+                        ("uri", uri.into()),
+                        ("diagnostics", Value::Array(Vec::new())),
+                    ]),
+                )
+            }
+            _ => {
+                debug!("Ignoring LSP notification {}", method);
+                Ok(())
+            }
+        }
+    }
+
+    /// The marked comments in `text`, the contents of `path`. `language_id`
+    /// picks the comment syntax when the path has no extension.
+    fn changes(&mut self, path: &Path, language_id: &str, text: &str) -> Result<Marked, AppError> {
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let settings = self.resolver.settings_for(dir)?;
+        let ext = match path.extension().and_then(|s| s.to_str()) {
+            Some(ext) => ext,
+            None => language_for_filetype(language_id),
+        };
+        Ok(Marked::new(
+            text,
+            settings.syntax_for(ext),
+            &settings.markers,
+        ))
+    }
+
+    fn document_changes(&mut self, uri: &str) -> Result<Marked, AppError> {
+        let Some(document) = self.documents.get(uri) else {
+            return Ok(Marked::new("", "", &Markers::default()));
+        };
+        let (text, language_id) = (document.text.clone(), document.language_id.clone());
+        self.changes(&uri_to_path(uri), &language_id, &text)
+    }
+
+    fn publish(&mut self, uri: &str) -> io::Result<()> {
+        let diagnostics = match self.document_changes(uri) {
+            Ok(marked) => marked
+                .changes
+                .iter()
+                .map(|change| marked.diagnostic(change))
+                .collect(),
+            Err(err) => {
+                error!("Error checking {}: {}", uri, err);
+                Vec::new()
+            }
+        };
+        self.notify(
+            "textDocument/publishDiagnostics",
+            object([
+                ("uri", uri.into()),
+                ("diagnostics", Value::Array(diagnostics)),
+            ]),
+        )
+    }
+
+    fn code_actions(&mut self, params: &Value) -> Result<Value, (i64, String)> {
+        let uri = params
+            .get("textDocument")
+            .get("uri")
+            .as_str()
+            .ok_or((INVALID_PARAMS, "Missing textDocument.uri".to_string()))?;
+        let range = params.get("range");
+        let first = range.get("start").get("line").as_usize().unwrap_or(0);
+        let last = range.get("end").get("line").as_usize().unwrap_or(first);
+        let marked = self
+            .document_changes(uri)
+            .map_err(|err| (INTERNAL_ERROR, err.to_string()))?;
+
+        let mut actions: Vec<Value> = marked
+            .changes
+            .iter()
+            .filter(|change| change.line_number <= last + 1 && change.end_line() > first)
+            .map(|change| {
+                object([
+                    ("title", "Remove marked comment".into()),
+                    ("kind", "quickfix".into()),
+                    ("diagnostics", Value::Array(vec![marked.diagnostic(change)])),
+                    (
+                        "edit",
+                        workspace_edit(vec![(
+                            uri.to_string(),
+                            vec![marked.text_edit(&LineEdit::from(change))],
+                        )]),
+                    ),
+                ])
+            })
+            .collect();
+        if !marked.changes.is_empty() {
+            actions.push(object([
+                ("title", "Remove all marked comments in file".into()),
+                ("kind", "source.fixAll".into()),
+                (
+                    "edit",
+                    workspace_edit(vec![(uri.to_string(), marked.text_edits())]),
+                ),
+            ]));
+        }
+        Ok(Value::Array(actions))
+    }
+
+    /// Asks the client to remove every marked comment in the workspace,
+    /// taking open documents as the editor has them rather than from disk.
+    fn clean_workspace(&mut self) -> Result<Value, (i64, String)> {
+        let files =
+            (self.list_files)(self.resolver).map_err(|err| (INTERNAL_ERROR, err.to_string()))?;
+        let open: HashMap<PathBuf, String> = self
+            .documents
+            .keys()
+            .map(|uri| (uri_to_path(uri), uri.clone()))
+            .collect();
+        let mut edits = Vec::new();
+        for path in files {
+            let (uri, marked) = match open.get(&path) {
+                Some(uri) => (uri.clone(), self.document_changes(uri)),
+                None => {
+                    let text = match fs::read_to_string(&path) {
+                        Ok(text) => text,
+                        Err(err) => {
+                            debug!("Skipping {}: {}", path.display(), err);
+                            continue;
+                        }
+                    };
+                    (path_to_uri(&path), self.changes(&path, "", &text))
+                }
+            };
+            let marked = marked.map_err(|err| (INTERNAL_ERROR, err.to_string()))?;
+            if !marked.changes.is_empty() {
+                edits.push((uri, marked.text_edits()));
+            }
+        }
+        if !edits.is_empty() {
+            let id = self.next_request;
+            self.next_request += 1;
+            self.send(object([
+                ("jsonrpc", "2.0".into()),
+                ("id", id.into()),
+                ("method", "workspace/applyEdit".into()),
+                (
+                    "params",
+                    object([
+                        ("label", "Remove marked comments".into()),
+                        ("edit", workspace_edit(edits)),
+                    ]),
+                ),
+            ]))
+            .map_err(|err| (INTERNAL_ERROR, err.to_string()))?;
+        }
+        Ok(Value::Null)
+    }
+}
+
+impl Marked {
+    /// The marked comments in `text`, written in `ext`.
+    fn new(text: &str, ext: &str, markers: &Markers) -> Marked {
+        let (bom, text) = split_bom(text);
+        Marked {
+            changes: find_changes(text, ext, markers),
+            bom: bom.encode_utf16().count(),
+            crlf: split_lines(text)
+                .map(|(_, ending)| ending == "\r\n")
+                .collect(),
+        }
+    }
+
+    /// An LSP position, in UTF-16 code units, `byte` bytes into `lines`,
+    /// which start on line `line_number`.
+    fn position(&self, line_number: usize, lines: &str, byte: usize) -> Value {
+        let before = &lines[..byte];
+        let line_start = before.rfind('\n').map_or(0, |idx| idx + 1);
+        let line = line_number - 1 + before.matches('\n').count();
+        let bom = if line == 0 { self.bom } else { 0 };
+        object([
+            ("line", line.into()),
+            (
+                "character",
+                (bom + before[line_start..].encode_utf16().count()).into(),
+            ),
+        ])
+    }
+
+    fn range(&self, change: &LineChange, start: usize, end: usize) -> Value {
+        object([
+            (
+                "start",
+                self.position(change.line_number, &change.original, start),
+            ),
+            (
+                "end",
+                self.position(change.line_number, &change.original, end),
+            ),
+        ])
+    }
+
+    fn diagnostic(&self, change: &LineChange) -> Value {
+        let span = change.span();
+        object([
+            ("range", self.range(change, span.start, span.end)),
+            ("severity", WARNING.into()),
+            ("code", change.kind.rule_id().into()),
+            ("source", env!("CARGO_PKG_NAME").into()),
+            ("message", change.kind.reason().into()),
+        ])
+    }
+
+    /// An edit replacing `edit`'s whole lines with their cleaned text, each
+    /// keeping its line ending.
+    fn text_edit(&self, edit: &LineEdit) -> Value {
+        let (line_number, original) = (edit.line_number, &*edit.original);
+        let mut new_text = String::with_capacity(edit.cleaned.len());
+        for (idx, line) in edit.cleaned.split('\n').enumerate() {
+            if idx > 0 {
+                let crlf = self.crlf.get(line_number + idx - 2).copied();
+                new_text.push_str(if crlf == Some(true) { "\r\n" } else { "\n" });
+            }
+            new_text.push_str(line);
+        }
+        object([
+            (
+                "range",
+                object([
+                    ("start", self.position(line_number, original, 0)),
+                    ("end", self.position(line_number, original, original.len())),
+                ]),
+            ),
+            ("newText", new_text.into()),
+        ])
+    }
+
+    /// The edits removing every marked comment, none overlapping another.
+    fn text_edits(&self) -> Vec<Value> {
+        line_edits(&self.changes)
+            .iter()
+            .map(|edit| self.text_edit(edit))
+            .collect()
+    }
+}
+
+fn workspace_edit(edits: Vec<(String, Vec<Value>)>) -> Value {
+    let changes = edits
+        .into_iter()
+        .map(|(uri, edits)| (uri, Value::Array(edits)))
+        .collect();
+    object([("changes", Value::Object(changes))])
+}
+
+/// The path a `file:` URI names. Other schemes give paths that match no file.
+fn uri_to_path(uri: &str) -> PathBuf {
+    let path = uri.strip_prefix("file://").unwrap_or(uri);
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut rest = path.as_bytes();
+    while let [first, tail @ ..] = rest {
+        if let [b'%', high, low, ..] = rest
+            && high.is_ascii_hexdigit()
+            && low.is_ascii_hexdigit()
+            && let Ok(byte) = u8::from_str_radix(&format!("{}{}", *high as char, *low as char), 16)
+        {
+            bytes.push(byte);
+            rest = &rest[3..];
+        } else {
+            bytes.push(*first);
+            rest = tail;
+        }
+    }
+    let path = String::from_utf8_lossy(&bytes).into_owned();
+    // Windows drive paths come as `file:///C:/...`
+    match path.as_bytes() {
+        [b'/', drive, b':', ..] if cfg!(windows) && drive.is_ascii_alphabetic() => {
+            PathBuf::from(&path[1..])
+        }
+        _ => PathBuf::from(path),
+    }
+}
+
+fn path_to_uri(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let mut uri = String::from(if path.starts_with('/') {
+        "file://"
+    } else {
+        "file:///"
+    });
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                uri.push(byte as char)
+            }
+            b':' if cfg!(windows) => uri.push(':'),
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use emoji_remover::Markers;
+
+    fn messages(input: &str) -> Vec<io::Result<Option<String>>> {
+        let mut input = input.as_bytes();
+        let mut out = Vec::new();
+        loop {
+            let message = read_message(&mut input);
+            let done = !matches!(message, Ok(Some(_)));
+            out.push(message);
+            if done {
+                return out;
+            }
+        }
+    }
+
+    #[test]
+    fn reads_framed_messages() {
+        let input = "Content-Length: 2\r\n\r\n{}\
+                     content-length:  4\r\nContent-Type: application/vscode-jsonrpc\r\n\r\n\"é\"";
+        let mut read = messages(input).into_iter().map(Result::unwrap);
+        assert_eq!(read.next(), Some(Some("{}".to_string())));
+        assert_eq!(read.next(), Some(Some("\"é\"".to_string())));
+        assert_eq!(read.next(), Some(None));
+    }
+
+    #[test]
+    fn rejects_bad_frames() {
+        let err = read_message(&mut "Content-Type: x\r\n\r\n{}".as_bytes()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let err = read_message(&mut "Content-Length: 10\r\n\r\n{}".as_bytes()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        let err = read_message(&mut &b"Content-Length: 1\r\n\r\n\xff"[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn positions_count_utf16_units() {
+        let marked = Marked::new(
+            "let s = \"😀\"; // ‼️ x\n/* a\n😀 ‼️ */\n",
+            "rs",
+            &Markers::default(),
+        );
+        let changes = &marked.changes;
+        assert_eq!(changes.len(), 2);
+        let span = changes[0].span();
+        assert_eq!(
+            marked.range(&changes[0], span.start, span.end).to_string(),
+            r#"{"start":{"line":0,"character":14},"end":{"line":0,"character":21}}"#
+        );
+        let span = changes[1].span();
+        assert_eq!(
+            marked.range(&changes[1], span.start, span.end).to_string(),
+            r#"{"start":{"line":1,"character":0},"end":{"line":2,"character":8}}"#
+        );
+    }
+
+    #[test]
+    fn positions_count_the_bom_on_the_first_line() {
+        let marked = Marked::new(
+            "\u{feff}a(); // ‼️ x\nb(); // ‼️ y\n",
+            "rs",
+            &Markers::default(),
+        );
+        let start = |change: &LineChange| marked.range(change, change.span().start, 0);
+        assert_eq!(
+            start(&marked.changes[0]).get("start").to_string(),
+            r#"{"line":0,"character":6}"#
+        );
+        assert_eq!(
+            start(&marked.changes[1]).get("start").to_string(),
+            r#"{"line":1,"character":5}"#
+        );
+    }
+
+    #[test]
+    fn edits_keep_line_endings() {
+        let marked = Marked::new("a(); /* ‼️\r\nx\r\n*/ b();\r\n", "rs", &Markers::default());
+        let edits = marked.text_edits();
+        assert_eq!(edits.len(), 1);
+        assert_eq!(edits[0].get("newText").as_str(), Some("a();\r\n\r\n b();"));
+        assert_eq!(
+            edits[0].get("range").to_string(),
+            r#"{"start":{"line":0,"character":0},"end":{"line":2,"character":7}}"#
+        );
+    }
+
+    #[test]
+    fn uris() {
+        let path = Path::new("/tmp/a b/ü%.rs");
+        let uri = path_to_uri(path);
+        assert_eq!(uri, "file:///tmp/a%20b/%C3%BC%25.rs");
+        assert_eq!(uri_to_path(&uri), path);
+        assert_eq!(uri_to_path("file:///a%+1b%2"), Path::new("/a%+1b%2"));
+    }
+}
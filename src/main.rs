@@ -1,16 +1,56 @@
-use clap::Parser;
+mod cache;
+mod config;
+#[cfg(unix)]
+mod daemon;
+mod diff;
+mod encoding;
+mod git;
+mod hook;
+mod journal;
+mod json;
+mod logging;
+mod lsp;
+mod msgpack;
+mod progress;
+mod report;
+mod rpc;
+mod timings;
+mod toml;
+mod writer;
+
+use clap::builder::{BoolishValueParser, NonEmptyStringValueParser};
+use clap::error::ErrorKind;
+use clap::{ArgAction, ArgGroup, CommandFactory, Parser, Subcommand, ValueEnum};
+use config::{CONFIG_FILE_NAME, Config, ConfigError, ConfigResolver};
+use emoji_remover::markers::Markers;
+use emoji_remover::regex;
+use emoji_remover::{
+    CommentScanner, LineChange, LineEdit, UTF8_BOM, apply_changes, cleaned_pieces, find_changes,
+    is_ambiguous_extension, language_for_file, language_for_filetype, line_edits, split_bom,
+    split_ending, split_lines, write_atomic,
+};
+use encoding::{Decoder, Encoding};
 use git2::Repository;
-use glob::Pattern;
+use log::{debug, error, info, trace, warn};
+use report::{Event, FileReport, OutputFormat, Summary};
+use std::collections::{BTreeMap, HashSet};
 use std::fs;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{self, BufRead, IsTerminal, Read, Seek, SeekFrom, Write};
+use std::ops::RangeInclusive;
 use std::path::{Path, PathBuf};
 use std::process;
+use std::time::Instant;
 use thiserror::Error;
+use timings::Phase;
 use walkdir::{DirEntry, WalkDir};
 
 #[derive(Debug, Error)]
 enum AppError {
     #[error("Failed to discover git repository: {0}")]
     GitDiscovery(#[from] git2::Error),
+    #[error("Failed to query git: {0}")]
+    Git(#[source] git2::Error),
     #[error("Cannot find toplevel: this is a bare repository")]
     BareRepo,
     #[error("File system walk error: {0}")]
@@ -21,243 +61,2144 @@ enum AppError {
     FileRead(PathBuf, #[source] std::io::Error),
     #[error("Failed to write file {0}: {1}")]
     FileWrite(PathBuf, #[source] std::io::Error),
-    #[error("File content for {0} is not valid UTF-8")]
+    #[error("File content for {0} is not valid UTF-8 (see --fallback-encoding and --lossy-utf8)")]
     InvalidUtf8(PathBuf),
+    #[error("Cleaned content of {0} cannot be written back as {1}")]
+    Unencodable(PathBuf, Encoding),
+    #[error("Cannot access path {0}: {1}")]
+    PathAccess(PathBuf, #[source] std::io::Error),
+    #[error("Path {0} is outside the directory being processed")]
+    OutsideRepo(PathBuf),
+    #[error("Failed to read standard input: {0}")]
+    StdinRead(#[source] std::io::Error),
+    #[error("Failed to write standard output: {0}")]
+    StdoutWrite(#[source] std::io::Error),
+    #[error("--stdin accepts at most one path, used to pick the comment syntax")]
+    StdinPaths,
+    #[error("RPC connection failed: {0}")]
+    Rpc(#[source] std::io::Error),
+    #[error("Language server connection failed: {0}")]
+    Lsp(#[source] std::io::Error),
+    #[error("Daemon socket failed: {0}")]
+    Daemon(#[source] std::io::Error),
+    #[error("Cannot locate the emoji-remover executable: {0}")]
+    CurrentExe(#[source] std::io::Error),
+    #[error(transparent)]
+    InvalidRegex(#[from] regex::RegexError),
+    #[error(transparent)]
+    Config(#[from] ConfigError),
 }
 
 #[derive(Parser, Debug)]
+#[command(after_help = "\
+Options are resolved with the precedence: command line > EMOJI_REMOVER_* environment \
+variables > .emoji-remover.toml files. The config keys include, exclude, marker, \
+marker_regex, all_emojis, include_doc_comments, include_directives and languages can be \
+set with EMOJI_REMOVER_INCLUDE, EMOJI_REMOVER_EXCLUDE, EMOJI_REMOVER_MARKER \
+(comma-separated lists), EMOJI_REMOVER_MARKER_REGEX (a single pattern), \
+EMOJI_REMOVER_ALL_EMOJIS, EMOJI_REMOVER_INCLUDE_DOC_COMMENTS and \
+EMOJI_REMOVER_INCLUDE_DIRECTIVES (true/false) and EMOJI_REMOVER_LANGUAGES \
+(comma-separated ext=syntax pairs). Every other option has a variable named after it, \
+such as EMOJI_REMOVER_STAGED=true, EMOJI_REMOVER_MAX_FILESIZE=10M or \
+EMOJI_REMOVER_LINES=1:20,40:60 (comma-separated ranges), shown with [env: ...] above. \
+The paths and the options that pick what a single invocation does, --stdin, --filetype, \
+--lang, --rpc, --lsp, --daemon, --socket, --files-from, --commit-msg and --commit, are \
+command line only.")]
+#[command(group(ArgGroup::new("selection").args(["staged", "modified", "since"])))]
 struct Cli {
-    /// Glob patterns to include (e.g., "*.rs" "src/**")
-
-    #[arg(long, short = 'i', num_args(1..), default_values_t = ["*.rs".to_string(), "*.toml".to_string(), "*.py".to_string(), "*.jsx".to_string(), "*.tsx".to_string(), "*.html".to_string(), "*.css".to_string(), "*.js".to_string(), "*.ts".to_string()])]
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Files or directories to process (defaults to the whole repository)
+    paths: Vec<PathBuf>,
+    /// Glob patterns to include (e.g., "*.rs" "src/**").
+    /// Defaults to common source extensions. Options given on the command line
+    /// override the same option in .emoji-remover.toml
+    #[arg(long, short = 'i', num_args(1..))]
     include: Vec<String>,
+    /// Marker that flags a comment for removal; repeat to accept several.
+    /// Defaults to ‼️ unless --marker-regex is given
+    #[arg(long, short = 'm', value_parser = NonEmptyStringValueParser::new())]
+    marker: Vec<String>,
+    /// Treat any emoji inside a comment as a marker
+    #[arg(long, overrides_with = "no_all_emojis")]
+    all_emojis: bool,
+    /// Only the markers count, even if all_emojis is set in the environment
+    /// or a config file
+    #[arg(long, overrides_with = "all_emojis")]
+    no_all_emojis: bool,
+    /// Also remove marked doc comments, such as Rust's /// and //! or Python
+    /// docstrings, which are left alone by default as they are part of the
+    /// API documentation
+    #[arg(long)]
+    include_doc_comments: bool,
+    /// Also remove marked directives, comments read by tools such as Go's
+    /// //go:build constraints, which are left alone by default
+    #[arg(long)]
+    include_directives: bool,
+    /// Regular expression matched against comment text; repeat to accept several
+    #[arg(long, value_parser = NonEmptyStringValueParser::new())]
+    marker_regex: Vec<String>,
     /// Glob patterns to exclude (e.g., "target/*" "*.log")
     #[arg(long, short = 'e', num_args(1..))]
     exclude: Vec<String>,
+    /// Print the lines that would be cleaned without writing any files
+    #[arg(long, env = "EMOJI_REMOVER_DRY_RUN", value_parser = BoolishValueParser::new(), conflicts_with_all = ["check", "interactive"])]
+    dry_run: bool,
+    /// Report marked comments without writing and exit non-zero if any are found
+    #[arg(long, env = "EMOJI_REMOVER_CHECK", value_parser = BoolishValueParser::new(), conflicts_with = "interactive")]
+    check: bool,
+    /// Show each marked comment in context and ask before removing it
+    #[arg(long, env = "EMOJI_REMOVER_INTERACTIVE", value_parser = BoolishValueParser::new(), conflicts_with = "stdin")]
+    interactive: bool,
+    /// Print a unified diff of every change to stdout. Files are still written
+    /// unless combined with --dry-run
+    #[arg(long, env = "EMOJI_REMOVER_DIFF", value_parser = BoolishValueParser::new(), conflicts_with = "check")]
+    diff: bool,
+    /// Output format for findings
+    #[arg(long, env = "EMOJI_REMOVER_FORMAT", value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+    /// When to color diff output
+    #[arg(long, env = "EMOJI_REMOVER_COLOR", value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+    /// Print every file examined and why files were skipped (-vv for more detail)
+    #[arg(long, short = 'v', env = "EMOJI_REMOVER_VERBOSE", action = ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+    /// Only print errors
+    #[arg(long, short = 'q', env = "EMOJI_REMOVER_QUIET", value_parser = BoolishValueParser::new())]
+    quiet: bool,
+    /// Read source from stdin and write the cleaned result to stdout.
+    /// A single path argument may be given to select the comment syntax by extension.
+    #[arg(long)]
+    stdin: bool,
+    /// Comment syntax for --stdin, as an editor filetype such as `rust`,
+    /// `python` or `html` (Neovim's `vim.bo.filetype`), for input without a path
+    #[arg(
+        long,
+        value_name = "FILETYPE",
+        requires = "stdin",
+        conflicts_with = "paths"
+    )]
+    filetype: Option<String>,
+    /// Apply the comment syntax of LANG, an extension such as `rs` or a
+    /// filetype such as `rust`, to every file whatever its extension. Files
+    /// the include patterns skip also need --include, e.g.
+    /// `--lang sh --include '*.conf' app.conf`
+    #[arg(long, value_name = "LANG", conflicts_with = "filetype")]
+    lang: Option<String>,
+    /// Serve msgpack-RPC on stdin and stdout for the Neovim plugin, which
+    /// sends buffers to clean with `clean_buffer(lines, filetype)`
+    #[arg(long, conflicts_with_all = ["stdin", "paths"])]
+    rpc: bool,
+    /// Run a language server on stdin and stdout, publishing a diagnostic for
+    /// every marked comment with code actions to remove them
+    #[arg(long, conflicts_with_all = ["stdin", "rpc", "paths"])]
+    lsp: bool,
+    /// Stay resident and clean files on request over a Unix domain socket,
+    /// sparing editors that clean on every save the startup cost
+    #[cfg(unix)]
+    #[arg(long, conflicts_with_all = ["stdin", "rpc", "lsp", "interactive", "selection", "files_from", "commit", "stash_first"])]
+    daemon: bool,
+    /// Socket --daemon listens on, by default `emoji-remover/daemon.sock` in
+    /// the git directory
+    #[cfg(unix)]
+    #[arg(long, value_name = "PATH", requires = "daemon")]
+    socket: Option<PathBuf>,
+    /// Only process files with staged changes, checking their staged content,
+    /// and stage the cleaned files again. Files that also have unstaged edits
+    /// are never cleaned, so the edits stay out of the commit; marked comments
+    /// staged in them fail the run
+    #[arg(long, env = "EMOJI_REMOVER_STAGED", value_parser = BoolishValueParser::new(), conflicts_with = "stdin")]
+    staged: bool,
+    /// Only process files with staged or unstaged changes relative to HEAD,
+    /// and untracked files that are not ignored
+    #[arg(long, env = "EMOJI_REMOVER_MODIFIED", value_parser = BoolishValueParser::new(), conflicts_with = "stdin")]
+    modified: bool,
+    /// Only process files that differ between the revision REV (e.g. origin/main)
+    /// and the working tree, and untracked files that are not ignored
+    #[arg(
+        long,
+        env = "EMOJI_REMOVER_SINCE",
+        value_name = "REV",
+        conflicts_with = "stdin"
+    )]
+    since: Option<String>,
+    /// Only remove marked comments on lines added or changed in the selection
+    /// made by --staged, --modified or --since
+    #[arg(long, env = "EMOJI_REMOVER_CHANGED_LINES_ONLY", value_parser = BoolishValueParser::new(), requires = "selection")]
+    changed_lines_only: bool,
+    /// Process exactly the files listed one per line in FILE ("-" for stdin),
+    /// in order, instead of walking the repository
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["paths", "stdin", "interactive", "selection"])]
+    files_from: Option<PathBuf>,
+    /// Strip markers from the commit message in FILE, for use as a commit-msg
+    /// hook. Lines left empty, or still marked by an emoji or regex marker, are dropped
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["paths", "stdin", "interactive", "diff", "selection", "files_from"])]
+    commit_msg: Option<PathBuf>,
+    /// Work on the current directory without git: nothing is skipped as
+    /// ignored, and the current directory is the root for config files
+    #[arg(long, env = "EMOJI_REMOVER_NO_GIT", value_parser = BoolishValueParser::new(), conflicts_with = "selection")]
+    no_git: bool,
+    /// List candidate files from the git index and status, as `git ls-files
+    /// --cached --others --exclude-standard` would, instead of walking the tree
+    #[arg(long, env = "EMOJI_REMOVER_LS_FILES", value_parser = BoolishValueParser::new(), conflicts_with_all = ["selection", "no_git", "files_from"])]
+    ls_files: bool,
+    /// Also process the files of checked-out submodules, each with its own
+    /// ignore rules. Submodules are skipped by default
+    #[arg(long, env = "EMOJI_REMOVER_RECURSE_SUBMODULES", value_parser = BoolishValueParser::new(), conflicts_with_all = ["no_git", "selection"])]
+    recurse_submodules: bool,
+    /// Only remove marked comments on lines whose last author, as "Name <email>"
+    /// per git blame, matches this regular expression. Uncommitted lines count
+    /// as written by the configured git user. A block comment spanning lines
+    /// is removed only when all of its lines match
+    #[arg(long, env = "EMOJI_REMOVER_AUTHOR", value_name = "PATTERN", conflicts_with_all = ["no_git", "stdin", "commit_msg"])]
+    author: Option<String>,
+    /// Clean files that contain merge conflict markers instead of skipping them
+    #[arg(long, env = "EMOJI_REMOVER_ALLOW_CONFLICTS", value_parser = BoolishValueParser::new())]
+    allow_conflicts: bool,
+    /// Clean files that look minified (`*.min.*` names or very long lines)
+    /// instead of skipping them
+    #[arg(long, env = "EMOJI_REMOVER_INCLUDE_MINIFIED", value_parser = BoolishValueParser::new())]
+    include_minified: bool,
+    /// Clean generated files (marked `@generated`, `DO NOT EDIT` or `Code
+    /// generated by` near the top) instead of skipping them
+    #[arg(long, env = "EMOJI_REMOVER_INCLUDE_GENERATED", value_parser = BoolishValueParser::new())]
+    include_generated: bool,
+    /// Descend into symlinked directories and clean the targets of symlinked
+    /// files, replacing the target rather than the link
+    #[arg(long, env = "EMOJI_REMOVER_FOLLOW_SYMLINKS", value_parser = BoolishValueParser::new(), overrides_with = "no_follow_symlinks")]
+    follow_symlinks: bool,
+    /// Skip symlinked files with a warning and do not descend into symlinked
+    /// directories (the default)
+    #[arg(long, env = "EMOJI_REMOVER_NO_FOLLOW_SYMLINKS", value_parser = BoolishValueParser::new(), overrides_with = "follow_symlinks")]
+    no_follow_symlinks: bool,
+    /// Descend at most N directories below each path when walking; 1 only
+    /// takes the files directly inside
+    #[arg(long, env = "EMOJI_REMOVER_MAX_DEPTH", value_name = "N")]
+    max_depth: Option<usize>,
+    /// Process dotfiles and dot-directories other than .git (the default)
+    #[arg(long, env = "EMOJI_REMOVER_HIDDEN", value_parser = BoolishValueParser::new(), overrides_with = "no_hidden")]
+    hidden: bool,
+    /// Skip dotfiles and dot-directories such as .github and .config
+    #[arg(long, env = "EMOJI_REMOVER_NO_HIDDEN", value_parser = BoolishValueParser::new(), overrides_with = "hidden")]
+    no_hidden: bool,
+    /// Commit the cleaned files once done, with MESSAGE or a default message.
+    /// Files that already had other changes are cleaned but left out
+    #[arg(
+        long,
+        value_name = "MESSAGE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = git::DEFAULT_COMMIT_MESSAGE,
+        conflicts_with_all = ["dry_run", "check", "no_git", "stdin", "commit_msg", "staged"]
+    )]
+    commit: Option<String>,
+    /// Save a snapshot of the working tree, untracked files included, as a
+    /// commit under refs/emoji-remover/snapshot before writing anything
+    #[arg(long, env = "EMOJI_REMOVER_STASH_FIRST", value_parser = BoolishValueParser::new(), conflicts_with_all = ["dry_run", "check", "no_git", "stdin", "commit_msg"])]
+    stash_first: bool,
+    /// Keep a copy of each file before rewriting it, named with SUFFIX appended
+    /// (".bak" by default)
+    #[arg(long, env = "EMOJI_REMOVER_BACKUP", value_name = "SUFFIX", num_args = 0..=1, require_equals = true, default_missing_value = ".bak")]
+    backup: Option<String>,
+    /// Put backups under DIR, mirroring the repository layout, instead of next
+    /// to each file. Implies --backup
+    #[arg(long, env = "EMOJI_REMOVER_BACKUP_DIR", value_name = "DIR")]
+    backup_dir: Option<PathBuf>,
+    /// Keep the modification time of rewritten files, so build tools do not
+    /// see them as changed
+    #[arg(long, env = "EMOJI_REMOVER_KEEP_MTIME", value_parser = BoolishValueParser::new())]
+    keep_mtime: bool,
+    /// Drop the UTF-8 byte order mark from files that are rewritten instead
+    /// of keeping it
+    #[arg(long, env = "EMOJI_REMOVER_STRIP_BOM", value_parser = BoolishValueParser::new())]
+    strip_bom: bool,
+    /// Decode files that are not valid UTF-8 with this encoding, and write
+    /// them back in it. UTF-16 files with a byte order mark are always read
+    #[arg(
+        long,
+        env = "EMOJI_REMOVER_FALLBACK_ENCODING",
+        value_name = "ENCODING",
+        value_enum
+    )]
+    fallback_encoding: Option<Encoding>,
+    /// Clean files with invalid UTF-8 sequences too, leaving the lines that
+    /// contain them untouched
+    #[arg(long, env = "EMOJI_REMOVER_LOSSY_UTF8", value_parser = BoolishValueParser::new(), conflicts_with = "fallback_encoding")]
+    lossy_utf8: bool,
+    /// Skip files larger than SIZE bytes. K, M and G suffixes are accepted,
+    /// and 0 disables the limit. Without it, files over 5M are skipped only
+    /// when they cannot be cleaned line by line, as with --diff
+    #[arg(long, env = "EMOJI_REMOVER_MAX_FILESIZE", value_name = "SIZE", value_parser = parse_size)]
+    max_filesize: Option<u64>,
+    /// Check every file, even those the cache in the git directory records
+    /// as unchanged since they were last found clean
+    #[arg(long, env = "EMOJI_REMOVER_NO_CACHE", value_parser = BoolishValueParser::new())]
+    no_cache: bool,
+    /// Report on stderr the time spent in each phase of the run and the
+    /// slowest files
+    #[arg(long, env = "EMOJI_REMOVER_TIMINGS", value_parser = BoolishValueParser::new())]
+    timings: bool,
+    /// Only remove marked comments on lines START to END, inclusive and
+    /// 1-based. A block comment with any of its lines in range is removed
+    /// whole. May be given several times or as a comma-separated list
+    #[arg(long = "lines", env = "EMOJI_REMOVER_LINES", value_delimiter = ',', value_name = "START:END", value_parser = parse_line_range)]
+    line_ranges: Vec<RangeInclusive<usize>>,
+}
+
+/// Parses a byte count such as `4096`, `512K` or `5M`.
+fn parse_size(text: &str) -> Result<u64, String> {
+    let (digits, unit) = match text.char_indices().find(|(_, c)| !c.is_ascii_digit()) {
+        Some((idx, _)) => text.split_at(idx),
+        None => (text, ""),
+    };
+    let multiplier: u64 = match unit.to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" => 1 << 10,
+        "M" | "MB" => 1 << 20,
+        "G" | "GB" => 1 << 30,
+        _ => return Err(format!("unknown size unit \"{}\"", unit)),
+    };
+    let count: u64 = digits
+        .parse()
+        .map_err(|_| format!("\"{}\" is not a size", text))?;
+    count
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("\"{}\" is too large", text))
 }
 
-fn process_file(file_path: &Path) -> Result<(), AppError> {
-    let content_bytes =
-        fs::read(file_path).map_err(|e| AppError::FileRead(file_path.to_path_buf(), e))?;
-    let content = String::from_utf8(content_bytes)
-        .map_err(|_| AppError::InvalidUtf8(file_path.to_path_buf()))?;
+/// Parses a line range such as `10:20`. Either end may be left out to mean
+/// the first or last line.
+fn parse_line_range(text: &str) -> Result<RangeInclusive<usize>, String> {
+    let (start, end) = text
+        .split_once(':')
+        .ok_or_else(|| format!("\"{}\" is not START:END", text))?;
+    let line = |part: &str, default: usize| match part {
+        "" => Ok(default),
+        part => match part.parse() {
+            Ok(0) | Err(_) => Err(format!("\"{}\" is not a line number", part)),
+            Ok(line) => Ok(line),
+        },
+    };
+    let (start, end) = (line(start, 1)?, line(end, usize::MAX)?);
+    if start > end {
+        return Err(format!("\"{}\" ends before it starts", text));
+    }
+    Ok(start..=end)
+}
 
-    let ext = file_path.extension().and_then(|s| s.to_str()).unwrap_or("");
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Put back the comments removed by the last run that wrote files
+    Undo,
+    /// Install a git pre-commit hook that runs emoji-remover on staged files.
+    /// An existing hook is kept, with the command run before the rest of it
+    InstallHook {
+        /// Clean and re-stage marked files instead of rejecting the commit
+        #[arg(long, conflicts_with = "commit_msg")]
+        fix: bool,
+        /// Install a commit-msg hook that strips markers from commit messages instead
+        #[arg(long)]
+        commit_msg: bool,
+    },
+}
 
-    let mut modified = false;
-    let cleaned_lines: Vec<String> = content
-        .lines()
-        .map(|line| {
-            let (comment_start, block_ender): (Option<usize>, Option<&str>) = if ext == "html" {
-                let html_idx = line.find("<!--");
-                let js_line_idx = line.find("//");
-                let css_block_idx = line.find("/*");
-
-                let mut best_start = None;
-                let mut best_ender = None;
-                let mut min_idx = usize::MAX;
-
-                if let Some(idx) = html_idx {
-                    if idx < min_idx {
-                        min_idx = idx;
-                        best_start = Some(idx);
-                        best_ender = Some("-->");
-                    }
+impl Cli {
+    /// Options from `EMOJI_REMOVER_*` variables with the command line layered on
+    /// top, to be applied over the config files.
+    fn overrides(&self) -> Result<Config, ConfigError> {
+        let non_empty = |values: &Vec<String>| (!values.is_empty()).then(|| values.clone());
+        let mut config = Config::from_env()?;
+        config.merge(Config {
+            include: non_empty(&self.include),
+            exclude: non_empty(&self.exclude),
+            marker: non_empty(&self.marker),
+            marker_regex: non_empty(&self.marker_regex),
+            all_emojis: if self.no_all_emojis {
+                Some(false)
+            } else {
+                self.all_emojis.then_some(true)
+            },
+            include_doc_comments: self.include_doc_comments.then_some(true),
+            include_directives: self.include_directives.then_some(true),
+            languages: BTreeMap::new(),
+        });
+        Ok(config)
+    }
+
+    fn selection(&self) -> Option<git::Selection> {
+        if self.staged {
+            Some(git::Selection::Staged)
+        } else if self.modified {
+            Some(git::Selection::Modified)
+        } else {
+            self.since.clone().map(git::Selection::Since)
+        }
+    }
+
+    fn writer(&self) -> writer::FileWriter {
+        let backup = (self.backup.is_some() || self.backup_dir.is_some()).then(|| writer::Backup {
+            suffix: self.backup.clone().unwrap_or_default(),
+            dir: self.backup_dir.clone(),
+        });
+        writer::FileWriter {
+            backup,
+            keep_mtime: self.keep_mtime,
+        }
+    }
+
+    fn traversal(&self) -> Traversal {
+        Traversal {
+            follow_symlinks: self.follow_symlinks,
+            max_depth: self.max_depth,
+            hidden: !self.no_hidden,
+        }
+    }
+
+    fn decoder(&self) -> Decoder {
+        Decoder {
+            fallback: self.fallback_encoding,
+            lossy: self.lossy_utf8,
+        }
+    }
+
+    fn use_color(&self) -> bool {
+        match self.color {
+            ColorChoice::Auto => io::stdout().is_terminal(),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+
+    fn mode(&self) -> Mode {
+        if self.check {
+            Mode::Check
+        } else if self.dry_run || self.format == OutputFormat::Ranges {
+            Mode::DryRun
+        } else if self.interactive {
+            Mode::Interactive
+        } else {
+            Mode::Write
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+/// Exit status used by `--check` when marked comments are found.
+const EXIT_MARKERS_FOUND: i32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Write,
+    DryRun,
+    Check,
+    Interactive,
+}
+
+/// State shared by every file processed in one run.
+struct Session {
+    mode: Mode,
+    root: PathBuf,
+    diff: bool,
+    color: bool,
+    format: OutputFormat,
+    resolver: ConfigResolver,
+    /// Set with `--staged` to check what is staged rather than working copies.
+    staged: Option<git::StagedFiles>,
+    /// Set with `--staged` so cleaned files are added back to the index.
+    restager: Option<git::Restager>,
+    /// Set when marked comments were found in files that could not be
+    /// cleaned, so the run fails like `--check` does.
+    left_marked: bool,
+    /// Set with `--changed-lines-only` to limit removals to these lines.
+    added_lines: Option<git::AddedLines>,
+    /// Set with `--author` to limit removals to lines by matching authors.
+    author: Option<git::AuthorFilter>,
+    /// Set with `--lines` to limit removals to these lines; empty for all.
+    line_ranges: Vec<RangeInclusive<usize>>,
+    /// Set with `--lang` to use this syntax instead of each file's extension.
+    lang: Option<String>,
+    /// Set with `--commit` to commit the cleaned files at the end of the run.
+    committer: Option<git::Committer>,
+    /// Records written removals for `emoji-remover undo`; unset without git.
+    journal: Option<journal::Journal>,
+    /// Files known to be clean; unset without git or with `--no-cache`.
+    cache: Option<cache::Cache>,
+    writer: writer::FileWriter,
+    allow_conflicts: bool,
+    include_minified: bool,
+    include_generated: bool,
+    strip_bom: bool,
+    decoder: Decoder,
+    /// Files larger than this are skipped, unless they are cleaned line by line.
+    max_filesize: Option<u64>,
+    /// Files cleaned line by line larger than this are skipped. Only set by
+    /// `--max-filesize`, as they are never held in memory.
+    max_streamed_filesize: Option<u64>,
+    prompt: Prompt,
+    /// Findings collected for formats that are rendered once the run finishes.
+    reports: Vec<FileReport>,
+    summary: Summary,
+    started: Instant,
+}
+
+impl Session {
+    /// Git-backed features start out disabled; `main` enables those requested.
+    fn new(cli: &Cli, root: PathBuf, resolver: ConfigResolver) -> Self {
+        Session {
+            mode: cli.mode(),
+            root,
+            diff: cli.diff,
+            color: cli.use_color(),
+            format: cli.format,
+            resolver,
+            staged: None,
+            restager: None,
+            left_marked: false,
+            added_lines: None,
+            author: None,
+            line_ranges: cli.line_ranges.clone(),
+            lang: cli
+                .lang
+                .as_deref()
+                .map(|lang| language_for_filetype(lang).to_string()),
+            committer: None,
+            journal: None,
+            cache: None,
+            writer: cli.writer(),
+            allow_conflicts: cli.allow_conflicts,
+            include_minified: cli.include_minified,
+            include_generated: cli.include_generated,
+            strip_bom: cli.strip_bom,
+            decoder: cli.decoder(),
+            max_filesize: Some(cli.max_filesize.unwrap_or(DEFAULT_MAX_FILESIZE))
+                .filter(|&limit| limit > 0),
+            max_streamed_filesize: cli.max_filesize.filter(|&limit| limit > 0),
+            prompt: Prompt::default(),
+            reports: Vec::new(),
+            summary: Summary::default(),
+            started: Instant::now(),
+        }
+    }
+
+    /// Whether files can be cleaned without holding their content in memory,
+    /// which the diff, prompts, blame and legacy encodings all need.
+    fn can_stream(&self) -> bool {
+        !self.diff
+            && self.mode != Mode::Interactive
+            && self.author.is_none()
+            && self.decoder.fallback.is_none()
+    }
+
+    /// Whether `--lines` allows removing the comment `change` removes, as
+    /// one of its lines is in range.
+    fn in_line_ranges(&self, change: &LineChange) -> bool {
+        self.line_ranges.is_empty()
+            || self.line_ranges.iter().any(|range| {
+                *range.start() <= change.end_line() && change.line_number <= *range.end()
+            })
+    }
+
+    /// Records in the cache that `label`, as described by `stamp`, is clean.
+    fn mark_clean(&mut self, label: &str, stamp: Option<cache::Stamp>) {
+        if let (Some(cache), Some(stamp)) = (&mut self.cache, stamp) {
+            cache.mark_clean(label, stamp);
+        }
+    }
+
+    /// Path shown in reports: relative to the repository root, with forward slashes.
+    fn label(&self, file_path: &Path) -> String {
+        let relative = file_path.strip_prefix(&self.root).unwrap_or(file_path);
+        relative.to_string_lossy().replace('\\', "/")
+    }
+
+    fn emit(&self, event: Event) {
+        if self.format == OutputFormat::Ndjson {
+            println!("{}", event.to_json());
+        }
+    }
+
+    fn finish(&mut self) {
+        self.summary.elapsed = self.started.elapsed();
+        match self.format {
+            OutputFormat::Text => info!(
+                "{}",
+                self.summary
+                    .describe(matches!(self.mode, Mode::Write | Mode::Interactive))
+            ),
+            OutputFormat::Ndjson => self.emit(Event::Summary {
+                summary: &self.summary,
+            }),
+            _ => {}
+        }
+        if let Some(rendered) = self.format.render(&self.reports, &self.summary) {
+            println!("{}", rendered);
+        }
+        timings::report(SLOWEST_FILES);
+    }
+}
+
+/// Answers collected across files while running with `--interactive`.
+#[derive(Debug, Default)]
+struct Prompt {
+    accept_all: bool,
+    quit: bool,
+}
+
+impl Prompt {
+    /// Asks whether `change` should be applied, showing the surrounding lines
+    /// for context. A block comment spanning lines is asked about once.
+    fn confirm(&mut self, label: &str, lines: &[&str], change: &LineChange) -> bool {
+        if self.quit {
+            return false;
+        }
+        if self.accept_all {
+            return true;
+        }
+        let first = (change.line_number - 1).saturating_sub(2);
+        let last = (change.end_line() + 2).min(lines.len());
+        eprintln!("\n{}:{}:{}", label, change.line_number, change.column);
+        for (offset, line) in lines[first..change.line_number - 1].iter().enumerate() {
+            eprintln!("{:>5}   {}", first + offset + 1, line);
+        }
+        for (number, original, _) in change.lines() {
+            eprintln!("{:>5} - {}", number, original);
+        }
+        for (number, _, cleaned) in change.lines() {
+            eprintln!("{:>5} + {}", number, cleaned);
+        }
+        for (offset, line) in lines[change.end_line()..last].iter().enumerate() {
+            eprintln!("{:>5}   {}", change.end_line() + offset + 1, line);
+        }
+        loop {
+            eprint!("Remove this comment? [y,n,a,q] ");
+            let mut answer = String::new();
+            match io::stdin().read_line(&mut answer) {
+                // EOF or a broken terminal behaves like quitting
+                Ok(0) | Err(_) => {
+                    self.quit = true;
+                    return false;
                 }
-                if let Some(idx) = js_line_idx {
-                    if idx < min_idx {
-                        min_idx = idx;
-                        best_start = Some(idx);
-                        best_ender = None;
-                    }
+                Ok(_) => {}
+            }
+            match answer.trim() {
+                "y" => return true,
+                "n" => return false,
+                "a" => {
+                    self.accept_all = true;
+                    return true;
                 }
-                if let Some(idx) = css_block_idx {
-                    if idx < min_idx {
-                        min_idx = idx;
-                        best_start = Some(idx);
-                        best_ender = Some("*/");
-                    }
+                "q" => {
+                    self.quit = true;
+                    return false;
                 }
-                (best_start, best_ender)
-            } else if ext == "css" {
-                (line.find("/*"), Some("*/"))
-            } else if matches!(ext, "jsx" | "tsx") {
-                let slash_idx = line.find("//");
-                let block_idx = line.find("{/*");
-                match (slash_idx, block_idx) {
-                    (Some(s), Some(b)) => {
-                        // Pick the one that appears first
-                        if s < b {
-                            (Some(s), None)
-                        } else {
-                            (Some(b), Some("*/}"))
-                        }
-                    }
-                    (Some(s), None) => (Some(s), None),
-                    (None, Some(b)) => (Some(b), Some("*/}")),
-                    (None, None) => (None, None),
+                _ => eprintln!("y - remove, n - keep, a - remove this and all remaining, q - quit"),
+            }
+        }
+    }
+}
+
+/// Files below this size are never considered minified.
+const MINIFIED_MIN_SIZE: usize = 1024;
+/// Average line length above which a file is considered minified.
+const MINIFIED_LINE_LENGTH: usize = 300;
+
+/// Whether `path` looks like a minified bundle, where removing "comments" is
+/// slow and likely to break code: a `.min.` name, or lines so long on
+/// average that the file cannot have been written by hand.
+fn looks_minified(path: &Path, size: usize, line_count: usize) -> bool {
+    let named_min = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.contains(".min."));
+    if named_min {
+        return true;
+    }
+    size >= MINIFIED_MIN_SIZE && size / line_count.max(1) > MINIFIED_LINE_LENGTH
+}
+
+/// How many leading lines are searched for a generated-code marker.
+const GENERATED_HEADER_LINES: usize = 10;
+const GENERATED_MARKERS: [&str; 3] = ["@generated", "DO NOT EDIT", "Code generated by"];
+
+/// Whether the first lines of `content` carry one of the conventional markers
+/// of generated code, which would get its comments back on regeneration.
+fn looks_generated(content: &str) -> bool {
+    content
+        .lines()
+        .take(GENERATED_HEADER_LINES)
+        .any(is_generated_marker)
+}
+
+fn is_generated_marker(line: &str) -> bool {
+    GENERATED_MARKERS.iter().any(|marker| line.contains(marker))
+}
+
+/// Whether `content` has an unresolved merge conflict, i.e. a line starting
+/// with `<<<<<<<` or `>>>>>>>` followed by a space or nothing else.
+fn has_conflict_markers(content: &str) -> bool {
+    content.lines().any(is_conflict_marker)
+}
+
+fn is_conflict_marker(line: &str) -> bool {
+    ["<<<<<<<", ">>>>>>>"].iter().any(|marker| {
+        line.strip_prefix(marker)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with(' '))
+    })
+}
+
+fn report_changes(label: &str, changes: &[LineChange], mode: Mode) {
+    match mode {
+        Mode::Write | Mode::Interactive => {}
+        Mode::DryRun => {
+            println!("Would clean: {}", label);
+            for (line_number, original, cleaned) in
+                line_edits(changes).iter().flat_map(LineEdit::lines)
+            {
+                println!("  {}: - {}", line_number, original);
+                println!("  {}: + {}", line_number, cleaned);
+            }
+        }
+        Mode::Check => {
+            for change in changes {
+                println!(
+                    "{}:{}:{}: marked comment: {}",
+                    label,
+                    change.line_number,
+                    change.column,
+                    // The first line of a block comment spanning lines
+                    change.original.lines().next().unwrap_or_default().trim()
+                );
+            }
+        }
+    }
+}
+
+/// Bytes read from either end of a file to find its modeline or `#!` line.
+const SNIFF_LEN: u64 = 1024;
+
+/// The language of `path`: its extension, unless that is missing or
+/// ambiguous and a modeline or `#!` line in the file names one.
+fn file_language(path: &Path) -> String {
+    let ext = path.extension().and_then(|s| s.to_str()).unwrap_or("");
+    if !is_ambiguous_extension(ext) {
+        return ext.to_string();
+    }
+    let ends = read_ends(path).unwrap_or_default();
+    language_for_file(ext, &ends)
+}
+
+/// The first and last `SNIFF_LEN` bytes of `path` as text, on separate lines.
+fn read_ends(path: &Path) -> io::Result<String> {
+    let mut file = fs::File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut ends = Vec::new();
+    (&mut file).take(SNIFF_LEN).read_to_end(&mut ends)?;
+    if len > SNIFF_LEN {
+        file.seek(SeekFrom::Start((len - SNIFF_LEN).max(SNIFF_LEN)))?;
+        ends.push(b'\n');
+        file.read_to_end(&mut ends)?;
+    }
+    Ok(String::from_utf8_lossy(&ends).into_owned())
+}
+
+/// Reads `path` unless its first bytes show it is binary, so binaries are
+/// rejected without reading all of them.
+fn read_text(path: &Path) -> io::Result<Option<Vec<u8>>> {
+    let mut file = fs::File::open(path)?;
+    let mut bytes = Vec::new();
+    (&mut file)
+        .take(encoding::SNIFF_LEN as u64)
+        .read_to_end(&mut bytes)?;
+    if encoding::looks_binary(&bytes) {
+        return Ok(None);
+    }
+    file.read_to_end(&mut bytes)?;
+    Ok(Some(bytes))
+}
+
+/// How many of the slowest files `--timings` lists.
+const SLOWEST_FILES: usize = 10;
+
+/// Files larger than this are cleaned line by line from disk rather than read
+/// into memory, unless an option needs their whole content. It stays below
+/// `DEFAULT_MAX_FILESIZE`, so large files are streamed rather than skipped.
+const STREAMING_THRESHOLD: u64 = 4 << 20;
+
+/// Files read into memory larger than this are skipped unless
+/// `--max-filesize` says otherwise.
+const DEFAULT_MAX_FILESIZE: u64 = 5 << 20;
+
+/// Cleans a file above `STREAMING_THRESHOLD` one line at a time: a first pass
+/// finds the changes, and a second one writes the cleaned copy. Returns `None`
+/// for UTF-16 files, whose lines cannot be read bytewise.
+fn process_large_file(
+    file_path: &Path,
+    label: &str,
+    markers: &Markers,
+    ext: &str,
+    stamp: Option<cache::Stamp>,
+    session: &mut Session,
+) -> Result<Option<bool>, AppError> {
+    let read_error = |e| AppError::FileRead(file_path.to_path_buf(), e);
+    let file = fs::File::open(file_path).map_err(read_error)?;
+    let mut reader = io::BufReader::new(file);
+    let head = reader.fill_buf().map_err(read_error)?;
+    if matches!(head, [0xFF, 0xFE, ..] | [0xFE, 0xFF, ..]) {
+        return Ok(None);
+    }
+    if encoding::looks_binary(&head[..head.len().min(encoding::SNIFF_LEN)]) {
+        debug!("Skipping {}: binary file", label);
+        return Ok(Some(false));
+    }
+    debug!("Streaming {}", label);
+
+    let mut scanner = CommentScanner::new(ext, markers);
+    let mut changes = Vec::new();
+    let mut invalid_lines = HashSet::new();
+    let (mut conflicted, mut generated) = (false, false);
+    let (mut size, mut line_count) = (0, 0);
+    let lossy = session.decoder.lossy;
+    // Reading and parsing are interleaved here, so both count as parsing
+    timings::measure(Phase::Parsing, || {
+        let mut buf = Vec::new();
+        while reader.read_until(b'\n', &mut buf).map_err(read_error)? > 0 {
+            size += buf.len();
+            line_count += 1;
+            let (mut text, _) = split_ending(&buf);
+            if line_count == 1 {
+                text = text.strip_prefix(UTF8_BOM).unwrap_or(text);
+            }
+            match std::str::from_utf8(text) {
+                Ok(line) => {
+                    conflicted |= is_conflict_marker(line);
+                    generated |= line_count <= GENERATED_HEADER_LINES && is_generated_marker(line);
+                    changes.extend(scanner.next_line(line_count, line));
+                }
+                // Lines with invalid UTF-8 are copied as they are, but still
+                // scanned so comments spanning them are seen whole
+                Err(_) if lossy => {
+                    invalid_lines.insert(line_count);
+                    let line = String::from_utf8_lossy(text);
+                    changes.extend(scanner.next_line(line_count, &line));
                 }
-            } else if matches!(ext, "rs" | "js" | "ts") {
-                (line.find("//"), None)
+                Err(_) => return Err(AppError::InvalidUtf8(file_path.to_path_buf())),
+            }
+            buf.clear();
+        }
+        changes.extend(scanner.finish());
+        Ok(())
+    })?;
+    if !invalid_lines.is_empty() {
+        debug!(
+            "Leaving {} lines with invalid UTF-8 in {} untouched",
+            invalid_lines.len(),
+            label
+        );
+        changes.retain(|change: &LineChange| {
+            !change
+                .line_numbers()
+                .any(|line| invalid_lines.contains(&line))
+        });
+    }
+
+    if changes.is_empty() {
+        trace!("No marked comments in {}", label);
+        session.mark_clean(label, stamp);
+        return Ok(Some(false));
+    }
+    if !session.allow_conflicts && conflicted {
+        warn!(
+            "Skipping {}: contains merge conflict markers (use --allow-conflicts to clean it anyway)",
+            label
+        );
+        return Ok(Some(false));
+    }
+    if !session.include_minified && looks_minified(file_path, size, line_count) {
+        debug!(
+            "Skipping {}: looks minified (use --include-minified to clean it anyway)",
+            label
+        );
+        return Ok(Some(false));
+    }
+    if !session.include_generated && generated {
+        debug!(
+            "Skipping {}: generated file (use --include-generated to clean it anyway)",
+            label
+        );
+        return Ok(Some(false));
+    }
+    if let Some(added_lines) = &session.added_lines {
+        let added = added_lines.get(Path::new(label));
+        changes.retain(|change| is_added(change, added));
+    }
+    changes.retain(|change| session.in_line_ranges(change));
+    if changes.is_empty() {
+        return Ok(Some(false));
+    }
+
+    let strip_bom = session.strip_bom;
+    let found = handle_changes(
+        session,
+        file_path,
+        label.to_string(),
+        changes,
+        None,
+        |writer, label, changes| {
+            writer.write_with(file_path, label, |out| {
+                rewrite_lines(file_path, changes, strip_bom, out)
+            })
+        },
+    )?;
+    Ok(Some(found))
+}
+
+/// Whether `change` touches one of the `added` lines, so a comment is kept
+/// or removed whole under `--changed-lines-only`.
+fn is_added(change: &LineChange, added: Option<&HashSet<usize>>) -> bool {
+    added.is_some_and(|lines| change.line_numbers().any(|line| lines.contains(&line)))
+}
+
+/// Copies `path` to `out` line by line with `changes` applied.
+fn rewrite_lines(
+    path: &Path,
+    changes: &[LineChange],
+    strip_bom: bool,
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    let mut reader = io::BufReader::new(fs::File::open(path)?);
+    let edits = line_edits(changes);
+    let mut pending = edits.iter().flat_map(LineEdit::lines).peekable();
+    let mut buf = Vec::new();
+    let mut number = 0;
+    while reader.read_until(b'\n', &mut buf)? > 0 {
+        number += 1;
+        let (mut text, ending) = split_ending(&buf);
+        if number == 1
+            && let Some(rest) = text.strip_prefix(UTF8_BOM)
+        {
+            if !strip_bom {
+                out.write_all(UTF8_BOM)?;
+            }
+            text = rest;
+        }
+        match pending.next_if(|(line_number, _, _)| *line_number == number) {
+            Some((_, _, cleaned)) => out.write_all(cleaned.as_bytes())?,
+            None => out.write_all(text)?,
+        }
+        out.write_all(ending)?;
+        buf.clear();
+    }
+    Ok(())
+}
+
+/// Identifies the settings a file is checked with, so it is only skipped as
+/// clean while its markers and comment syntax stay the same.
+fn check_fingerprint(markers: &Markers, syntax: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    (markers.fingerprint(), syntax).hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Cleans a single file according to `mode`, returning whether any marked comments were found.
+fn process_file(file_path: &Path, session: &mut Session) -> Result<bool, AppError> {
+    let mode = session.mode;
+    let label = session.label(file_path);
+    debug!("Examining {}", label);
+    session.summary.files_scanned += 1;
+    session.emit(Event::FileStarted { path: &label });
+    let dir = file_path.parent().unwrap_or(&session.root).to_path_buf();
+    let settings = session.resolver.settings_for(&dir)?;
+    let language = match &session.lang {
+        Some(lang) => lang.clone(),
+        None => file_language(file_path),
+    };
+    let ext = settings.syntax_for(&language);
+
+    // A partially staged file is checked as staged, which its working copy is not
+    let staged_content = match &session.staged {
+        Some(staged) if staged.is_partially_staged(Path::new(&label)) => {
+            let Some(content) = staged.content(Path::new(&label)).map_err(AppError::Git)? else {
+                return Ok(false);
+            };
+            if matches!(mode, Mode::Write | Mode::Interactive) {
+                let marked = std::str::from_utf8(&content).is_ok_and(|text| {
+                    !find_changes(split_bom(text).1, ext, &settings.markers).is_empty()
+                });
+                if marked {
+                    warn!(
+                        "Not cleaning {}: marked comments are staged, but it also has unstaged changes that re-staging would commit (stage or stash them first)",
+                        label
+                    );
+                    session.left_marked = true;
+                }
+                return Ok(false);
+            }
+            Some(content)
+        }
+        _ => None,
+    };
+
+    let read_error = |e| AppError::FileRead(file_path.to_path_buf(), e);
+    let metadata = fs::metadata(file_path).map_err(read_error)?;
+    let stamp = session
+        .cache
+        .as_ref()
+        .filter(|_| staged_content.is_none())
+        .and_then(|_| cache::Stamp::new(&metadata, check_fingerprint(&settings.markers, ext)));
+    if let (Some(cache), Some(stamp)) = (&session.cache, stamp)
+        && cache.is_clean(&label, stamp)
+    {
+        debug!(
+            "Skipping {}: unchanged since it was last found clean",
+            label
+        );
+        return Ok(false);
+    }
+    let size = match &staged_content {
+        Some(content) => content.len() as u64,
+        None => metadata.len(),
+    };
+    let streamed = size > STREAMING_THRESHOLD && session.can_stream() && staged_content.is_none();
+    let over_limit = |limit: Option<u64>| {
+        let over = limit.is_some_and(|limit| size > limit);
+        if over {
+            info!(
+                "Skipping {}: {} bytes is over the --max-filesize limit",
+                label, size
+            );
+        }
+        over
+    };
+    if streamed {
+        if over_limit(session.max_streamed_filesize) {
+            return Ok(false);
+        }
+        if let Some(found) =
+            process_large_file(file_path, &label, &settings.markers, ext, stamp, session)?
+        {
+            return Ok(found);
+        }
+    }
+    // Also for UTF-16 files, which are read whole even when large
+    if over_limit(session.max_filesize) {
+        return Ok(false);
+    }
+    let content_bytes = match staged_content {
+        Some(content) => {
+            let head = &content[..content.len().min(encoding::SNIFF_LEN)];
+            (!encoding::looks_binary(head)).then_some(content)
+        }
+        None => timings::measure(Phase::Reading, || read_text(file_path)).map_err(read_error)?,
+    };
+    let Some(content_bytes) = content_bytes else {
+        debug!("Skipping {}: binary file", label);
+        return Ok(false);
+    };
+    let decoded = timings::measure(Phase::Reading, || session.decoder.decode(&content_bytes))
+        .ok_or_else(|| AppError::InvalidUtf8(file_path.to_path_buf()))?;
+    let (bom, content) = split_bom(&decoded.text);
+    if !decoded.invalid_lines.is_empty() {
+        debug!(
+            "Leaving {} lines with invalid UTF-8 in {} untouched",
+            decoded.invalid_lines.len(),
+            label
+        );
+    }
+
+    // Most files hold no marker at all; spare them the per-line parsing
+    if !timings::measure(Phase::Parsing, || settings.markers.may_match(content)) {
+        trace!("No markers in {}", label);
+        session.mark_clean(&label, stamp);
+        return Ok(false);
+    }
+
+    if !session.allow_conflicts && has_conflict_markers(content) {
+        warn!(
+            "Skipping {}: contains merge conflict markers (use --allow-conflicts to clean it anyway)",
+            label
+        );
+        return Ok(false);
+    }
+    if !session.include_minified
+        && looks_minified(file_path, content.len(), content.lines().count())
+    {
+        debug!(
+            "Skipping {}: looks minified (use --include-minified to clean it anyway)",
+            label
+        );
+        return Ok(false);
+    }
+    if !session.include_generated && looks_generated(content) {
+        debug!(
+            "Skipping {}: generated file (use --include-generated to clean it anyway)",
+            label
+        );
+        return Ok(false);
+    }
+
+    let mut changes = timings::measure(Phase::Parsing, || {
+        find_changes(content, ext, &settings.markers)
+    });
+    if changes.is_empty() {
+        session.mark_clean(&label, stamp);
+    }
+    changes.retain(|change| {
+        !change
+            .line_numbers()
+            .any(|line| decoded.invalid_lines.contains(&line))
+    });
+    if let Some(added_lines) = &session.added_lines {
+        let added = added_lines.get(Path::new(&label));
+        changes.retain(|change| is_added(change, added));
+    }
+    changes.retain(|change| session.in_line_ranges(change));
+    if let Some(author) = &session.author
+        && !changes.is_empty()
+    {
+        let authored = author
+            .matching_lines(file_path, &content_bytes)
+            .map_err(AppError::Git)?;
+        changes.retain(|change| change.line_numbers().all(|line| authored.contains(&line)));
+    }
+    if changes.is_empty() {
+        trace!("No marked comments in {}", label);
+        return Ok(false);
+    }
+
+    if mode == Mode::Interactive {
+        let label = file_path.display().to_string();
+        let lines: Vec<&str> = content.lines().collect();
+        changes.retain(|change| session.prompt.confirm(&label, &lines, change));
+        if changes.is_empty() {
+            return Ok(true);
+        }
+    }
+
+    let diff = session
+        .diff
+        .then(|| diff::unified_diff(&label, content, &changes, session.color));
+    let strip_bom = session.strip_bom && decoded.encoding == Encoding::Utf8;
+    handle_changes(
+        session,
+        file_path,
+        label,
+        changes,
+        diff,
+        |writer, label, changes| {
+            let bom = if strip_bom { "" } else { bom };
+            let pieces = cleaned_pieces(content, changes);
+            // Plain UTF-8 is written straight from the pieces, without a copy
+            if decoded.is_verbatim() {
+                return writer.write_with(file_path, label, |out| {
+                    out.write_all(bom.as_bytes())?;
+                    pieces
+                        .iter()
+                        .try_for_each(|piece| out.write_all(piece.as_bytes()))
+                });
+            }
+            let output = format!("{}{}", bom, pieces.concat());
+            let output = decoded
+                .encode(&content_bytes, &output)
+                .ok_or_else(|| AppError::Unencodable(file_path.to_path_buf(), decoded.encoding))?;
+            writer.write(file_path, label, &output)
+        },
+    )
+}
+
+/// Reports the marked comments found in `file_path` and, when writing, saves
+/// the cleaned file with `write` and records what was removed.
+fn handle_changes(
+    session: &mut Session,
+    file_path: &Path,
+    label: String,
+    changes: Vec<LineChange>,
+    diff: Option<String>,
+    write: impl FnOnce(&writer::FileWriter, &str, &[LineChange]) -> Result<(), AppError>,
+) -> Result<bool, AppError> {
+    let mode = session.mode;
+    progress::clear();
+    session.summary.record(&changes);
+    for change in &changes {
+        session.emit(Event::Removal {
+            removal: &change.removal(Some(Path::new(&label))),
+        });
+        if session.format == OutputFormat::Quickfix {
+            // Absolute paths so the list resolves regardless of the editor's cwd
+            println!(
+                "{}",
+                report::quickfix_line(&change.removal(Some(file_path)))
+            );
+        }
+    }
+    if let Some(diff) = diff {
+        print!("{}", diff);
+    }
+
+    let written = matches!(mode, Mode::Write | Mode::Interactive);
+    if written {
+        timings::measure(Phase::Writing, || write(&session.writer, &label, &changes))?;
+        if session.format == OutputFormat::Text {
+            match fs::read_link(file_path) {
+                Ok(target) => info!(
+                    "Cleaned: {} (symbolic link to {})",
+                    file_path.display(),
+                    target.display()
+                ),
+                Err(_) => info!("Cleaned: {}", file_path.display()),
+            }
+        }
+        session.emit(Event::FileWritten { path: &label });
+        if let Some(journal) = &mut session.journal {
+            journal.record(&label, &changes)?;
+        }
+        if let Some(restager) = &mut session.restager {
+            restager.stage(Path::new(&label)).map_err(AppError::Git)?;
+        }
+        if let Some(committer) = &mut session.committer
+            && !committer.add(Path::new(&label))
+        {
+            warn!(
+                "Not committing {}: it has other changes besides the cleanup",
+                label
+            );
+        }
+    } else if !session.diff && session.format == OutputFormat::Text {
+        report_changes(&file_path.display().to_string(), &changes, mode);
+    }
+
+    if session.format.is_buffered() {
+        session.reports.push(FileReport {
+            path: label,
+            changes,
+            written,
+        });
+    }
+
+    Ok(true)
+}
+
+/// Filters stdin to stdout without touching the filesystem or requiring a git repository.
+/// The comment syntax comes from `filetype`, an editor filetype name, or else
+/// from the extension of `syntax_hint`.
+fn process_stdin(
+    syntax_hint: Option<&Path>,
+    filetype: Option<&str>,
+    session: &mut Session,
+) -> Result<bool, AppError> {
+    let mut content_bytes = Vec::new();
+    io::stdin()
+        .read_to_end(&mut content_bytes)
+        .map_err(AppError::StdinRead)?;
+    let decoded = session
+        .decoder
+        .decode(&content_bytes)
+        .ok_or_else(|| AppError::InvalidUtf8(PathBuf::from("<stdin>")))?;
+    let (bom, content) = split_bom(&decoded.text);
+
+    let language = match (filetype, &session.lang) {
+        (Some(filetype), _) => language_for_filetype(filetype).to_string(),
+        (None, Some(lang)) => lang.clone(),
+        (None, None) => {
+            let ext = syntax_hint
+                .and_then(|p| p.extension())
+                .and_then(|s| s.to_str())
+                .unwrap_or("");
+            language_for_file(ext, content)
+        }
+    };
+    let root = session.root.clone();
+    let settings = session.resolver.settings_for(&root)?;
+    let ext = settings.syntax_for(&language);
+
+    let mut changes = find_changes(content, ext, &settings.markers);
+    changes.retain(|change| {
+        !change
+            .line_numbers()
+            .any(|line| decoded.invalid_lines.contains(&line))
+            && session.in_line_ranges(change)
+    });
+    let output = apply_changes(content, &changes);
+    let found = !changes.is_empty();
+    session.summary.files_scanned += 1;
+    session.summary.record(&changes);
+    session.emit(Event::FileStarted { path: "<stdin>" });
+    for change in &changes {
+        let removal = change.removal(None);
+        session.emit(Event::Removal { removal: &removal });
+        if session.format == OutputFormat::Quickfix {
+            println!("{}", report::quickfix_line(&removal));
+        }
+    }
+    // Reports replace the cleaned content since both would share stdout
+    match session.format {
+        OutputFormat::Ndjson | OutputFormat::Quickfix => {}
+        OutputFormat::Json | OutputFormat::Sarif | OutputFormat::Ranges => {
+            if found {
+                session.reports.push(FileReport {
+                    path: "<stdin>".to_string(),
+                    changes,
+                    written: false,
+                });
+            }
+        }
+        OutputFormat::Text if session.diff => {
+            if found {
+                print!(
+                    "{}",
+                    diff::unified_diff("<stdin>", content, &changes, session.color)
+                );
+            }
+        }
+        OutputFormat::Text if session.mode == Mode::Write => {
+            let bom = if session.strip_bom && decoded.encoding == Encoding::Utf8 {
+                ""
             } else {
-                (line.find('#'), None)
+                bom
             };
+            let output = decoded
+                .encode(&content_bytes, &format!("{}{}", bom, output))
+                .ok_or_else(|| AppError::Unencodable(PathBuf::from("<stdin>"), decoded.encoding))?;
+            io::stdout()
+                .lock()
+                .write_all(&output)
+                .map_err(AppError::StdoutWrite)?;
+        }
+        OutputFormat::Text => report_changes("<stdin>", &changes, session.mode),
+    }
 
-            if let Some(start) = comment_start {
-                if let Some(ender) = block_ender {
-                    // Try to find the closing tag on the same line
-                    if let Some(end_offset) = line[start..].find(ender) {
-                        let end = start + end_offset + ender.len();
-                        let comment_content = &line[start..end];
-
-                        if comment_content.contains("‼️") {
-                            modified = true;
-                            let prefix = &line[..start];
-                            let suffix = &line[end..];
-                            // If the line is just the comment, trim. Otherwise splice it out.
-                            if suffix.trim().is_empty() {
-                                prefix.trim_end().to_string()
-                            } else {
-                                format!("{}{}", prefix, suffix)
-                            }
-                        } else {
-                            line.to_string()
-                        }
-                    } else {
-                        // Fallback for unclosed block on same line (truncates rest of line)
-                        let comment_part = &line[start..];
-                        if comment_part.contains("‼️") {
-                            modified = true;
-                            line[..start].trim_end().to_string()
-                        } else {
-                            line.to_string()
-                        }
-                    }
-                } else {
-                    // Standard single-line comment processing
-                    let comment_part = &line[start..];
-                    if comment_part.contains("‼️") {
-                        modified = true;
-                        line[..start].trim_end().to_string()
-                    } else {
-                        line.to_string()
-                    }
+    Ok(found)
+}
+
+/// Removes markers from a commit message file, returning whether any were found.
+/// Comment lines are left alone since git strips them itself.
+fn process_commit_msg(path: &Path, markers: &Markers, mode: Mode) -> Result<bool, AppError> {
+    let content = fs::read(path).map_err(|e| AppError::FileRead(path.to_path_buf(), e))?;
+    let content =
+        String::from_utf8(content).map_err(|_| AppError::InvalidUtf8(path.to_path_buf()))?;
+
+    let mut found = false;
+    let mut output = String::with_capacity(content.len());
+    for (idx, (line, ending)) in split_lines(&content).enumerate() {
+        if line.starts_with('#') || !markers.matches(line) {
+            output.push_str(line);
+            output.push_str(ending);
+            continue;
+        }
+        if !found && mode == Mode::DryRun {
+            println!("Would clean: {}", path.display());
+        }
+        found = true;
+        let stripped = markers.strip_literals(line);
+        let dropped = stripped.trim().is_empty() || markers.matches(&stripped);
+        match mode {
+            Mode::Check => println!(
+                "{}:{}: marked commit message: {}",
+                path.display(),
+                idx + 1,
+                line.trim()
+            ),
+            Mode::DryRun => {
+                println!("  {}: - {}", idx + 1, line);
+                if !dropped {
+                    println!("  {}: + {}", idx + 1, stripped);
                 }
-            } else {
-                line.to_string()
             }
-        })
-        .collect();
+            Mode::Write | Mode::Interactive => {}
+        }
+        if !dropped {
+            output.push_str(&stripped);
+            output.push_str(ending);
+        }
+    }
 
-    if modified {
-        let output = cleaned_lines.join("\n");
-        fs::write(file_path, output)
-            .map_err(|e| AppError::FileWrite(file_path.to_path_buf(), e))?;
-        eprintln!("Cleaned: {}", file_path.display());
+    if found && mode == Mode::Write {
+        fs::write(path, output).map_err(|e| AppError::FileWrite(path.to_path_buf(), e))?;
+        info!("Cleaned commit message: {}", path.display());
     }
+    Ok(found)
+}
 
-    Ok(())
+/// Restores the removals recorded in the journal, returning how many were put
+/// back, or `None` without a journal. Lines edited since the run are left
+/// alone with a warning. `decoder` reads files as the cleaning run did.
+fn undo(decoder: Decoder) -> Result<Option<usize>, AppError> {
+    let repo = Repository::discover(".").map_err(AppError::GitDiscovery)?;
+    let root = repo.workdir().ok_or(AppError::BareRepo)?;
+    let Some(entries) = journal::read(repo.path())? else {
+        return Ok(None);
+    };
+
+    let mut by_file: BTreeMap<&str, Vec<&journal::Entry>> = BTreeMap::new();
+    for entry in &entries {
+        by_file.entry(&entry.path).or_default().push(entry);
+    }
+    let mut restored = 0;
+    for (label, file_entries) in by_file {
+        let path = root.join(label);
+        let bytes = fs::read(&path).map_err(|e| AppError::FileRead(path.clone(), e))?;
+        let decoded = decoder
+            .decode(&bytes)
+            .ok_or_else(|| AppError::InvalidUtf8(path.clone()))?;
+        let (bom, content) = split_bom(&decoded.text);
+        let mut lines: Vec<(String, &str)> = split_lines(content)
+            .map(|(line, ending)| (line.to_string(), ending))
+            .collect();
+        let mut changed = false;
+        for entry in file_entries {
+            // A block comment spanning lines was recorded as one removal
+            let start = entry.line_number - 1;
+            let cleaned: Vec<&str> = entry.cleaned.split('\n').collect();
+            match lines.get_mut(start..start + cleaned.len()) {
+                Some(range)
+                    if range
+                        .iter()
+                        .zip(&cleaned)
+                        .all(|((line, _), cleaned)| line == cleaned) =>
+                {
+                    for ((line, _), original) in range.iter_mut().zip(entry.original.split('\n')) {
+                        *line = original.to_string();
+                    }
+                    changed = true;
+                    restored += 1;
+                }
+                _ => warn!(
+                    "Not restoring {}:{}: the line changed since it was cleaned",
+                    label, entry.line_number
+                ),
+            }
+        }
+        if changed {
+            let output: String = std::iter::once(bom)
+                .chain(
+                    lines
+                        .iter()
+                        .flat_map(|(line, ending)| [line.as_str(), ending]),
+                )
+                .collect();
+            let output = decoded
+                .encode(&bytes, &output)
+                .ok_or_else(|| AppError::Unencodable(path.clone(), decoded.encoding))?;
+            write_atomic(&path, &output, false)
+                .map_err(|e| AppError::FileWrite(path.clone(), e))?;
+            info!("Restored: {}", path.display());
+        }
+    }
+    journal::remove(repo.path())?;
+    Ok(Some(restored))
 }
 
 fn find_git_root() -> Result<PathBuf, AppError> {
     let repo = Repository::discover(".").map_err(AppError::GitDiscovery)?;
     let workdir = repo.workdir().ok_or(AppError::BareRepo)?;
-    Ok(workdir.to_path_buf())
+    fs::canonicalize(workdir).map_err(|e| AppError::PathAccess(workdir.to_path_buf(), e))
+}
+
+/// The directory paths are resolved against: the repository's working tree, or
+/// the current directory with `--no-git`.
+fn find_root(cli: &Cli) -> Result<PathBuf, AppError> {
+    if cli.no_git {
+        fs::canonicalize(".").map_err(|e| AppError::PathAccess(PathBuf::from("."), e))
+    } else {
+        find_git_root()
+    }
 }
 
 fn is_git_dir(entry: &DirEntry) -> bool {
-    entry.file_name().to_str().map_or(false, |s| s == ".git")
+    entry.file_name().to_str() == Some(".git")
 }
 
+/// Resolves user-supplied paths to canonical locations inside the repository.
+/// An empty list means the whole repository.
+fn resolve_scopes(repo_root: &Path, paths: &[PathBuf]) -> Result<Vec<PathBuf>, AppError> {
+    if paths.is_empty() {
+        return Ok(vec![repo_root.to_path_buf()]);
+    }
+    paths
+        .iter()
+        .map(|path| {
+            let resolved =
+                fs::canonicalize(path).map_err(|e| AppError::PathAccess(path.clone(), e))?;
+            if !resolved.starts_with(repo_root) {
+                return Err(AppError::OutsideRepo(path.clone()));
+            }
+            Ok(resolved)
+        })
+        .collect()
+}
+
+/// How directories are walked and which files found are kept.
+#[derive(Debug, Clone, Copy)]
+struct Traversal {
+    follow_symlinks: bool,
+    max_depth: Option<usize>,
+    /// Whether dotfiles and dot-directories are processed.
+    hidden: bool,
+}
+
+/// Whether any component of `relative` is a dotfile or dot-directory.
+fn is_hidden(relative: &Path) -> bool {
+    relative.components().any(|c| {
+        c.as_os_str()
+            .to_str()
+            .is_some_and(|name| name.starts_with('.'))
+    })
+}
+
+/// Lists every file under `scopes`, skipping `.git` directories, nested
+/// working trees that are not being processed, directories whose contents
+/// are all excluded and, with `check_ignored`, directories ignored by git.
+fn walk_scopes(
+    scopes: &[PathBuf],
+    workspace: Option<&git::Workspace>,
+    check_ignored: bool,
+    traversal: Traversal,
+    repo_root: &Path,
+    resolver: &mut ConfigResolver,
+) -> Result<Vec<PathBuf>, AppError> {
+    let mut files = Vec::new();
+    for scope in scopes {
+        let mut walk = WalkDir::new(scope).follow_links(traversal.follow_symlinks);
+        if let Some(depth) = traversal.max_depth {
+            walk = walk.max_depth(depth);
+        }
+        let walker = walk.into_iter().filter_entry(|e| {
+            if is_git_dir(e) {
+                return false;
+            }
+            let is_dir = e.file_type().is_dir();
+            let skip_reason =
+                if e.depth() > 0 && !traversal.hidden && is_hidden(Path::new(e.file_name())) {
+                    Some("hidden")
+                } else if e.depth() > 0 && is_dir && is_excluded_dir(e.path(), repo_root, resolver)
+                {
+                    Some("matches an exclude pattern")
+                } else if let Some(workspace) = workspace.filter(|_| is_dir) {
+                    workspace.skip_reason(e.path()).or_else(|| {
+                        let ignored = check_ignored
+                            && timings::measure(Phase::IgnoreChecks, || {
+                                workspace.ignores_dir(e.path())
+                            });
+                        ignored.then_some("ignored by git")
+                    })
+                } else {
+                    None
+                };
+            if let Some(reason) = skip_reason {
+                debug!("Skipping {}: {}", e.path().display(), reason);
+            }
+            skip_reason.is_none()
+        });
+        for entry_result in walker {
+            let entry = match entry_result {
+                Ok(entry) => entry,
+                Err(err) if err.loop_ancestor().is_some() => {
+                    let path = err.path().unwrap_or(Path::new("")).display().to_string();
+                    warn!("Skipping {}: symbolic link loop", path);
+                    continue;
+                }
+                Err(err) => return Err(err.into()),
+            };
+            if !entry.path().is_dir() {
+                files.push(entry.into_path());
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Whether everything under `dir` is excluded by the settings in effect there,
+/// so the directory need not be walked. Errors loading the settings are left
+/// for file selection to report.
+fn is_excluded_dir(dir: &Path, repo_root: &Path, resolver: &mut ConfigResolver) -> bool {
+    let Some(relative) = dir.strip_prefix(repo_root).ok().and_then(Path::to_str) else {
+        return false;
+    };
+    resolver
+        .settings_for(dir)
+        .is_ok_and(|settings| settings.excludes_dir(&relative.replace('\\', "/")))
+}
+
+/// Lists the files to process. When `changed` holds repository-relative paths
+/// reported by git, only those are considered instead of walking the tree.
 fn list_non_ignored_files(
+    workspace: Option<&git::Workspace>,
+    check_ignored: bool,
     repo_root: &Path,
-    includes: &[String],
-    excludes: &[String],
+    paths: &[PathBuf],
+    changed: Option<&HashSet<PathBuf>>,
+    traversal: Traversal,
+    resolver: &mut ConfigResolver,
 ) -> Result<Vec<PathBuf>, AppError> {
-    let repo = Repository::open(repo_root)?;
-    let include_patterns: Result<Vec<Pattern>, _> =
-        includes.iter().map(|s| Pattern::new(s)).collect();
-    let include_patterns = include_patterns.map_err(AppError::InvalidGlob)?;
-    let exclude_patterns: Result<Vec<Pattern>, _> =
-        excludes.iter().map(|s| Pattern::new(s)).collect();
-    let exclude_patterns = exclude_patterns.map_err(AppError::InvalidGlob)?;
-    let mut non_ignored_files = Vec::new();
-    let walker = WalkDir::new(repo_root)
-        .into_iter()
-        .filter_entry(|e| !is_git_dir(e));
-    for entry_result in walker {
-        let entry = entry_result?;
-        if entry.path().is_dir() {
-            continue;
+    let scopes = resolve_scopes(repo_root, paths)?;
+    let candidates = match changed {
+        Some(changed) => changed
+            .iter()
+            .filter(|relative| traversal.hidden || !is_hidden(relative))
+            .map(|relative| repo_root.join(relative))
+            .filter(|path| scopes.iter().any(|scope| path.starts_with(scope)) && path.is_file())
+            .collect(),
+        None => timings::measure(Phase::Traversal, || {
+            walk_scopes(
+                &scopes,
+                workspace,
+                check_ignored,
+                traversal,
+                repo_root,
+                resolver,
+            )
+        })?,
+    };
+    let mut non_ignored_files = select_files(
+        workspace,
+        check_ignored,
+        repo_root,
+        candidates,
+        traversal,
+        resolver,
+    )?;
+    // Overlapping path arguments can yield the same file more than once
+    non_ignored_files.sort();
+    non_ignored_files.dedup();
+    Ok(non_ignored_files)
+}
+
+/// Lists the files named by `--files-from`, in the order given. Paths are not
+/// walked, but the config file, ignored files and the include and exclude
+/// patterns are still skipped.
+fn list_given_files(
+    workspace: Option<&git::Workspace>,
+    repo_root: &Path,
+    listed: &[PathBuf],
+    traversal: Traversal,
+    resolver: &mut ConfigResolver,
+) -> Result<Vec<PathBuf>, AppError> {
+    if listed.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut candidates = Vec::new();
+    for path in resolve_scopes(repo_root, listed)? {
+        if path.is_dir() {
+            debug!("Skipping {}: not a file", path.display());
+        } else if !candidates.contains(&path) {
+            candidates.push(path);
         }
-        let relative_path = match entry.path().strip_prefix(repo_root) {
+    }
+    select_files(workspace, true, repo_root, candidates, traversal, resolver)
+}
+
+/// Reads newline-separated paths from `source`, or from stdin when it is `-`.
+fn read_file_list(source: &Path) -> Result<Vec<PathBuf>, AppError> {
+    let text = if source == Path::new("-") {
+        let mut text = String::new();
+        io::stdin()
+            .read_to_string(&mut text)
+            .map_err(AppError::StdinRead)?;
+        text
+    } else {
+        fs::read_to_string(source).map_err(|e| AppError::FileRead(source.to_path_buf(), e))?
+    };
+    Ok(text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(PathBuf::from)
+        .collect())
+}
+
+/// Keeps the candidates that are not the config file, match the include and
+/// exclude patterns in effect for their directory and, in a git workspace, are
+/// not ruled out by `.gitattributes` or (with `check_ignored`) ignored by the
+/// repository that owns them. Symlinks are skipped unless followed.
+fn select_files(
+    workspace: Option<&git::Workspace>,
+    check_ignored: bool,
+    repo_root: &Path,
+    candidates: Vec<PathBuf>,
+    traversal: Traversal,
+    resolver: &mut ConfigResolver,
+) -> Result<Vec<PathBuf>, AppError> {
+    let mut non_ignored_files = Vec::new();
+    for path in candidates {
+        let relative_path = match path.strip_prefix(repo_root) {
             Ok(p) => p,
             Err(_) => continue,
         };
         if relative_path.as_os_str().is_empty() {
             continue;
         }
-        if repo.is_path_ignored(relative_path)? {
+        // The config file holds markers by design; cleaning it would corrupt them
+        if path
+            .file_name()
+            .is_some_and(|name| name == CONFIG_FILE_NAME)
+        {
+            debug!("Skipping {}: configuration file", relative_path.display());
             continue;
         }
+        if let Some((repo, owned_path)) = workspace.and_then(|workspace| workspace.owner(&path)) {
+            let ignored = check_ignored
+                && timings::measure(Phase::IgnoreChecks, || repo.is_path_ignored(owned_path))?;
+            if ignored {
+                debug!("Skipping {}: ignored by git", relative_path.display());
+                continue;
+            }
+            let attribute_reason = timings::measure(Phase::IgnoreChecks, || {
+                git::attribute_skip_reason(repo, owned_path)
+            })?;
+            if let Some(reason) = attribute_reason {
+                debug!("Skipping {}: {}", relative_path.display(), reason);
+                continue;
+            }
+        }
         let relative_path_str = match relative_path.to_str() {
             Some(s) => s.replace('\\', "/"),
-            None => continue, // Skip non-UTF8 paths
+            None => {
+                debug!(
+                    "Skipping {}: path is not valid UTF-8",
+                    relative_path.display()
+                );
+                continue;
+            }
         };
+        let settings = resolver.settings_for(path.parent().unwrap_or(repo_root))?;
         let mut is_excluded = false;
-        for pattern in &exclude_patterns {
+        for pattern in &settings.excludes {
             if pattern.matches(&relative_path_str) {
                 is_excluded = true;
                 break;
             }
         }
         if is_excluded {
+            debug!("Skipping {}: matches an exclude pattern", relative_path_str);
             continue;
         }
-        if include_patterns.is_empty() {
-            non_ignored_files.push(entry.path().to_path_buf());
-        } else {
-            let mut is_included = false;
-            for pattern in &include_patterns {
-                if pattern.matches(&relative_path_str) {
-                    is_included = true;
-                    break;
-                }
-            }
-            if is_included {
-                non_ignored_files.push(entry.path().to_path_buf());
-            }
+        let is_included = |relative: &str| {
+            settings
+                .includes
+                .iter()
+                .any(|pattern| pattern.matches(relative))
+        };
+        // Files whose content names their language are included as if their
+        // name ended in its extension, so `*.py` takes in Python scripts
+        let is_included = settings.includes.is_empty()
+            || is_included(&relative_path_str)
+            || path
+                .extension()
+                .is_none_or(|ext| is_ambiguous_extension(&ext.to_string_lossy()))
+                && Some(file_language(&path))
+                    .filter(|language| !is_ambiguous_extension(language))
+                    .is_some_and(|ext| is_included(&format!("{}.{}", relative_path_str, ext)));
+        if !is_included {
+            debug!("Skipping {}: matches no include pattern", relative_path_str);
+            continue;
+        }
+        if !traversal.follow_symlinks && path.is_symlink() {
+            warn!(
+                "Skipping {}: symbolic link (use --follow-symlinks to clean its target)",
+                relative_path_str
+            );
+            continue;
         }
+        non_ignored_files.push(path);
     }
     Ok(non_ignored_files)
 }
 
+/// The repository-relative files selected by `--staged`, `--modified`,
+/// `--since` or `--ls-files` (or `None` to walk the tree), and the lines added
+/// in each when `--changed-lines-only` is given.
+fn changed_files(
+    cli: &Cli,
+    workspace: Option<&git::Workspace>,
+) -> Result<(Option<HashSet<PathBuf>>, Option<git::AddedLines>), AppError> {
+    let Some(workspace) = workspace else {
+        return Ok((None, None));
+    };
+    if cli.ls_files {
+        let listed = workspace.listed_paths().map_err(AppError::Git)?;
+        return Ok((Some(listed), None));
+    }
+    let repo = workspace.root_repo();
+    let Some(selection) = cli.selection() else {
+        return Ok((None, None));
+    };
+    let paths = selection.paths(repo).map_err(AppError::Git)?;
+    let added_lines = if cli.changed_lines_only {
+        Some(selection.added_lines(repo).map_err(AppError::Git)?)
+    } else {
+        None
+    };
+    Ok((Some(paths), added_lines))
+}
+
+/// The config for the files under `root`, with the command-line options
+/// layered on top. Exits when an `EMOJI_REMOVER_*` variable is invalid.
+fn config_resolver(cli: &Cli, root: Option<PathBuf>) -> ConfigResolver {
+    match cli.overrides() {
+        Ok(overrides) => ConfigResolver::new(root, overrides),
+        Err(err) => {
+            error!("{}", err);
+            process::exit(1);
+        }
+    }
+}
+
 fn main() {
     let cli = Cli::parse();
-    let root = match find_git_root() {
+    logging::init(cli.verbose, cli.quiet);
+    if cli.timings {
+        timings::enable();
+    }
+    if cli.diff && cli.format != OutputFormat::Text {
+        Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--diff can only be used with the text output format",
+            )
+            .exit();
+    }
+    if let Some(Command::InstallHook { fix, commit_msg }) = cli.command {
+        let hook = if commit_msg {
+            hook::Hook::CommitMsg
+        } else {
+            hook::Hook::PreCommit { fix }
+        };
+        let result = Repository::discover(".")
+            .map_err(AppError::GitDiscovery)
+            .and_then(|repo| hook::install(&repo, hook));
+        match result {
+            Ok(path) => info!("Installed hook: {}", path.display()),
+            Err(err) => {
+                error!("Error installing hook: {}", err);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+    if let Some(Command::Undo) = cli.command {
+        match undo(cli.decoder()) {
+            Ok(Some(restored)) => info!("Restored {} comments", restored),
+            Ok(None) => info!("Nothing to undo"),
+            Err(err) => {
+                error!("Error undoing the last run: {}", err);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+    let mode = cli.mode();
+    if let Some(path) = &cli.commit_msg {
+        let mut resolver = config_resolver(&cli, find_root(&cli).ok());
+        let result = resolver
+            .settings_for(Path::new(""))
+            .and_then(|settings| process_commit_msg(path, &settings.markers, mode));
+        match result {
+            Ok(found) => {
+                if mode == Mode::Check && found {
+                    process::exit(EXIT_MARKERS_FOUND);
+                }
+                return;
+            }
+            Err(err) => {
+                error!(
+                    "Error processing commit message {}: {}",
+                    path.display(),
+                    err
+                );
+                process::exit(1);
+            }
+        }
+    }
+    if cli.rpc {
+        let mut resolver = config_resolver(&cli, find_root(&cli).ok());
+        let result = resolver
+            .settings_for(Path::new(""))
+            .and_then(|settings| rpc::serve(&settings).map_err(AppError::Rpc));
+        if let Err(err) = result {
+            error!("{}", err);
+            process::exit(1);
+        }
+        return;
+    }
+    if cli.lsp {
+        let root = find_root(&cli).ok();
+        let workspace = match &root {
+            Some(root) if !cli.no_git => git::Workspace::open(root, cli.recurse_submodules).ok(),
+            _ => None,
+        };
+        let mut resolver = config_resolver(&cli, root.clone());
+        let traversal = cli.traversal();
+        let result = lsp::serve(&mut resolver, |resolver| match &root {
+            Some(root) => list_non_ignored_files(
+                workspace.as_ref(),
+                workspace.is_some(),
+                root,
+                &[],
+                None,
+                traversal,
+                resolver,
+            ),
+            None => Ok(Vec::new()),
+        })
+        .map_err(AppError::Lsp);
+        if let Err(err) = result {
+            error!("{}", err);
+            process::exit(1);
+        }
+        return;
+    }
+    if cli.stdin {
+        // stdin works outside a repository, but still honors the project config when inside one
+        let resolver = config_resolver(&cli, find_root(&cli).ok());
+        let mut session = Session::new(&cli, PathBuf::new(), resolver);
+        let result = if cli.paths.len() > 1 {
+            Err(AppError::StdinPaths)
+        } else {
+            process_stdin(
+                cli.paths.first().map(PathBuf::as_path),
+                cli.filetype.as_deref(),
+                &mut session,
+            )
+        };
+        match result {
+            Ok(found) => {
+                session.finish();
+                if mode == Mode::Check && found {
+                    process::exit(EXIT_MARKERS_FOUND);
+                }
+                return;
+            }
+            Err(err) => {
+                error!("Error processing stdin: {}", err);
+                session.emit(Event::Error {
+                    path: "<stdin>",
+                    message: err.to_string(),
+                });
+                process::exit(1);
+            }
+        }
+    }
+    let root = match timings::measure(Phase::Discovery, || find_root(&cli)) {
         Ok(path) => path,
         Err(err) => {
-            eprintln!("Error finding git root: {}", err);
+            error!(
+                "Error finding git root: {} (use --no-git outside a repository)",
+                err
+            );
+            process::exit(1);
+        }
+    };
+    let workspace = if cli.no_git {
+        None
+    } else {
+        let opened = timings::measure(Phase::Discovery, || {
+            git::Workspace::open(&root, cli.recurse_submodules)
+        });
+        match opened {
+            Ok(workspace) => Some(workspace),
+            Err(err) => {
+                error!("Error opening git repository: {}", err);
+                process::exit(1);
+            }
+        }
+    };
+    let mut resolver = config_resolver(&cli, Some(root.clone()));
+    let changed = timings::measure(Phase::Traversal, || changed_files(&cli, workspace.as_ref()));
+    let (changed, added_lines) = match changed {
+        Ok(changed) => changed,
+        Err(err) => {
+            error!("Error reading git status: {}", err);
             process::exit(1);
         }
     };
-    let files_to_process = match list_non_ignored_files(&root, &cli.include, &cli.exclude) {
+    let listed = match &cli.files_from {
+        Some(source) => read_file_list(source).and_then(|listed| {
+            list_given_files(
+                workspace.as_ref(),
+                &root,
+                &listed,
+                cli.traversal(),
+                &mut resolver,
+            )
+        }),
+        // The index listing already reflects git's ignore rules
+        None => list_non_ignored_files(
+            workspace.as_ref(),
+            !cli.ls_files,
+            &root,
+            &cli.paths,
+            changed.as_ref(),
+            cli.traversal(),
+            &mut resolver,
+        ),
+    };
+    let files_to_process = match listed {
         Ok(files) => files,
         Err(err) => {
-            eprintln!("Error listing files: {}", err);
+            error!("Error listing files: {}", err);
+            process::exit(1);
+        }
+    };
+    let author = match cli.author.as_deref().map(regex::Regex::new).transpose() {
+        Ok(pattern) => pattern.map(git::AuthorFilter::new),
+        Err(err) => {
+            error!("{}", err);
             process::exit(1);
         }
     };
+    let staged = if cli.staged {
+        match git::StagedFiles::open(&root) {
+            Ok(staged) => Some(staged),
+            Err(err) => {
+                error!("Error opening git index: {}", err);
+                process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
+    let restager = if cli.staged && matches!(mode, Mode::Write | Mode::Interactive) {
+        match git::Restager::open(&root) {
+            Ok(restager) => Some(restager),
+            Err(err) => {
+                error!("Error opening git index: {}", err);
+                process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
+    let committer = match cli
+        .commit
+        .as_ref()
+        .map(|_| git::Committer::open(&root))
+        .transpose()
+    {
+        Ok(committer) => committer,
+        Err(err) => {
+            error!("Error opening git repository: {}", err);
+            process::exit(1);
+        }
+    };
+    let journal = workspace
+        .as_ref()
+        .map(|workspace| journal::Journal::new(workspace.root_repo().path()));
+    let mut session = Session::new(&cli, root, resolver);
+    session.staged = staged;
+    session.restager = restager;
+    session.added_lines = added_lines;
+    session.author = author;
+    session.committer = committer;
+    session.journal = journal;
+    session.cache = workspace
+        .as_ref()
+        .filter(|_| !cli.no_cache)
+        .map(|workspace| cache::Cache::load(workspace.root_repo().path()));
+    #[cfg(unix)]
+    if cli.daemon {
+        let socket = cli.socket.clone().or_else(|| {
+            workspace
+                .as_ref()
+                .map(|workspace| daemon::location(workspace.root_repo().path()))
+        });
+        let Some(socket) = socket else {
+            error!("--daemon needs --socket outside a git repository");
+            process::exit(1);
+        };
+        let result = daemon::serve(&socket, files_to_process, &mut session, |session| {
+            let root = session.root.clone();
+            list_non_ignored_files(
+                workspace.as_ref(),
+                !cli.ls_files,
+                &root,
+                &cli.paths,
+                None,
+                cli.traversal(),
+                &mut session.resolver,
+            )
+        });
+        if let Err(err) = result {
+            error!("{}", AppError::Daemon(err));
+            process::exit(1);
+        }
+        return;
+    }
     if files_to_process.is_empty() {
-        eprintln!("No files found matching criteria.");
+        info!("No files found matching criteria.");
+        session.finish();
         return;
     }
-    eprintln!("Found {} files to process...", files_to_process.len());
+    info!("Found {} files to process...", files_to_process.len());
+    if cli.stash_first && matches!(mode, Mode::Write | Mode::Interactive) {
+        match Repository::open(&session.root).and_then(|repo| git::snapshot_worktree(&repo)) {
+            Ok(id) => info!(
+                "Saved a snapshot of the working tree as {} ({}). Restore it with:\n  git restore --source={} --worktree -- .",
+                id,
+                git::SNAPSHOT_REF,
+                id
+            ),
+            Err(err) => {
+                error!("Error saving a snapshot of the working tree: {}", err);
+                process::exit(1);
+            }
+        }
+    }
+    let mut found_markers = false;
+    // Prompts and progress would fight over the terminal
+    let mut progress = (!cli.quiet && mode != Mode::Interactive && io::stderr().is_terminal())
+        .then(|| progress::Progress::new(files_to_process.len()));
     for file_path in files_to_process {
-        if let Err(e) = process_file(&file_path) {
-            eprintln!("Error processing file {}: {}", file_path.display(), e);
+        if session.prompt.quit {
+            break;
         }
+        let label = session.label(&file_path);
+        if let Some(progress) = &mut progress {
+            progress.start(&label);
+        }
+        match timings::measure_file(&label, || process_file(&file_path, &mut session)) {
+            Ok(found) => found_markers |= found,
+            Err(e) => {
+                error!("Error processing file {}: {}", file_path.display(), e);
+                session.emit(Event::Error {
+                    path: &session.label(&file_path),
+                    message: e.to_string(),
+                });
+            }
+        }
+        if let Some(progress) = &mut progress {
+            progress.finish_file();
+        }
+    }
+    drop(progress);
+    if let Some(cache) = &session.cache
+        && let Err(err) = cache.save()
+    {
+        warn!("Could not save the cache: {}", err);
+    }
+    if let Some(restager) = &mut session.restager
+        && let Err(err) = restager.write()
+    {
+        error!("Error writing git index: {}", err);
+        process::exit(1);
+    }
+    if let (Some(committer), Some(message)) = (&mut session.committer, &cli.commit) {
+        match committer.commit(message) {
+            Ok(Some(id)) => info!("Committed cleanup as {}", id),
+            Ok(None) => {}
+            Err(err) => {
+                error!("Error committing cleaned files: {}", err);
+                process::exit(1);
+            }
+        }
+    }
+    info!("Done.");
+    session.finish();
+    if (mode == Mode::Check && found_markers) || session.left_marked {
+        process::exit(EXIT_MARKERS_FOUND);
     }
-    eprintln!("Done.");
 }
-
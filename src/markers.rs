@@ -0,0 +1,127 @@
+use crate::emoji;
+use crate::regex::{Regex, RegexError};
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+pub const DEFAULT_MARKER: &str = "‼️";
+
+/// The strings that mark a comment for removal.
+pub struct Markers {
+    literals: Vec<String>,
+    /// Treat any emoji as a marker.
+    all_emojis: bool,
+    patterns: Vec<Regex>,
+    /// Whether marked doc comments are removed too.
+    doc_comments: bool,
+    /// Whether marked directives are removed too.
+    directives: bool,
+    fingerprint: u64,
+}
+
+impl Default for Markers {
+    fn default() -> Markers {
+        Markers::new(None, None, false).expect("no patterns to compile")
+    }
+}
+
+impl Markers {
+    /// Builds the markers from literal `markers` and `marker_regex` patterns.
+    /// Without either, the default marker is used.
+    pub fn new(
+        markers: Option<&[String]>,
+        marker_regex: Option<&[String]>,
+        all_emojis: bool,
+    ) -> Result<Self, RegexError> {
+        let patterns = marker_regex
+            .iter()
+            .copied()
+            .flatten()
+            .map(|p| Regex::new(p))
+            .collect::<Result<_, _>>()?;
+        let literals = match (markers, marker_regex) {
+            (Some(markers), _) => markers.to_vec(),
+            (None, Some(_)) => Vec::new(),
+            (None, None) => vec![DEFAULT_MARKER.to_string()],
+        };
+        let mut hasher = DefaultHasher::new();
+        (&literals, all_emojis, marker_regex).hash(&mut hasher);
+        Ok(Markers {
+            literals,
+            all_emojis,
+            patterns,
+            doc_comments: false,
+            directives: false,
+            fingerprint: hasher.finish(),
+        })
+    }
+
+    /// Whether marked doc comments, such as Rust's `///` and `//!`, Javadoc's
+    /// `/** */` or Python docstrings, are removed too. They are left alone by default,
+    /// as they are part of the generated API documentation.
+    pub fn with_doc_comments(mut self, doc_comments: bool) -> Self {
+        self.doc_comments = doc_comments;
+        self
+    }
+
+    pub fn doc_comments(&self) -> bool {
+        self.doc_comments
+    }
+
+    /// Whether marked directives, comments read by tools such as Go's
+    /// `//go:build` constraints, are removed too. They are left alone by
+    /// default.
+    pub fn with_directives(mut self, directives: bool) -> Self {
+        self.directives = directives;
+        self
+    }
+
+    pub fn directives(&self) -> bool {
+        self.directives
+    }
+
+    /// Identifies this set of markers, to tell whether results obtained with
+    /// another set still apply.
+    pub fn fingerprint(&self) -> u64 {
+        if !self.doc_comments && !self.directives {
+            return self.fingerprint;
+        }
+        let mut hasher = DefaultHasher::new();
+        (self.fingerprint, self.doc_comments, self.directives).hash(&mut hasher);
+        hasher.finish()
+    }
+
+    pub fn matches(&self, comment: &str) -> bool {
+        self.literals.iter().any(|m| comment.contains(m.as_str()))
+            || (self.all_emojis && emoji::contains_emoji(comment))
+            || self.patterns.iter().any(|p| p.is_match(comment))
+    }
+
+    /// Whether `text`, a whole file, may hold a marker at all. A cheap scan run
+    /// before comments are parsed; regular expressions are not prescanned,
+    /// as they may depend on where a comment starts.
+    pub fn may_match(&self, text: &str) -> bool {
+        !self.patterns.is_empty()
+            || self.literals.iter().any(|m| text.contains(m.as_str()))
+            || (self.all_emojis && emoji::contains_emoji(text))
+    }
+
+    /// Removes every literal marker from `text`, joining the words on either
+    /// side with a single space.
+    pub fn strip_literals(&self, text: &str) -> String {
+        let mut out = text.to_string();
+        for marker in &self.literals {
+            while let Some(start) = out.find(marker.as_str()) {
+                let before = out[..start].trim_end();
+                let after = out[start + marker.len()..].trim_start();
+                let separator = if before.is_empty() || after.is_empty() {
+                    ""
+                } else {
+                    " "
+                };
+                // Keep the indentation of a marker-led line
+                let indent = if before.is_empty() { &out[..start] } else { "" };
+                out = format!("{}{}{}{}", indent, before, separator, after);
+            }
+        }
+        out
+    }
+}
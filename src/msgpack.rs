@@ -0,0 +1,390 @@
+//! The subset of MessagePack needed to talk msgpack-RPC with Neovim.
+//!
+//! Every format is read; extension values are kept as raw bytes. Integers
+//! beyond the range of `i64` are rejected, as Neovim never sends them.
+
+use std::io::{self, Read};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    /// A string that is valid UTF-8. Strings that are not are read as `Bin`.
+    Str(String),
+    Bin(Vec<u8>),
+    Array(Vec<Value>),
+    Map(Vec<(Value, Value)>),
+    Ext(i8, Vec<u8>),
+}
+
+impl Value {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            Value::Int(n) => Some(*n),
+            _ => None,
+        }
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Value {
+        Value::Str(s.to_string())
+    }
+}
+
+impl From<usize> for Value {
+    fn from(n: usize) -> Value {
+        Value::Int(i64::try_from(n).unwrap_or(i64::MAX))
+    }
+}
+
+/// Reads one value, or `None` if `input` ends before it starts.
+pub fn read(input: &mut impl Read) -> io::Result<Option<Value>> {
+    let mut marker = [0];
+    if input.read(&mut marker)? == 0 {
+        return Ok(None);
+    }
+    read_after(input, marker[0]).map(Some)
+}
+
+fn read_value(input: &mut impl Read) -> io::Result<Value> {
+    let marker = read_bytes::<1>(input)?[0];
+    read_after(input, marker)
+}
+
+fn read_after(input: &mut impl Read, marker: u8) -> io::Result<Value> {
+    Ok(match marker {
+        0x00..=0x7f => Value::Int(i64::from(marker)),
+        0x80..=0x8f => read_map(input, usize::from(marker & 0x0f))?,
+        0x90..=0x9f => read_array(input, usize::from(marker & 0x0f))?,
+        0xa0..=0xbf => read_str(input, usize::from(marker & 0x1f))?,
+        0xc0 => Value::Nil,
+        0xc2 => Value::Bool(false),
+        0xc3 => Value::Bool(true),
+        0xc4 => {
+            let len = read_len::<1>(input)?;
+            Value::Bin(read_vec(input, len)?)
+        }
+        0xc5 => {
+            let len = read_len::<2>(input)?;
+            Value::Bin(read_vec(input, len)?)
+        }
+        0xc6 => {
+            let len = read_len::<4>(input)?;
+            Value::Bin(read_vec(input, len)?)
+        }
+        0xc7 => {
+            let len = read_len::<1>(input)?;
+            read_ext(input, len)?
+        }
+        0xc8 => {
+            let len = read_len::<2>(input)?;
+            read_ext(input, len)?
+        }
+        0xc9 => {
+            let len = read_len::<4>(input)?;
+            read_ext(input, len)?
+        }
+        0xca => Value::Float(f64::from(f32::from_be_bytes(read_bytes(input)?))),
+        0xcb => Value::Float(f64::from_be_bytes(read_bytes(input)?)),
+        0xcc => Value::Int(i64::from(read_bytes::<1>(input)?[0])),
+        0xcd => Value::Int(i64::from(u16::from_be_bytes(read_bytes(input)?))),
+        0xce => Value::Int(i64::from(u32::from_be_bytes(read_bytes(input)?))),
+        0xcf => {
+            let n = u64::from_be_bytes(read_bytes(input)?);
+            Value::Int(i64::try_from(n).map_err(|_| invalid("integer out of range"))?)
+        }
+        0xd0 => Value::Int(i64::from(i8::from_be_bytes(read_bytes(input)?))),
+        0xd1 => Value::Int(i64::from(i16::from_be_bytes(read_bytes(input)?))),
+        0xd2 => Value::Int(i64::from(i32::from_be_bytes(read_bytes(input)?))),
+        0xd3 => Value::Int(i64::from_be_bytes(read_bytes(input)?)),
+        0xd4 => read_ext(input, 1)?,
+        0xd5 => read_ext(input, 2)?,
+        0xd6 => read_ext(input, 4)?,
+        0xd7 => read_ext(input, 8)?,
+        0xd8 => read_ext(input, 16)?,
+        0xd9 => {
+            let len = read_len::<1>(input)?;
+            read_str(input, len)?
+        }
+        0xda => {
+            let len = read_len::<2>(input)?;
+            read_str(input, len)?
+        }
+        0xdb => {
+            let len = read_len::<4>(input)?;
+            read_str(input, len)?
+        }
+        0xdc => {
+            let len = read_len::<2>(input)?;
+            read_array(input, len)?
+        }
+        0xdd => {
+            let len = read_len::<4>(input)?;
+            read_array(input, len)?
+        }
+        0xde => {
+            let len = read_len::<2>(input)?;
+            read_map(input, len)?
+        }
+        0xdf => {
+            let len = read_len::<4>(input)?;
+            read_map(input, len)?
+        }
+        0xe0..=0xff => Value::Int(i64::from(marker as i8)),
+        0xc1 => return Err(invalid("reserved marker 0xc1")),
+    })
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn read_bytes<const N: usize>(input: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    input.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Reads an `N`-byte big-endian length.
+fn read_len<const N: usize>(input: &mut impl Read) -> io::Result<usize> {
+    let bytes = read_bytes::<N>(input)?;
+    let len = bytes.iter().fold(0u64, |len, &b| len << 8 | u64::from(b));
+    usize::try_from(len).map_err(|_| invalid("length out of range"))
+}
+
+fn read_vec(input: &mut impl Read, len: usize) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    input.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() < len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(bytes)
+}
+
+fn read_str(input: &mut impl Read, len: usize) -> io::Result<Value> {
+    let bytes = read_vec(input, len)?;
+    Ok(match String::from_utf8(bytes) {
+        Ok(s) => Value::Str(s),
+        Err(err) => Value::Bin(err.into_bytes()),
+    })
+}
+
+fn read_ext(input: &mut impl Read, len: usize) -> io::Result<Value> {
+    let kind = i8::from_be_bytes(read_bytes(input)?);
+    Ok(Value::Ext(kind, read_vec(input, len)?))
+}
+
+fn read_array(input: &mut impl Read, len: usize) -> io::Result<Value> {
+    let mut items = Vec::new();
+    for _ in 0..len {
+        items.push(read_value(input)?);
+    }
+    Ok(Value::Array(items))
+}
+
+fn read_map(input: &mut impl Read, len: usize) -> io::Result<Value> {
+    let mut entries = Vec::new();
+    for _ in 0..len {
+        let key = read_value(input)?;
+        entries.push((key, read_value(input)?));
+    }
+    Ok(Value::Map(entries))
+}
+
+/// Appends the encoding of `value` to `out`, always in the smallest format.
+pub fn write(out: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Nil => out.push(0xc0),
+        Value::Bool(b) => out.push(if *b { 0xc3 } else { 0xc2 }),
+        Value::Int(n) => write_int(out, *n),
+        Value::Float(f) => {
+            out.push(0xcb);
+            out.extend_from_slice(&f.to_be_bytes());
+        }
+        Value::Str(s) => {
+            write_len(out, s.len(), Some(0xa0), [0xd9, 0xda, 0xdb]);
+            out.extend_from_slice(s.as_bytes());
+        }
+        Value::Bin(bytes) => {
+            write_len(out, bytes.len(), None, [0xc4, 0xc5, 0xc6]);
+            out.extend_from_slice(bytes);
+        }
+        Value::Array(items) => {
+            write_len(out, items.len(), Some(0x90), [0xdc, 0xdc, 0xdd]);
+            for item in items {
+                write(out, item);
+            }
+        }
+        Value::Map(entries) => {
+            write_len(out, entries.len(), Some(0x80), [0xde, 0xde, 0xdf]);
+            for (key, value) in entries {
+                write(out, key);
+                write(out, value);
+            }
+        }
+        Value::Ext(kind, bytes) => {
+            write_len(out, bytes.len(), None, [0xc7, 0xc8, 0xc9]);
+            out.push(*kind as u8);
+            out.extend_from_slice(bytes);
+        }
+    }
+}
+
+fn write_int(out: &mut Vec<u8>, n: i64) {
+    match n {
+        -32..=0x7f => out.push(n as u8),
+        0x80..=0xff => out.extend_from_slice(&[0xcc, n as u8]),
+        0x100..=0xffff => {
+            out.push(0xcd);
+            out.extend_from_slice(&(n as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            out.push(0xce);
+            out.extend_from_slice(&(n as u32).to_be_bytes());
+        }
+        _ if n >= 0 => {
+            out.push(0xcf);
+            out.extend_from_slice(&(n as u64).to_be_bytes());
+        }
+        -0x80..=-33 => out.extend_from_slice(&[0xd0, n as u8]),
+        -0x8000..=-0x81 => {
+            out.push(0xd1);
+            out.extend_from_slice(&(n as i16).to_be_bytes());
+        }
+        -0x8000_0000..=-0x8001 => {
+            out.push(0xd2);
+            out.extend_from_slice(&(n as i32).to_be_bytes());
+        }
+        _ => {
+            out.push(0xd3);
+            out.extend_from_slice(&n.to_be_bytes());
+        }
+    }
+}
+
+/// Writes a length with the fix format starting at `fix` when it fits in
+/// its bits, else with the 8-, 16- or 32-bit format in `markers`. Formats
+/// without an 8-bit length repeat their 16-bit marker there.
+fn write_len(out: &mut Vec<u8>, len: usize, fix: Option<u8>, markers: [u8; 3]) {
+    let fix_limit = match fix {
+        Some(0xa0) => 32,
+        Some(_) => 16,
+        None => 0,
+    };
+    match (fix, len) {
+        (Some(fix), len) if len < fix_limit => out.push(fix | len as u8),
+        (_, 0..=0xff) if markers[0] != markers[1] => {
+            out.extend_from_slice(&[markers[0], len as u8])
+        }
+        (_, 0..=0xffff) => {
+            out.push(markers[1]);
+            out.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        _ => {
+            out.push(markers[2]);
+            out.extend_from_slice(&(len as u32).to_be_bytes());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(value: &Value) -> Vec<u8> {
+        let mut out = Vec::new();
+        write(&mut out, value);
+        out
+    }
+
+    fn decode(mut bytes: &[u8]) -> io::Result<Option<Value>> {
+        read(&mut bytes)
+    }
+
+    #[test]
+    fn integers_use_the_smallest_format() {
+        for (n, head) in [
+            (0, vec![0x00]),
+            (0x7f, vec![0x7f]),
+            (-32, vec![0xe0]),
+            (0x80, vec![0xcc]),
+            (0xffff, vec![0xcd]),
+            (0x1_0000, vec![0xce]),
+            (1 << 32, vec![0xcf]),
+            (-33, vec![0xd0]),
+            (-0x81, vec![0xd1]),
+            (-0x8001, vec![0xd2]),
+            (i64::MIN, vec![0xd3]),
+        ] {
+            let bytes = encode(&Value::Int(n));
+            assert_eq!(bytes[..1], head[..], "{}", n);
+            assert_eq!(decode(&bytes).unwrap(), Some(Value::Int(n)));
+        }
+    }
+
+    #[test]
+    fn lengths_pick_the_format() {
+        for (len, head) in [(31, 0xbf), (32, 0xd9), (0x100, 0xda), (0x1_0000, 0xdb)] {
+            let value = Value::Str("a".repeat(len));
+            let bytes = encode(&value);
+            assert_eq!(bytes[0], head, "{}", len);
+            assert_eq!(decode(&bytes).unwrap(), Some(value));
+        }
+        for (len, head) in [(15, 0x9f), (16, 0xdc), (0x1_0000, 0xdd)] {
+            let value = Value::Array(vec![Value::Nil; len]);
+            let bytes = encode(&value);
+            assert_eq!(bytes[0], head, "{}", len);
+            assert_eq!(decode(&bytes).unwrap(), Some(value));
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        let value = Value::Map(vec![
+            (
+                Value::from("lines"),
+                Value::Array(vec![Value::from("é"), Value::Nil]),
+            ),
+            (Value::Bool(true), Value::Float(1.5)),
+            (Value::Bin(vec![0, 255]), Value::Ext(-1, vec![1, 2, 3])),
+        ]);
+        assert_eq!(decode(&encode(&value)).unwrap(), Some(value));
+    }
+
+    #[test]
+    fn reads_other_formats() {
+        assert_eq!(
+            decode(&[0xca, 0x3f, 0xc0, 0, 0]).unwrap(),
+            Some(Value::Float(1.5))
+        );
+        assert_eq!(decode(&[0xd4, 5, 9]).unwrap(), Some(Value::Ext(5, vec![9])));
+        assert_eq!(decode(&[0xa1, 0xff]).unwrap(), Some(Value::Bin(vec![0xff])));
+        assert_eq!(decode(&[]).unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_invalid_input() {
+        let kind = |bytes: &[u8]| decode(bytes).unwrap_err().kind();
+        assert_eq!(kind(&[0xc1]), io::ErrorKind::InvalidData);
+        assert_eq!(
+            kind(&[0xcf, 0xff, 0, 0, 0, 0, 0, 0, 0]),
+            io::ErrorKind::InvalidData
+        );
+        assert_eq!(kind(&[0xa3, b'a']), io::ErrorKind::UnexpectedEof);
+        assert_eq!(kind(&[0x92, 0x01]), io::ErrorKind::UnexpectedEof);
+        assert_eq!(
+            kind(&[0xdb, 0xff, 0xff, 0xff, 0xff]),
+            io::ErrorKind::UnexpectedEof
+        );
+    }
+}
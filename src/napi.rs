@@ -0,0 +1,218 @@
+//! Node.js interface to the cleaning engine, loaded by `node/index.js`.
+//!
+//! A Node-API module written against the stable C interface Node exports to
+//! addons, whose functions are resolved when Node loads the library.
+//! JavaScript sees one function:
+//!
+//! ```js
+//! clean(text, language, marker?)
+//! // => { output, changes: [{ line, byteRange, colRange, removedText, commentKind }] }
+//! ```
+
+use crate::markers::Markers;
+use crate::{CleanOptions, clean_source};
+use std::ffi::{CStr, c_char, c_void};
+use std::ops::Range;
+use std::ptr;
+
+type Env = *mut c_void;
+type Value = *mut c_void;
+type CallbackInfo = *mut c_void;
+type Callback = unsafe extern "C" fn(Env, CallbackInfo) -> Value;
+
+const NAPI_OK: i32 = 0;
+const NAPI_UNDEFINED: i32 = 0;
+const NAPI_NULL: i32 = 1;
+const NAPI_STRING: i32 = 4;
+
+unsafe extern "C" {
+    fn napi_get_cb_info(
+        env: Env,
+        info: CallbackInfo,
+        argc: *mut usize,
+        argv: *mut Value,
+        this_arg: *mut Value,
+        data: *mut *mut c_void,
+    ) -> i32;
+    fn napi_typeof(env: Env, value: Value, result: *mut i32) -> i32;
+    fn napi_get_value_string_utf8(
+        env: Env,
+        value: Value,
+        buf: *mut c_char,
+        bufsize: usize,
+        result: *mut usize,
+    ) -> i32;
+    fn napi_create_string_utf8(env: Env, s: *const c_char, len: usize, result: *mut Value) -> i32;
+    fn napi_create_uint32(env: Env, value: u32, result: *mut Value) -> i32;
+    fn napi_create_object(env: Env, result: *mut Value) -> i32;
+    fn napi_create_array_with_length(env: Env, len: usize, result: *mut Value) -> i32;
+    fn napi_set_named_property(env: Env, object: Value, name: *const c_char, value: Value) -> i32;
+    fn napi_set_element(env: Env, object: Value, index: u32, value: Value) -> i32;
+    fn napi_create_function(
+        env: Env,
+        name: *const c_char,
+        len: usize,
+        cb: Callback,
+        data: *mut c_void,
+        result: *mut Value,
+    ) -> i32;
+    fn napi_throw_type_error(env: Env, code: *const c_char, msg: *const c_char) -> i32;
+}
+
+/// Entry point Node calls when the library is loaded as an addon.
+///
+/// # Safety
+///
+/// Must only be called by Node, with a valid environment and exports object.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn napi_register_module_v1(env: Env, exports: Value) -> Value {
+    unsafe {
+        let mut function = ptr::null_mut();
+        if napi_create_function(
+            env,
+            c"clean".as_ptr(),
+            5,
+            clean,
+            ptr::null_mut(),
+            &mut function,
+        ) == NAPI_OK
+        {
+            napi_set_named_property(env, exports, c"clean".as_ptr(), function);
+        }
+    }
+    exports
+}
+
+unsafe extern "C" fn clean(env: Env, info: CallbackInfo) -> Value {
+    let mut argc = 3;
+    let mut argv = [ptr::null_mut(); 3];
+    let status = unsafe {
+        napi_get_cb_info(
+            env,
+            info,
+            &mut argc,
+            argv.as_mut_ptr(),
+            ptr::null_mut(),
+            ptr::null_mut(),
+        )
+    };
+    if status != NAPI_OK {
+        return ptr::null_mut();
+    }
+    let (text, language) = unsafe { (string_arg(env, argv[0]), string_arg(env, argv[1])) };
+    let (Some(text), Some(language)) = (text, language) else {
+        return unsafe { throw(env, c"clean(text, language, marker?) takes strings") };
+    };
+    let markers = match unsafe { optional_string_arg(env, argv[2]) } {
+        Ok(None) => Markers::default(),
+        Ok(Some(marker)) if marker.is_empty() => {
+            return unsafe { throw(env, c"marker must not be empty") };
+        }
+        Ok(Some(marker)) => {
+            Markers::new(Some(&[marker]), None, false).expect("no patterns to compile")
+        }
+        Err(()) => return unsafe { throw(env, c"marker must be a string") },
+    };
+    let result = clean_source(&text, &language, &CleanOptions { markers });
+
+    unsafe {
+        let cleaned = object(env);
+        set(env, cleaned, c"output", string(env, &result.output));
+        let mut changes = ptr::null_mut();
+        napi_create_array_with_length(env, result.changes.len(), &mut changes);
+        for (idx, change) in result.changes.iter().enumerate() {
+            let removal = change.removal(None);
+            let item = object(env);
+            set(env, item, c"line", number(env, removal.line));
+            set(env, item, c"endLine", number(env, removal.end_line));
+            set(env, item, c"byteRange", range(env, &removal.byte_range));
+            set(env, item, c"colRange", range(env, &removal.col_range));
+            set(
+                env,
+                item,
+                c"removedText",
+                string(env, &removal.removed_text),
+            );
+            set(
+                env,
+                item,
+                c"commentKind",
+                string(env, removal.comment_kind.name()),
+            );
+            napi_set_element(env, changes, idx as u32, item);
+        }
+        set(env, cleaned, c"changes", changes);
+        cleaned
+    }
+}
+
+unsafe fn string_arg(env: Env, value: Value) -> Option<String> {
+    let mut kind = NAPI_UNDEFINED;
+    if value.is_null() || unsafe { napi_typeof(env, value, &mut kind) } != NAPI_OK {
+        return None;
+    }
+    if kind != NAPI_STRING {
+        return None;
+    }
+    let mut len = 0;
+    unsafe { napi_get_value_string_utf8(env, value, ptr::null_mut(), 0, &mut len) };
+    let mut buf = vec![0u8; len + 1];
+    let status = unsafe {
+        napi_get_value_string_utf8(env, value, buf.as_mut_ptr().cast(), buf.len(), &mut len)
+    };
+    if status != NAPI_OK {
+        return None;
+    }
+    buf.truncate(len);
+    String::from_utf8(buf).ok()
+}
+
+/// A string argument that may also be left out, `undefined` or `null`.
+unsafe fn optional_string_arg(env: Env, value: Value) -> Result<Option<String>, ()> {
+    let mut kind = NAPI_UNDEFINED;
+    if value.is_null() || unsafe { napi_typeof(env, value, &mut kind) } != NAPI_OK {
+        return Ok(None);
+    }
+    match kind {
+        NAPI_UNDEFINED | NAPI_NULL => Ok(None),
+        _ => unsafe { string_arg(env, value) }.map(Some).ok_or(()),
+    }
+}
+
+unsafe fn throw(env: Env, message: &CStr) -> Value {
+    unsafe { napi_throw_type_error(env, ptr::null(), message.as_ptr()) };
+    ptr::null_mut()
+}
+
+unsafe fn object(env: Env) -> Value {
+    let mut object = ptr::null_mut();
+    unsafe { napi_create_object(env, &mut object) };
+    object
+}
+
+unsafe fn set(env: Env, object: Value, name: &CStr, value: Value) {
+    unsafe { napi_set_named_property(env, object, name.as_ptr(), value) };
+}
+
+unsafe fn string(env: Env, text: &str) -> Value {
+    let mut value = ptr::null_mut();
+    unsafe { napi_create_string_utf8(env, text.as_ptr().cast(), text.len(), &mut value) };
+    value
+}
+
+unsafe fn number(env: Env, n: usize) -> Value {
+    let mut value = ptr::null_mut();
+    unsafe { napi_create_uint32(env, u32::try_from(n).unwrap_or(u32::MAX), &mut value) };
+    value
+}
+
+/// `range` as a two-element array, start and end.
+unsafe fn range(env: Env, range: &Range<usize>) -> Value {
+    let mut array = ptr::null_mut();
+    unsafe {
+        napi_create_array_with_length(env, 2, &mut array);
+        napi_set_element(env, array, 0, number(env, range.start));
+        napi_set_element(env, array, 1, number(env, range.end));
+    }
+    array
+}
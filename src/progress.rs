@@ -0,0 +1,105 @@
+//! A progress bar on stderr for runs over many files.
+//!
+//! The bar only appears once a run has taken a moment, so quick runs print
+//! nothing extra. While it is shown, log messages clear it before printing
+//! and it is drawn again with the next file.
+
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// How long a run goes before the bar appears.
+const SHOW_AFTER: Duration = Duration::from_millis(500);
+/// Minimum time between redraws.
+const REDRAW_EVERY: Duration = Duration::from_millis(100);
+const BAR_WIDTH: usize = 24;
+/// Longest file label shown; longer ones keep their end.
+const LABEL_WIDTH: usize = 40;
+
+/// Whether a bar is currently drawn on the last line of stderr.
+static SHOWN: AtomicBool = AtomicBool::new(false);
+
+pub struct Progress {
+    total: usize,
+    done: usize,
+    started: Instant,
+    drawn: Option<Instant>,
+}
+
+impl Progress {
+    pub fn new(total: usize) -> Progress {
+        Progress {
+            total,
+            done: 0,
+            started: Instant::now(),
+            drawn: None,
+        }
+    }
+
+    /// Shows that `label` is being processed, after the files before it.
+    pub fn start(&mut self, label: &str) {
+        let now = Instant::now();
+        if now.duration_since(self.started) < SHOW_AFTER
+            || self
+                .drawn
+                .is_some_and(|drawn| now.duration_since(drawn) < REDRAW_EVERY)
+        {
+            return;
+        }
+        self.drawn = Some(now);
+        let filled = BAR_WIDTH * self.done / self.total.max(1);
+        let eta = match self.done {
+            0 => String::from("--:--"),
+            done => {
+                let per_file = now.duration_since(self.started) / done as u32;
+                format_duration(per_file * (self.total - done) as u32)
+            }
+        };
+        eprint!(
+            "\r\x1b[K[{}{}] {}/{} ETA {} {}",
+            "#".repeat(filled),
+            "-".repeat(BAR_WIDTH - filled),
+            self.done,
+            self.total,
+            eta,
+            shorten(label)
+        );
+        let _ = io::stderr().flush();
+        SHOWN.store(true, Ordering::Relaxed);
+    }
+
+    pub fn finish_file(&mut self) {
+        self.done = (self.done + 1).min(self.total);
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        clear();
+    }
+}
+
+/// Erases the bar if it is shown, so the next line of output starts clean.
+pub fn clear() {
+    if SHOWN.swap(false, Ordering::Relaxed) {
+        eprint!("\r\x1b[K");
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{:02}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+fn shorten(label: &str) -> String {
+    let count = label.chars().count();
+    if count <= LABEL_WIDTH {
+        return label.to_string();
+    }
+    let tail: String = label.chars().skip(count - LABEL_WIDTH + 1).collect();
+    format!("…{}", tail)
+}
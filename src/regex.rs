@@ -0,0 +1,587 @@
+//! A small regular expression engine for `--marker-regex`.
+//!
+//! Supports literals, `.`, character classes (`[a-z]`, `[^...]`, `\d`, `\w`, `\s`
+//! and their negations), groups with alternation, the `* + ?` and `{n,m}`
+//! quantifiers, the `^`/`$` anchors and a leading `(?i)` case-insensitivity flag.
+//! Patterns are matched anywhere in the input, like `Regex::is_match` in the
+//! `regex` crate.
+//!
+//! Patterns compile to a program for a Pike VM, which steps through all the
+//! ways a pattern can match at once instead of backtracking, so matching
+//! takes time linear in the input whatever the pattern is.
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+#[error("Invalid regex {pattern:?}: {message}")]
+pub struct RegexError {
+    pattern: String,
+    message: String,
+}
+
+#[derive(Debug, Clone)]
+enum ClassItem {
+    Char(char),
+    Range(char, char),
+    Digit(bool),
+    Word(bool),
+    Space(bool),
+}
+
+impl ClassItem {
+    fn matches(&self, c: char) -> bool {
+        match *self {
+            ClassItem::Char(expected) => c == expected,
+            ClassItem::Range(start, end) => (start..=end).contains(&c),
+            ClassItem::Digit(negated) => c.is_ascii_digit() != negated,
+            ClassItem::Word(negated) => (c.is_alphanumeric() || c == '_') != negated,
+            ClassItem::Space(negated) => c.is_whitespace() != negated,
+        }
+    }
+
+    /// Case-insensitive input is lowercased, so also try the uppercase form
+    /// against classes such as `[A-Z]`.
+    fn matches_upper(&self, c: char) -> bool {
+        c.to_uppercase().any(|upper| self.matches(upper))
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Char(char),
+    Any,
+    Class {
+        items: Vec<ClassItem>,
+        negated: bool,
+    },
+    Start,
+    End,
+    Alternation(Vec<Vec<Node>>),
+    Repeat {
+        node: Box<Node>,
+        min: usize,
+        max: Option<usize>,
+    },
+}
+
+/// The most instructions a compiled pattern may have, which bounds the work
+/// per input character. Counted repetitions such as `(a{100}){100}` reach it.
+const MAX_PROGRAM_LEN: usize = 10_000;
+
+#[derive(Debug, Clone)]
+enum Inst {
+    Char(char),
+    Any,
+    Class {
+        items: Vec<ClassItem>,
+        negated: bool,
+    },
+    Start,
+    End,
+    /// Continue at both targets.
+    Split(usize, usize),
+    Jump(usize),
+    Match,
+}
+
+#[derive(Debug, Clone)]
+pub struct Regex {
+    program: Vec<Inst>,
+    case_insensitive: bool,
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Regex, RegexError> {
+        let (case_insensitive, body) = match pattern.strip_prefix("(?i)") {
+            Some(rest) => (true, rest),
+            None => (false, pattern),
+        };
+        let mut parser = Parser {
+            chars: body.chars().collect(),
+            pos: 0,
+        };
+        let fail = |message: String| RegexError {
+            pattern: pattern.to_string(),
+            message,
+        };
+        let alternatives = parser.parse_alternation().map_err(fail)?;
+        if parser.pos < parser.chars.len() {
+            return Err(fail(format!("unmatched ')' at offset {}", parser.pos)));
+        }
+        let mut compiler = Compiler {
+            program: Vec::new(),
+        };
+        compiler.alternation(&alternatives).map_err(fail)?;
+        compiler.push(Inst::Match).map_err(fail)?;
+        Ok(Regex {
+            program: compiler.program,
+            case_insensitive,
+        })
+    }
+
+    /// Whether the pattern matches anywhere in `text`.
+    pub fn is_match(&self, text: &str) -> bool {
+        let input: Vec<char> = if self.case_insensitive {
+            text.chars().flat_map(char::to_lowercase).collect()
+        } else {
+            text.chars().collect()
+        };
+        let mut current = Threads::new(self.program.len());
+        let mut next = Threads::new(self.program.len());
+        for pos in 0..=input.len() {
+            // A match may start at any position
+            if self.add_thread(&mut current, 0, pos, input.len()) {
+                return true;
+            }
+            let Some(&c) = input.get(pos) else {
+                break;
+            };
+            for &pc in &current.pcs {
+                let step = match &self.program[pc] {
+                    Inst::Char(expected) => self.char_eq(*expected, c),
+                    Inst::Any => c != '\n',
+                    Inst::Class { items, negated } => {
+                        let hit = items.iter().any(|item| {
+                            item.matches(c) || (self.case_insensitive && item.matches_upper(c))
+                        });
+                        hit != *negated
+                    }
+                    _ => false,
+                };
+                if step && self.add_thread(&mut next, pc + 1, pos + 1, input.len()) {
+                    return true;
+                }
+            }
+            std::mem::swap(&mut current, &mut next);
+            next.clear();
+        }
+        false
+    }
+
+    fn char_eq(&self, expected: char, actual: char) -> bool {
+        if self.case_insensitive {
+            expected.to_lowercase().eq(std::iter::once(actual))
+        } else {
+            expected == actual
+        }
+    }
+
+    /// Adds the thread at `pc` to `threads`, following jumps, splits and
+    /// anchors at `pos` in an input of `len` characters. Returns whether
+    /// one of them reaches the end of the pattern.
+    fn add_thread(&self, threads: &mut Threads, pc: usize, pos: usize, len: usize) -> bool {
+        let mut stack = vec![pc];
+        while let Some(pc) = stack.pop() {
+            if std::mem::replace(&mut threads.seen[pc], true) {
+                continue;
+            }
+            match self.program[pc] {
+                Inst::Match => return true,
+                Inst::Jump(target) => stack.push(target),
+                Inst::Split(first, second) => stack.extend([second, first]),
+                Inst::Start if pos == 0 => stack.push(pc + 1),
+                Inst::End if pos == len => stack.push(pc + 1),
+                Inst::Start | Inst::End => {}
+                Inst::Char(_) | Inst::Any | Inst::Class { .. } => threads.pcs.push(pc),
+            }
+        }
+        false
+    }
+}
+
+/// The instructions the VM is at for one input position, each once.
+struct Threads {
+    pcs: Vec<usize>,
+    seen: Vec<bool>,
+}
+
+impl Threads {
+    fn new(len: usize) -> Threads {
+        Threads {
+            pcs: Vec::new(),
+            seen: vec![false; len],
+        }
+    }
+
+    fn clear(&mut self) {
+        self.pcs.clear();
+        self.seen.fill(false);
+    }
+}
+
+/// Turns a parsed pattern into VM instructions.
+struct Compiler {
+    program: Vec<Inst>,
+}
+
+impl Compiler {
+    /// Appends `inst`, returning where it is.
+    fn push(&mut self, inst: Inst) -> Result<usize, String> {
+        if self.program.len() >= MAX_PROGRAM_LEN {
+            return Err("pattern too large".to_string());
+        }
+        self.program.push(inst);
+        Ok(self.program.len() - 1)
+    }
+
+    /// Appends a split going on to the next instruction, and to one patched
+    /// in later.
+    fn split(&mut self) -> Result<usize, String> {
+        let next = self.program.len() + 1;
+        self.push(Inst::Split(next, 0))
+    }
+
+    /// Points the split or jump at `at` to `target` for its last target.
+    fn patch(&mut self, at: usize, target: usize) {
+        match &mut self.program[at] {
+            Inst::Split(_, second) => *second = target,
+            Inst::Jump(to) => *to = target,
+            _ => unreachable!("only splits and jumps are patched"),
+        }
+    }
+
+    fn alternation(&mut self, alternatives: &[Vec<Node>]) -> Result<(), String> {
+        let mut jumps = Vec::new();
+        for (idx, seq) in alternatives.iter().enumerate() {
+            if idx + 1 == alternatives.len() {
+                self.sequence(seq)?;
+            } else {
+                let split = self.split()?;
+                self.sequence(seq)?;
+                jumps.push(self.push(Inst::Jump(0))?);
+                let next = self.program.len();
+                self.patch(split, next);
+            }
+        }
+        let end = self.program.len();
+        for jump in jumps {
+            self.patch(jump, end);
+        }
+        Ok(())
+    }
+
+    fn sequence(&mut self, nodes: &[Node]) -> Result<(), String> {
+        nodes.iter().try_for_each(|node| self.node(node))
+    }
+
+    fn node(&mut self, node: &Node) -> Result<(), String> {
+        let inst = match node {
+            Node::Char(c) => Inst::Char(*c),
+            Node::Any => Inst::Any,
+            Node::Class { items, negated } => Inst::Class {
+                items: items.clone(),
+                negated: *negated,
+            },
+            Node::Start => Inst::Start,
+            Node::End => Inst::End,
+            Node::Alternation(alternatives) => return self.alternation(alternatives),
+            Node::Repeat { node, min, max } => return self.repeat(node, *min, *max),
+        };
+        self.push(inst).map(|_| ())
+    }
+
+    fn repeat(&mut self, node: &Node, min: usize, max: Option<usize>) -> Result<(), String> {
+        for _ in 0..min {
+            self.node(node)?;
+        }
+        match max {
+            None => {
+                let split = self.split()?;
+                self.node(node)?;
+                self.push(Inst::Jump(split))?;
+                let end = self.program.len();
+                self.patch(split, end);
+            }
+            Some(max) => {
+                let mut splits = Vec::new();
+                for _ in min..max {
+                    let split = self.split()?;
+                    splits.push(split);
+                    self.node(node)?;
+                }
+                let end = self.program.len();
+                for split in splits {
+                    self.patch(split, end);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.pos += 1;
+        c
+    }
+
+    fn parse_alternation(&mut self) -> Result<Vec<Vec<Node>>, String> {
+        let mut alternatives = vec![self.parse_sequence()?];
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            alternatives.push(self.parse_sequence()?);
+        }
+        Ok(alternatives)
+    }
+
+    fn parse_sequence(&mut self) -> Result<Vec<Node>, String> {
+        let mut nodes = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.parse_atom()?;
+            nodes.push(self.parse_quantifier(atom)?);
+        }
+        Ok(nodes)
+    }
+
+    fn parse_atom(&mut self) -> Result<Node, String> {
+        match self.next() {
+            Some('.') => Ok(Node::Any),
+            Some('^') => Ok(Node::Start),
+            Some('$') => Ok(Node::End),
+            Some('(') => {
+                // Non-capturing groups behave the same as plain ones here
+                if self.chars[self.pos..].starts_with(&['?', ':']) {
+                    self.pos += 2;
+                }
+                let alternatives = self.parse_alternation()?;
+                if self.next() != Some(')') {
+                    return Err("unclosed group".to_string());
+                }
+                Ok(Node::Alternation(alternatives))
+            }
+            Some('[') => self.parse_class(),
+            Some('\\') => match self.escape()? {
+                ClassItem::Char(c) => Ok(Node::Char(c)),
+                item => Ok(Node::Class {
+                    items: vec![item],
+                    negated: false,
+                }),
+            },
+            Some(c @ ('*' | '+' | '?')) => Err(format!("nothing to repeat before '{}'", c)),
+            Some(c) => Ok(Node::Char(c)),
+            None => Err("unexpected end of pattern".to_string()),
+        }
+    }
+
+    fn escape(&mut self) -> Result<ClassItem, String> {
+        let item = match self.next() {
+            Some('d') => ClassItem::Digit(false),
+            Some('D') => ClassItem::Digit(true),
+            Some('w') => ClassItem::Word(false),
+            Some('W') => ClassItem::Word(true),
+            Some('s') => ClassItem::Space(false),
+            Some('S') => ClassItem::Space(true),
+            Some('n') => ClassItem::Char('\n'),
+            Some('t') => ClassItem::Char('\t'),
+            Some(c) if c.is_ascii_alphanumeric() => {
+                return Err(format!("unsupported escape '\\{}'", c));
+            }
+            Some(c) => ClassItem::Char(c),
+            None => return Err("trailing backslash".to_string()),
+        };
+        Ok(item)
+    }
+
+    fn parse_class(&mut self) -> Result<Node, String> {
+        let negated = self.peek() == Some('^');
+        if negated {
+            self.pos += 1;
+        }
+        let mut items = Vec::new();
+        let mut first = true;
+        loop {
+            let item = match self.next() {
+                None => return Err("unclosed character class".to_string()),
+                // A leading ']' is a literal, as in POSIX
+                Some(']') if !first => break,
+                Some('\\') => self.escape()?,
+                Some(c) => ClassItem::Char(c),
+            };
+            first = false;
+            if let ClassItem::Char(start) = item
+                && self.peek() == Some('-')
+                && self.chars.get(self.pos + 1).is_some_and(|&c| c != ']')
+            {
+                self.pos += 1;
+                let end = match self.next() {
+                    Some('\\') => match self.escape()? {
+                        ClassItem::Char(c) => c,
+                        _ => return Err("invalid range in character class".to_string()),
+                    },
+                    Some(c) => c,
+                    None => return Err("unclosed character class".to_string()),
+                };
+                if end < start {
+                    return Err(format!("invalid range {}-{}", start, end));
+                }
+                items.push(ClassItem::Range(start, end));
+            } else {
+                items.push(item);
+            }
+        }
+        Ok(Node::Class { items, negated })
+    }
+
+    fn parse_quantifier(&mut self, atom: Node) -> Result<Node, String> {
+        let (min, max) = match self.peek() {
+            Some('{') => match self.parse_bounds() {
+                Some(bounds) => bounds,
+                // Not a valid repetition: treat '{' as a literal like most engines
+                None => return Ok(atom),
+            },
+            Some(c @ ('*' | '+' | '?')) => {
+                self.pos += 1;
+                match c {
+                    '*' => (0, None),
+                    '+' => (1, None),
+                    _ => (0, Some(1)),
+                }
+            }
+            _ => return Ok(atom),
+        };
+        if matches!(atom, Node::Start | Node::End) {
+            return Err("anchors cannot be repeated".to_string());
+        }
+        Ok(Node::Repeat {
+            node: Box::new(atom),
+            min,
+            max,
+        })
+    }
+
+    /// Parses `{n}`, `{n,}` or `{n,m}`, consuming it only if well-formed.
+    fn parse_bounds(&mut self) -> Option<(usize, Option<usize>)> {
+        let close = self.chars[self.pos..].iter().position(|&c| c == '}')? + self.pos;
+        let body: String = self.chars[self.pos + 1..close].iter().collect();
+        let bounds = match body.split_once(',') {
+            None => {
+                let n = body.parse().ok()?;
+                (n, Some(n))
+            }
+            Some((min, "")) => (min.parse().ok()?, None),
+            Some((min, max)) => {
+                let (min, max) = (min.parse().ok()?, max.parse().ok()?);
+                if max < min {
+                    return None;
+                }
+                (min, Some(max))
+            }
+        };
+        self.pos = close + 1;
+        Some(bounds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_match(pattern: &str, text: &str) -> bool {
+        Regex::new(pattern).unwrap().is_match(text)
+    }
+
+    #[test]
+    fn literals_and_classes() {
+        assert!(is_match("TODO", "// a TODO here"));
+        assert!(!is_match("TODO", "// todo"));
+        assert!(is_match("a.c", "abc"));
+        assert!(!is_match("a.c", "a\nc"));
+        assert!(is_match("[a-c]x", "bx"));
+        assert!(!is_match("[^a-c]x", "bx"));
+        assert!(is_match("[]]", "]"));
+        assert!(is_match(r"\d+\s\w", "12 a"));
+        assert!(!is_match(r"\D", "123"));
+        assert!(is_match(r"a\.b", "a.b"));
+        assert!(!is_match(r"a\.b", "axb"));
+    }
+
+    #[test]
+    fn alternation_and_groups() {
+        assert!(is_match("(?:fix|hack)me", "hackme"));
+        assert!(is_match("a(b|c)d", "acd"));
+        assert!(!is_match("a(b|c)d", "aed"));
+        assert!(is_match("x|", "anything"));
+    }
+
+    #[test]
+    fn quantifiers() {
+        assert!(is_match("^ab*c$", "ac"));
+        assert!(is_match("^ab*c$", "abbbc"));
+        assert!(!is_match("^ab+c$", "ac"));
+        assert!(is_match("^ab?c$", "abc"));
+        assert!(!is_match("^ab?c$", "abbc"));
+        assert!(is_match("^a{2,3}$", "aaa"));
+        assert!(!is_match("^a{2,3}$", "aaaa"));
+        assert!(!is_match("^a{2,3}$", "a"));
+        assert!(is_match("^a{2}$", "aa"));
+        assert!(is_match("^a{2,}$", "aaaaa"));
+        assert!(is_match("^(ab){2}$", "abab"));
+        // Not a valid repetition, so a literal
+        assert!(is_match("a{x}", "a{x}"));
+    }
+
+    #[test]
+    fn anchors() {
+        assert!(is_match("^//", "// x"));
+        assert!(!is_match("^x", "// x"));
+        assert!(is_match("x$", "// x"));
+        assert!(!is_match("//$", "// x"));
+        assert!(is_match("^$", ""));
+    }
+
+    #[test]
+    fn case_insensitive() {
+        assert!(is_match("(?i)todo", "// TODO"));
+        assert!(is_match("(?i)[A-Z]+!", "// hey!"));
+        assert!(!is_match("todo", "// TODO"));
+    }
+
+    #[test]
+    fn empty_loops_end() {
+        assert!(is_match("^(a*)*$", "aaa"));
+        assert!(is_match("(a|)*b", "aab"));
+        assert!(!is_match("^(a?)*$", "ab"));
+    }
+
+    #[test]
+    fn long_input_does_not_overflow_the_stack() {
+        let line = format!("// {}TODO", "a".repeat(200_000));
+        assert!(is_match("a.*TODO", &line));
+        assert!(!is_match("a.*DONE", &line));
+    }
+
+    #[test]
+    fn nested_repetition_is_linear() {
+        let text = "a".repeat(10_000);
+        assert!(!is_match("(a*)*b", &text));
+        assert!(!is_match("(a|aa)+b", &text));
+        assert!(!is_match("^(a+)+$", &format!("{}!", text)));
+    }
+
+    #[test]
+    fn invalid_patterns() {
+        for pattern in ["(a", "a)", "*a", "[a", r"\q", "a\\", "[z-a]", "^*"] {
+            assert!(Regex::new(pattern).is_err(), "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn oversized_pattern() {
+        let err = Regex::new("(a{100}){200}").unwrap_err();
+        assert!(err.to_string().contains("pattern too large"));
+        assert!(Regex::new("a{0,99999999999}").is_err());
+    }
+}
@@ -0,0 +1,343 @@
+use clap::ValueEnum;
+use emoji_remover::{CommentKind, LineChange, Removal};
+use std::ops::Range;
+use std::path::Path;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human-readable messages
+    Text,
+    /// A single JSON document written once processing finishes
+    Json,
+    /// One JSON event per line, streamed while processing
+    Ndjson,
+    /// SARIF 2.1.0 log for code-scanning integrations
+    Sarif,
+    /// `file:line:col: message` lines for Vim's default errorformat
+    Quickfix,
+    /// Only where each marked comment is, as a single JSON document, for
+    /// editors to highlight. Nothing is removed
+    Ranges,
+}
+
+impl OutputFormat {
+    /// Whether findings are collected and rendered once the run finishes.
+    pub fn is_buffered(self) -> bool {
+        matches!(
+            self,
+            OutputFormat::Json | OutputFormat::Sarif | OutputFormat::Ranges
+        )
+    }
+
+    pub fn render(self, reports: &[FileReport], summary: &Summary) -> Option<String> {
+        match self {
+            OutputFormat::Json => Some(render_json(reports, summary)),
+            OutputFormat::Sarif => Some(render_sarif(reports, summary)),
+            OutputFormat::Ranges => Some(render_ranges(reports)),
+            OutputFormat::Text | OutputFormat::Ndjson | OutputFormat::Quickfix => None,
+        }
+    }
+}
+
+/// Totals for a whole run.
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub files_scanned: usize,
+    pub files_modified: usize,
+    /// Comments, not lines: a block comment spanning lines counts once.
+    pub comments_removed: usize,
+    pub bytes_removed: usize,
+    pub elapsed: Duration,
+}
+
+impl Summary {
+    /// Counts the comments `changes` remove from one file.
+    pub fn record(&mut self, changes: &[LineChange]) {
+        if changes.is_empty() {
+            return;
+        }
+        self.files_modified += 1;
+        self.comments_removed += changes.len();
+        self.bytes_removed += changes
+            .iter()
+            .map(|c| c.original.len().saturating_sub(c.cleaned.len()))
+            .sum::<usize>();
+    }
+
+    /// One-line description for text output. `applied` distinguishes real runs from previews.
+    pub fn describe(&self, applied: bool) -> String {
+        let (modified, removed) = if applied {
+            ("modified", "removed")
+        } else {
+            ("would modify", "would remove")
+        };
+        format!(
+            "Scanned {} files, {} {}, {} {} comments ({} bytes) in {:.2}s",
+            self.files_scanned,
+            modified,
+            self.files_modified,
+            removed,
+            self.comments_removed,
+            self.bytes_removed,
+            self.elapsed.as_secs_f64()
+        )
+    }
+
+    fn to_json(&self) -> String {
+        format!(
+            "{{\"files_scanned\":{},\"files_modified\":{},\"comments_removed\":{},\"bytes_removed\":{},\"elapsed_ms\":{}}}",
+            self.files_scanned,
+            self.files_modified,
+            self.comments_removed,
+            self.bytes_removed,
+            self.elapsed.as_millis()
+        )
+    }
+}
+
+/// Every marked comment found in one file.
+pub struct FileReport {
+    pub path: String,
+    pub changes: Vec<LineChange>,
+    pub written: bool,
+}
+
+fn render_json(reports: &[FileReport], summary: &Summary) -> String {
+    let mut out = String::from("{\"files\":[");
+    for (i, report) in reports.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        out.push_str(&format!(
+            "{{\"path\":{},\"written\":{},\"removals\":[",
+            json_string(&report.path),
+            report.written
+        ));
+        for (j, change) in report.changes.iter().enumerate() {
+            if j > 0 {
+                out.push(',');
+            }
+            out.push_str(&format!("{{{}}}", removal_fields(&change.removal(None))));
+        }
+        out.push_str("]}");
+    }
+    out.push_str(&format!("],\"summary\":{}}}", summary.to_json()));
+    out
+}
+
+fn render_ranges(reports: &[FileReport]) -> String {
+    let files: Vec<String> = reports
+        .iter()
+        .map(|report| {
+            let ranges: Vec<String> = report
+                .changes
+                .iter()
+                .map(|change| {
+                    let removal = change.removal(None);
+                    format!(
+                        "{{\"line\":{},\"end_line\":{},\"byte_range\":{},\"col_range\":{},\"kind\":{}}}",
+                        removal.line,
+                        removal.end_line,
+                        json_range(&removal.byte_range),
+                        json_range(&removal.col_range),
+                        json_string(removal.comment_kind.name())
+                    )
+                })
+                .collect();
+            format!(
+                "{{\"path\":{},\"ranges\":[{}]}}",
+                json_string(&report.path),
+                ranges.join(",")
+            )
+        })
+        .collect();
+    format!("{{\"files\":[{}]}}", files.join(","))
+}
+
+fn render_sarif(reports: &[FileReport], summary: &Summary) -> String {
+    let kinds = [
+        CommentKind::Line,
+        CommentKind::Block,
+        CommentKind::UnclosedBlock,
+    ];
+    let rules: Vec<String> = kinds
+        .iter()
+        .map(|kind| {
+            format!(
+                "{{\"id\":{},\"shortDescription\":{{\"text\":{}}}}}",
+                json_string(kind.rule_id()),
+                json_string(kind.reason())
+            )
+        })
+        .collect();
+
+    let mut results = Vec::new();
+    for report in reports {
+        for change in &report.changes {
+            // SARIF columns are counted in code points here, not bytes
+            let removal = change.removal(None);
+            let last_line = change.original.rsplit('\n').next().unwrap_or_default();
+            let line_end_column = last_line.chars().count() + 1;
+            let location = format!(
+                "{{\"physicalLocation\":{{\"artifactLocation\":{{\"uri\":{}}},\"region\":{{\"startLine\":{},\"startColumn\":{},\"endLine\":{},\"endColumn\":{}}}}}}}",
+                json_string(&report.path),
+                removal.line,
+                removal.col_range.start,
+                removal.end_line,
+                removal.col_range.end
+            );
+            let fix = format!(
+                "{{\"description\":{{\"text\":\"Remove marked comment\"}},\"artifactChanges\":[{{\"artifactLocation\":{{\"uri\":{}}},\"replacements\":[{{\"deletedRegion\":{{\"startLine\":{},\"startColumn\":1,\"endLine\":{},\"endColumn\":{}}},\"insertedContent\":{{\"text\":{}}}}}]}}]}}",
+                json_string(&report.path),
+                removal.line,
+                removal.end_line,
+                line_end_column,
+                json_string(&change.cleaned)
+            );
+            results.push(format!(
+                "{{\"ruleId\":{},\"level\":\"warning\",\"message\":{{\"text\":{}}},\"locations\":[{}],\"fixes\":[{}]}}",
+                json_string(removal.comment_kind.rule_id()),
+                json_string(&format!("Marked comment: {}", removal.removed_text)),
+                location,
+                fix
+            ));
+        }
+    }
+
+    format!(
+        "{{\"$schema\":\"https://json.schemastore.org/sarif-2.1.0.json\",\"version\":\"2.1.0\",\"runs\":[{{\"tool\":{{\"driver\":{{\"name\":\"emoji-remover\",\"version\":{},\"rules\":[{}]}}}},\"columnKind\":\"unicodeCodePoints\",\"results\":[{}],\"properties\":{{\"summary\":{}}}}}]}}",
+        json_string(env!("CARGO_PKG_VERSION")),
+        rules.join(","),
+        results.join(","),
+        summary.to_json()
+    )
+}
+
+/// The fields describing `removal`, apart from its file. `column` is the
+/// 1-based byte column, as in quickfix output.
+pub fn removal_fields(removal: &Removal) -> String {
+    format!(
+        "\"line\":{},\"end_line\":{},\"column\":{},\"byte_range\":{},\"col_range\":{},\"text\":{},\"kind\":{},\"reason\":{}",
+        removal.line,
+        removal.end_line,
+        removal.byte_range.start + 1,
+        json_range(&removal.byte_range),
+        json_range(&removal.col_range),
+        json_string(&removal.removed_text),
+        json_string(removal.comment_kind.name()),
+        json_string(removal.comment_kind.reason())
+    )
+}
+
+fn json_range(range: &Range<usize>) -> String {
+    format!("[{},{}]", range.start, range.end)
+}
+
+/// The quickfix entry for `removal`, which shows only the first line of a
+/// block comment spanning lines.
+pub fn quickfix_line(removal: &Removal) -> String {
+    format!(
+        "{}:{}:{}: {}: {}",
+        display_file(removal),
+        removal.line,
+        removal.byte_range.start + 1,
+        removal.comment_kind.reason(),
+        removal.removed_text.lines().next().unwrap_or_default()
+    )
+}
+
+fn display_file(removal: &Removal) -> String {
+    removal
+        .file
+        .as_deref()
+        .unwrap_or(Path::new("<stdin>"))
+        .display()
+        .to_string()
+}
+
+/// A single line of `--format ndjson` output.
+pub enum Event<'a> {
+    FileStarted { path: &'a str },
+    Removal { removal: &'a Removal },
+    FileWritten { path: &'a str },
+    Error { path: &'a str, message: String },
+    Summary { summary: &'a Summary },
+}
+
+impl Event<'_> {
+    pub fn to_json(&self) -> String {
+        match self {
+            Event::FileStarted { path } => {
+                format!(
+                    "{{\"event\":\"file_started\",\"path\":{}}}",
+                    json_string(path)
+                )
+            }
+            Event::Removal { removal } => format!(
+                "{{\"event\":\"removal\",\"path\":{},{}}}",
+                json_string(&display_file(removal)),
+                removal_fields(removal)
+            ),
+            Event::FileWritten { path } => {
+                format!(
+                    "{{\"event\":\"file_written\",\"path\":{}}}",
+                    json_string(path)
+                )
+            }
+            Event::Error { path, message } => format!(
+                "{{\"event\":\"error\",\"path\":{},\"message\":{}}}",
+                json_string(path),
+                json_string(message)
+            ),
+            Event::Summary { summary } => {
+                format!("{{\"event\":\"summary\",{}", &summary.to_json()[1..])
+            }
+        }
+    }
+}
+
+/// Quotes and escapes `value` as a JSON string literal.
+pub fn json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use emoji_remover::{Markers, find_changes};
+
+    #[test]
+    fn block_spanning_lines_counts_once() {
+        let content = "a /* ‼️ one\ntwo\nthree */\nb // ‼️\n";
+        let mut summary = Summary::default();
+        summary.record(&find_changes(content, "rs", &Markers::default()));
+        assert_eq!(summary.files_modified, 1);
+        assert_eq!(summary.comments_removed, 2);
+        assert_eq!(summary.bytes_removed, 35);
+    }
+
+    #[test]
+    fn replacement_longer_than_comment() {
+        let mut changes = find_changes("a // ‼️\n", "rs", &Markers::default());
+        changes[0].replace_comment("// a much longer replacement");
+        let mut summary = Summary::default();
+        summary.record(&changes);
+        assert_eq!(summary.comments_removed, 1);
+        assert_eq!(summary.bytes_removed, 0);
+    }
+}
@@ -0,0 +1,112 @@
+//! `--rpc`: msgpack-RPC over stdin and stdout, so the Neovim plugin can run
+//! the binary as a job and clean buffers in place, saved or not.
+//!
+//! One method is served:
+//!
+//! `clean_buffer(lines, filetype)` returns `{ lines, removals }`: the
+//! buffer's lines with marked comments removed, and where each removal was,
+//! with 1-based lines, 0-based byte ranges and 1-based character columns.
+
+use crate::config::Settings;
+use crate::msgpack::{self, Value};
+use emoji_remover::{
+    LineChange, LineEdit, Removal, find_changes, language_for_filetype, line_edits,
+};
+use log::debug;
+use std::io::{self, BufReader, Write};
+
+const REQUEST: i64 = 0;
+const RESPONSE: i64 = 1;
+const NOTIFICATION: i64 = 2;
+
+/// Answers requests until stdin is closed.
+pub fn serve(settings: &Settings) -> io::Result<()> {
+    let mut input = BufReader::new(io::stdin().lock());
+    let mut output = io::stdout().lock();
+    while let Some(message) = msgpack::read(&mut input)? {
+        let Value::Array(parts) = message else {
+            debug!("Ignoring RPC message that is not an array");
+            continue;
+        };
+        match parts.as_slice() {
+            [kind, id, method, Value::Array(params)] if kind.as_int() == Some(REQUEST) => {
+                let (error, result) = match handle(settings, method.as_str(), params) {
+                    Ok(result) => (Value::Nil, result),
+                    Err(message) => (Value::from(message.as_str()), Value::Nil),
+                };
+                let response = Value::Array(vec![Value::Int(RESPONSE), id.clone(), error, result]);
+                let mut bytes = Vec::new();
+                msgpack::write(&mut bytes, &response);
+                output.write_all(&bytes)?;
+                output.flush()?;
+            }
+            [kind, ..] if kind.as_int() == Some(NOTIFICATION) => {
+                debug!("Ignoring RPC notification");
+            }
+            _ => debug!("Ignoring malformed RPC message"),
+        }
+    }
+    Ok(())
+}
+
+fn handle(settings: &Settings, method: Option<&str>, params: &[Value]) -> Result<Value, String> {
+    match (method, params) {
+        (Some("clean_buffer"), [Value::Array(lines), filetype]) => {
+            let lines = lines
+                .iter()
+                .map(Value::as_str)
+                .collect::<Option<Vec<_>>>()
+                .ok_or("clean_buffer: lines must be UTF-8 strings")?;
+            let filetype = filetype
+                .as_str()
+                .ok_or("clean_buffer: filetype must be a string")?;
+            Ok(clean_buffer(settings, &lines, filetype))
+        }
+        (Some("clean_buffer"), _) => Err("clean_buffer takes (lines, filetype)".to_string()),
+        (Some(method), _) => Err(format!("Unknown method: {}", method)),
+        (None, _) => Err("Method name must be a string".to_string()),
+    }
+}
+
+fn clean_buffer(settings: &Settings, lines: &[&str], filetype: &str) -> Value {
+    let syntax = settings.syntax_for(language_for_filetype(filetype));
+    let changes = find_changes(&lines.join("\n"), syntax, &settings.markers);
+    let mut cleaned: Vec<Value> = lines.iter().map(|&line| Value::from(line)).collect();
+    for (line_number, _, line) in line_edits(&changes).iter().flat_map(LineEdit::lines) {
+        cleaned[line_number - 1] = Value::from(line);
+    }
+    Value::Map(vec![
+        (Value::from("lines"), Value::Array(cleaned)),
+        (
+            Value::from("removals"),
+            Value::Array(changes.iter().map(removal_value).collect()),
+        ),
+    ])
+}
+
+fn removal_value(change: &LineChange) -> Value {
+    let Removal {
+        line,
+        end_line,
+        byte_range,
+        col_range,
+        removed_text,
+        comment_kind,
+        ..
+    } = change.removal(None);
+    let range = |start: usize, end: usize| Value::Array(vec![start.into(), end.into()]);
+    Value::Map(vec![
+        (Value::from("line"), line.into()),
+        (Value::from("end_line"), end_line.into()),
+        (
+            Value::from("byte_range"),
+            range(byte_range.start, byte_range.end),
+        ),
+        (
+            Value::from("col_range"),
+            range(col_range.start, col_range.end),
+        ),
+        (Value::from("text"), Value::from(removed_text.as_str())),
+        (Value::from("kind"), Value::from(comment_kind.name())),
+    ])
+}
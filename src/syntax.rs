@@ -0,0 +1,1538 @@
+//! The comment and string literal syntax of each language, keyed by file
+//! extension.
+
+use crate::clean::CommentKind;
+use std::ops::Range;
+
+/// How comments and string literals are written in one language.
+pub(crate) struct Syntax {
+    /// Openers of comments that run to the end of the line.
+    line: &'static [&'static str],
+    /// Openers of block comments and their closers.
+    block: &'static [(&'static str, &'static str)],
+    /// Characters that open and close string literals, inside which comment
+    /// openers are text. A backslash escapes the next character.
+    quotes: &'static [char],
+    /// The quotes inside which a backslash is text, doubling the quote to
+    /// write it instead.
+    verbatim_quotes: &'static [char],
+    /// Whether a `"` starting a command, or that no later `"` on the line
+    /// closes, opens a line comment, as in Vim script.
+    vim_comments: bool,
+    /// The quotes whose literals may span lines, such as JavaScript's
+    /// template literals. Other literals end with their line.
+    multiline_quotes: &'static [char],
+    /// Whether `"""` and `\'\'\'` open strings that may span lines.
+    triple_quotes: bool,
+    /// Whether triple-quoted strings starting a line, Python's docstrings,
+    /// are taken for doc comments.
+    docstrings: bool,
+    /// Openers of doc comments, which are part of the API documentation.
+    /// Repeating the opener's last character, as in `////`, makes a plain
+    /// comment again.
+    doc: &'static [&'static str],
+    /// Openers of directives, comments read by tools rather than people.
+    directives: &'static [&'static str],
+    /// Whether block comments nest, so each opener inside one needs a
+    /// closer of its own.
+    nested_blocks: bool,
+    /// Whether `#` lines are C preprocessor directives: the path of an
+    /// `#include <...>` is quoted, and the message of an `#error` or
+    /// `#warning` is text, apostrophes included.
+    preprocessor: bool,
+    /// Whether an apostrophe inside a number, as in `1'000`, separates
+    /// digits rather than opening a character literal.
+    digit_separators: bool,
+    /// Block comments whose delimiters start lines of their own, like Ruby's
+    /// `=begin` and `=end`.
+    line_blocks: &'static [(&'static str, &'static str)],
+    /// Whether line block delimiters stand alone on their lines and may be
+    /// indented, like MATLAB's `%{` and `%}`.
+    lone_line_blocks: bool,
+    /// Whether an apostrophe straight after a value, as in MATLAB's `a'`,
+    /// transposes it rather than opening a string.
+    transpose: bool,
+    /// Whether Ruby's percent literals, regular expression literals and
+    /// heredocs are recognized.
+    ruby_literals: bool,
+    /// Text that starts like a line comment but is code, such as PHP's `#[`
+    /// attributes.
+    not_comments: &'static [&'static str],
+    /// Regions of another language inside this one, like PHP in HTML.
+    embedded: Option<Embedded>,
+    /// Whether `~` opens an Elixir sigil such as `~r/.../`.
+    sigils: bool,
+    /// The character that makes the next one a character code, as Elixir's
+    /// `?#` or Erlang's `$%`.
+    char_code: Option<char>,
+    /// Whether `@"` opens a C# verbatim string, which may span lines and
+    /// in which `""` is a quote and a backslash is text.
+    verbatim_strings: bool,
+    /// Whether Swift raw strings (`#"..."#`, `##"""...."""##`) are
+    /// recognized.
+    swift_raw_strings: bool,
+    /// Whether C++ raw strings (`R"(...)"`, `u8R"x(...)x"`) are recognized.
+    cpp_raw_strings: bool,
+    /// Whether words are shell words: a backslash outside quotes escapes the
+    /// next character, line comments only start words, and `<<` opens a
+    /// here-document.
+    shell_words: bool,
+    /// Whether dashes followed by an operator symbol, as in `-->`, make an
+    /// operator rather than a line comment, as in Haskell.
+    dash_operators: bool,
+    /// Whether Lua long brackets (`[[...]]`, `[==[...]==]`) open strings,
+    /// and block comments after `--`.
+    long_brackets: bool,
+    /// Whether Rust raw strings (`r"..."`, `br#"..."#`) are recognized.
+    rust_raw_strings: bool,
+    /// Whether a single quote opens a character literal only when it closes
+    /// one character later, as in `'a'` or `'\n'`, and otherwise starts a
+    /// Rust lifetime or a Scala symbol such as `'a`.
+    char_literals: bool,
+}
+
+/// The regions of a language embedded in another, between tags.
+pub(crate) struct Embedded {
+    /// The tags opening a region, longest first where one starts another.
+    open: &'static [&'static str],
+    close: &'static str,
+    syntax: &'static Syntax,
+}
+
+/// What is still open at the end of a line.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) enum Carry {
+    #[default]
+    Code,
+    Quote(char),
+    /// A triple-quoted string, with its quotes.
+    TripleQuote(&'static str),
+    /// A region of the embedded language, with what is open inside it.
+    Embedded(Box<Carry>),
+    /// A C# verbatim string.
+    VerbatimString,
+    /// A C++ or Swift raw string, with its closer such as `)x"` or `"#`.
+    DelimitedString(String),
+    /// A Rust raw string closed by `"` and this many `#`.
+    RawString(usize),
+    /// A block comment, with its delimiters and how deeply it is nested.
+    Block {
+        opener: &'static str,
+        closer: &'static str,
+        depth: usize,
+    },
+    /// The body of a here-document, ended by a line holding only its
+    /// delimiter, after any of the `indent` characters.
+    Heredoc {
+        delimiter: String,
+        indent: &'static [char],
+    },
+    /// A block comment whose closer starts a line of its own.
+    LineBlock(&'static str),
+    /// A literal between delimiters, such as Ruby's `%w[...]` or an Elixir
+    /// sigil, with its delimiters and how deeply bracket delimiters nest.
+    Literal {
+        open: char,
+        close: char,
+        depth: usize,
+    },
+    /// A Lua long string, closed by `]`, this many `=` and `]`.
+    LongString(usize),
+    /// A Lua long comment, closed like a long string.
+    LongComment(usize),
+}
+
+impl Carry {
+    /// Whether a comment is open.
+    pub(crate) fn in_comment(&self) -> bool {
+        match self {
+            Carry::Block { .. } | Carry::LineBlock(_) | Carry::LongComment(_) => true,
+            Carry::Embedded(inner) => inner.in_comment(),
+            _ => false,
+        }
+    }
+}
+
+/// `#` line comments and single-line strings in either quote, the syntax of
+/// shell scripts, TOML and other languages not listed.
+const HASH: Syntax = Syntax {
+    line: &["#"],
+    block: &[],
+    quotes: &['"', '\''],
+    verbatim_quotes: &[],
+    vim_comments: false,
+    multiline_quotes: &[],
+    triple_quotes: false,
+    docstrings: false,
+    doc: &[],
+    directives: &[],
+    nested_blocks: false,
+    preprocessor: false,
+    digit_separators: false,
+    cpp_raw_strings: false,
+    swift_raw_strings: false,
+    line_blocks: &[],
+    lone_line_blocks: false,
+    transpose: false,
+    ruby_literals: false,
+    sigils: false,
+    char_code: None,
+    not_comments: &[],
+    embedded: None,
+    verbatim_strings: false,
+    shell_words: false,
+    dash_operators: false,
+    long_brackets: false,
+    rust_raw_strings: false,
+    char_literals: false,
+};
+
+/// HTML, with `//` comments for inline scripts.
+const HTML: Syntax = Syntax {
+    line: &["//"],
+    block: &[("<!--", "-->"), ("/*", "*/")],
+    // Double quotes only: apostrophes are common in markup text
+    quotes: &['"'],
+    ..HASH
+};
+
+/// PHP code, inside `<?php ?>` tags. `#[` opens an attribute.
+const PHP: Syntax = Syntax {
+    line: &["//", "#"],
+    block: &[("/*", "*/")],
+    quotes: &['"', '\'', '`'],
+    multiline_quotes: &['"', '\'', '`'],
+    doc: &["/**"],
+    not_comments: &["#["],
+    ..HASH
+};
+
+/// Python's triple-quoted strings.
+const TRIPLE_QUOTES: [&str; 2] = ["\"\"\"", "\'\'\'"];
+
+impl Syntax {
+    /// The syntax of `ext`. Unknown extensions get `#` line comments.
+    pub(crate) fn for_language(ext: &str) -> Syntax {
+        match ext {
+            "html" => HTML,
+            "php" => Syntax {
+                embedded: Some(Embedded {
+                    open: &["<?php", "<?="],
+                    close: "?>",
+                    syntax: &PHP,
+                }),
+                ..HTML
+            },
+            "css" => Syntax {
+                line: &[],
+                block: &[("/*", "*/")],
+                ..HASH
+            },
+            // `{/* */}` in markup, where it is removed with its braces
+            "jsx" | "tsx" => Syntax {
+                line: &["//"],
+                block: &[("{/*", "*/}"), ("/*", "*/")],
+                quotes: &['"', '\'', '`'],
+                multiline_quotes: &['`'],
+                // JSDoc
+                doc: &["/**"],
+                ..HASH
+            },
+            "js" | "ts" => Syntax {
+                line: &["//"],
+                block: &[("/*", "*/")],
+                quotes: &['"', '\'', '`'],
+                multiline_quotes: &['`'],
+                doc: &["/**"],
+                ..HASH
+            },
+            "lua" => Syntax {
+                line: &["--"],
+                // LuaCATS annotations, read by the language server
+                doc: &["---"],
+                long_brackets: true,
+                ..HASH
+            },
+            // Single-quoted strings are verbatim, and either kind may span lines
+            "sh" | "bash" | "zsh" => Syntax {
+                verbatim_quotes: &['\''],
+                multiline_quotes: &['"', '\''],
+                shell_words: true,
+                ..HASH
+            },
+            "vim" => Syntax {
+                line: &[],
+                verbatim_quotes: &['\''],
+                vim_comments: true,
+                ..HASH
+            },
+            // Raw strings in backquotes are verbatim and may span lines
+            "go" => Syntax {
+                line: &["//"],
+                block: &[("/*", "*/")],
+                quotes: &['"', '\'', '`'],
+                verbatim_quotes: &['`'],
+                multiline_quotes: &['`'],
+                directives: &["//go:", "// +build", "//line ", "//export "],
+                ..HASH
+            },
+            "c" | "h" | "cc" | "cpp" | "cxx" | "hh" | "hpp" | "hxx" => Syntax {
+                line: &["//"],
+                block: &[("/*", "*/")],
+                // Doxygen
+                doc: &["///", "//!", "/**", "/*!"],
+                preprocessor: true,
+                digit_separators: true,
+                cpp_raw_strings: true,
+                ..HASH
+            },
+            // Text blocks in triple quotes span lines
+            "java" => Syntax {
+                line: &["//"],
+                block: &[("/*", "*/")],
+                triple_quotes: true,
+                doc: &["/**"],
+                ..HASH
+            },
+            "kt" | "kts" => Syntax {
+                line: &["//"],
+                block: &[("/*", "*/")],
+                triple_quotes: true,
+                doc: &["/**"],
+                nested_blocks: true,
+                ..HASH
+            },
+            "scala" => Syntax {
+                line: &["//"],
+                block: &[("/*", "*/")],
+                quotes: &['"'],
+                triple_quotes: true,
+                doc: &["/**"],
+                nested_blocks: true,
+                char_literals: true,
+                ..HASH
+            },
+            // Raw string literals in triple quotes span lines
+            "cs" => Syntax {
+                line: &["//"],
+                block: &[("/*", "*/")],
+                triple_quotes: true,
+                doc: &["///", "/**"],
+                verbatim_strings: true,
+                ..HASH
+            },
+            "rb" => Syntax {
+                quotes: &['"', '\'', '`'],
+                multiline_quotes: &['"', '\'', '`'],
+                line_blocks: &[("=begin", "=end")],
+                ruby_literals: true,
+                ..HASH
+            },
+            // Multi-line strings in triple quotes span lines
+            "swift" => Syntax {
+                line: &["//"],
+                block: &[("/*", "*/")],
+                quotes: &['"'],
+                triple_quotes: true,
+                doc: &["///", "/**"],
+                nested_blocks: true,
+                swift_raw_strings: true,
+                ..HASH
+            },
+            // Quotes are doubled rather than escaped, and strings and quoted
+            // identifiers may span lines. PostgreSQL's dollar-quoted function
+            // bodies are scanned as code, for the comments in them.
+            "sql" => Syntax {
+                line: &["--"],
+                block: &[("/*", "*/")],
+                verbatim_quotes: &['"', '\''],
+                multiline_quotes: &['"', '\''],
+                ..HASH
+            },
+            // Single quotes are character literals or primes, as in `foldl'`
+            "hs" => Syntax {
+                line: &["--"],
+                block: &[("{-", "-}")],
+                quotes: &['"'],
+                // Haddock
+                doc: &["-- |", "-- ^", "{-|", "{- |", "{- ^"],
+                // Pragmas
+                directives: &["{-#"],
+                nested_blocks: true,
+                dash_operators: true,
+                char_literals: true,
+                ..HASH
+            },
+            // Octave also takes `#` comments and double-quoted strings
+            "m" => Syntax {
+                line: &["%", "#"],
+                verbatim_quotes: &['"', '\''],
+                line_blocks: &[("%{", "%}"), ("#{", "#}")],
+                lone_line_blocks: true,
+                transpose: true,
+                ..HASH
+            },
+            "ml" | "mli" => Syntax {
+                line: &[],
+                block: &[("(*", "*)")],
+                quotes: &['"'],
+                multiline_quotes: &['"'],
+                // odoc
+                doc: &["(**"],
+                nested_blocks: true,
+                char_literals: true,
+                ..HASH
+            },
+            "fs" | "fsi" | "fsx" => Syntax {
+                line: &["//"],
+                block: &[("(*", "*)")],
+                quotes: &['"'],
+                multiline_quotes: &['"'],
+                triple_quotes: true,
+                doc: &["///", "(**"],
+                nested_blocks: true,
+                char_literals: true,
+                verbatim_strings: true,
+                ..HASH
+            },
+            "ex" | "exs" => Syntax {
+                multiline_quotes: &['"', '\''],
+                triple_quotes: true,
+                sigils: true,
+                char_code: Some('?'),
+                ..HASH
+            },
+            // Single quotes are atoms
+            "erl" | "hrl" => Syntax {
+                line: &["%"],
+                multiline_quotes: &['"'],
+                triple_quotes: true,
+                char_code: Some('$'),
+                ..HASH
+            },
+            "py" => Syntax {
+                triple_quotes: true,
+                docstrings: true,
+                ..HASH
+            },
+            "rs" => Syntax {
+                line: &["//"],
+                block: &[("/*", "*/")],
+                quotes: &['"'],
+                multiline_quotes: &['"'],
+                doc: &["///", "//!", "/**", "/*!"],
+                nested_blocks: true,
+                rust_raw_strings: true,
+                char_literals: true,
+                ..HASH
+            },
+            _ => HASH,
+        }
+    }
+
+    /// Stops reporting docstrings as comments, for when doc comments are left
+    /// alone anyway: as strings, they do not hide the comments after them.
+    pub(crate) fn without_docstrings(self) -> Syntax {
+        Syntax {
+            docstrings: false,
+            ..self
+        }
+    }
+
+    /// The comments in `line` outside string literals, in order, given what
+    /// the previous lines left open in `carry`, which is updated for the
+    /// next. A block comment left open runs to the end of the line, and one
+    /// still open from the previous lines starts the line.
+    pub(crate) fn scan(&self, line: &str, carry: &mut Carry) -> Vec<(Range<usize>, CommentKind)> {
+        let Some(embedded) = &self.embedded else {
+            return self.scan_until(line, carry, &[]).0;
+        };
+        let mut comments = Vec::new();
+        let mut pos = 0;
+        loop {
+            let (found, end) = match carry {
+                Carry::Embedded(inner) => {
+                    embedded
+                        .syntax
+                        .scan_until(&line[pos..], inner, &[embedded.close])
+                }
+                _ => self.scan_until(&line[pos..], carry, embedded.open),
+            };
+            comments.extend(
+                found
+                    .into_iter()
+                    .map(|(span, kind)| (pos + span.start..pos + span.end, kind)),
+            );
+            let Some(end) = end else {
+                break;
+            };
+            pos += end;
+            *carry = match carry {
+                Carry::Embedded(_) => Carry::Code,
+                _ => Carry::Embedded(Box::default()),
+            };
+        }
+        comments
+    }
+
+    /// Scans `line` like `scan`, but stops where one of `stops` is found
+    /// outside literals and comments, or ends a line comment, returning how
+    /// far it got past the stop.
+    fn scan_until(
+        &self,
+        line: &str,
+        carry: &mut Carry,
+        stops: &[&str],
+    ) -> (Vec<(Range<usize>, CommentKind)>, Option<usize>) {
+        let mut state = carry.clone();
+        let mut end = None;
+        let mut heredoc = None;
+        let directive = match state {
+            Carry::Code if self.preprocessor => preprocessor_directive(line),
+            _ => None,
+        };
+        let quoted_path = matches!(directive, Some("include" | "include_next" | "import"));
+        let message = matches!(directive, Some("error" | "warning"));
+        let mut escaped = false;
+        let mut comments = Vec::new();
+        let mut pos = 0;
+        while let Some(c) = line[pos..].chars().next() {
+            match state {
+                Carry::Code => {}
+                Carry::Embedded(_) => unreachable!("embedded regions are scanned by `scan`"),
+                Carry::Quote(open) => {
+                    if escaped {
+                        escaped = false;
+                    } else if c == '\\' && !self.verbatim_quotes.contains(&open) {
+                        escaped = true;
+                    } else if c == open {
+                        state = Carry::Code;
+                    }
+                    pos += c.len_utf8();
+                    continue;
+                }
+                Carry::TripleQuote(quotes) => {
+                    if escaped {
+                        escaped = false;
+                    } else if c == '\\' {
+                        escaped = true;
+                    } else if line[pos..].starts_with(quotes) {
+                        pos += quotes.len();
+                        state = Carry::Code;
+                        continue;
+                    }
+                    pos += c.len_utf8();
+                    continue;
+                }
+                Carry::VerbatimString => {
+                    if line[pos..].starts_with("\"\"") {
+                        pos += 2;
+                        continue;
+                    }
+                    if c == '"' {
+                        state = Carry::Code;
+                    }
+                    pos += c.len_utf8();
+                    continue;
+                }
+                Carry::DelimitedString(ref closer) => match line[pos..].find(closer.as_str()) {
+                    Some(offset) => {
+                        pos += offset + closer.len();
+                        state = Carry::Code;
+                        continue;
+                    }
+                    None => break,
+                },
+                Carry::RawString(hashes) => {
+                    let closer = format!("\"{}", "#".repeat(hashes));
+                    match line[pos..].find(&closer) {
+                        Some(offset) => {
+                            pos += offset + closer.len();
+                            state = Carry::Code;
+                            continue;
+                        }
+                        None => break,
+                    }
+                }
+                Carry::Heredoc {
+                    ref delimiter,
+                    indent,
+                } => {
+                    if line.trim_start_matches(indent) == delimiter {
+                        state = Carry::Code;
+                    }
+                    break;
+                }
+                Carry::LineBlock(closer) => {
+                    if self.is_line_block_marker(line, 0, closer) {
+                        state = Carry::Code;
+                    }
+                    comments.push((pos..line.len(), CommentKind::Block));
+                    break;
+                }
+                Carry::Literal {
+                    open,
+                    close,
+                    ref mut depth,
+                } => {
+                    if escaped {
+                        escaped = false;
+                    } else if c == '\\' {
+                        escaped = true;
+                    } else if c == close {
+                        *depth -= 1;
+                        if *depth == 0 {
+                            state = Carry::Code;
+                        }
+                    } else if c == open {
+                        *depth += 1;
+                    }
+                    pos += c.len_utf8();
+                    continue;
+                }
+                Carry::LongString(level) => match line[pos..].find(&long_closer(level)) {
+                    Some(offset) => {
+                        pos += offset + level + 2;
+                        state = Carry::Code;
+                        continue;
+                    }
+                    None => break,
+                },
+                Carry::LongComment(level) => match line[pos..].find(&long_closer(level)) {
+                    Some(offset) => {
+                        let end = pos + offset + level + 2;
+                        comments.push((pos..end, CommentKind::Block));
+                        pos = end;
+                        state = Carry::Code;
+                        continue;
+                    }
+                    None => {
+                        comments.push((pos..line.len(), CommentKind::Block));
+                        break;
+                    }
+                },
+                Carry::Block {
+                    opener,
+                    closer,
+                    mut depth,
+                } => match self.block_end(&line[pos..], opener, closer, &mut depth) {
+                    Some(offset) => {
+                        let end = pos + offset;
+                        comments.push((pos..end, CommentKind::Block));
+                        pos = end;
+                        state = Carry::Code;
+                        continue;
+                    }
+                    None => {
+                        comments.push((pos..line.len(), CommentKind::Block));
+                        state = Carry::Block {
+                            opener,
+                            closer,
+                            depth,
+                        };
+                        break;
+                    }
+                },
+            }
+            let rest = &line[pos..];
+            if let Some(stop) = stops.iter().find(|stop| rest.starts_with(**stop)) {
+                end = Some(pos + stop.len());
+                break;
+            }
+            if let Some(text) = self
+                .not_comments
+                .iter()
+                .find(|text| rest.starts_with(**text))
+            {
+                pos += text.len();
+                continue;
+            }
+            if self.rust_raw_strings
+                && let Some((prefix_len, hashes)) = raw_string_start(line, pos)
+            {
+                state = Carry::RawString(hashes);
+                pos += prefix_len;
+                continue;
+            }
+            if self.transpose && c == '\'' && ends_value(&line[..pos]) {
+                // Repeated, as in `a''`
+                pos += rest.len() - rest.trim_start_matches('\'').len();
+                continue;
+            }
+            if self.char_literals && c == '\'' {
+                pos += char_literal_len(rest);
+                continue;
+            }
+            if self.long_brackets {
+                if let Some(opening) = rest.strip_prefix("--")
+                    && let Some(level) = long_bracket_level(opening)
+                {
+                    let body = pos + level + 4;
+                    match line[body..].find(&long_closer(level)) {
+                        Some(offset) => {
+                            let end = body + offset + level + 2;
+                            comments.push((pos..end, CommentKind::Block));
+                            pos = end;
+                            continue;
+                        }
+                        None => {
+                            comments.push((pos..line.len(), CommentKind::Block));
+                            state = Carry::LongComment(level);
+                            break;
+                        }
+                    }
+                }
+                if let Some(level) = long_bracket_level(rest) {
+                    state = Carry::LongString(level);
+                    pos += level + 2;
+                    continue;
+                }
+            }
+            if let Some(&(_, closer)) = self
+                .line_blocks
+                .iter()
+                .find(|(opener, _)| self.is_line_block_marker(line, pos, opener))
+            {
+                comments.push((0..line.len(), CommentKind::Block));
+                state = Carry::LineBlock(closer);
+                break;
+            }
+            if self.ruby_literals {
+                let before = &line[..pos];
+                if heredoc.is_none()
+                    && let Some((len, delimiter, indent)) = ruby_heredoc_start(rest)
+                {
+                    heredoc = Some(Carry::Heredoc { delimiter, indent });
+                    pos += len;
+                    continue;
+                }
+                if let Some((len, open, close)) = percent_literal_start(before, rest) {
+                    state = Carry::Literal {
+                        open,
+                        close,
+                        depth: 1,
+                    };
+                    pos += len;
+                    continue;
+                }
+                if c == '/' && expects_operand(before) && !rest[1..].starts_with(' ') {
+                    state = Carry::Quote('/');
+                    pos += 1;
+                    continue;
+                }
+            }
+            if self.sigils
+                && let Some((len, open, close)) = sigil_start(rest)
+            {
+                state = Carry::Literal {
+                    open,
+                    close,
+                    depth: 1,
+                };
+                pos += len;
+                continue;
+            }
+            if Some(c) == self.char_code {
+                pos += c.len_utf8();
+                // The character, after a backslash for escapes such as `?\n`
+                if line[pos..].starts_with('\\') {
+                    pos += 1;
+                }
+                pos += line[pos..].chars().next().map_or(0, char::len_utf8);
+                continue;
+            }
+            if self.cpp_raw_strings
+                && let Some((opening_len, closer)) = cpp_raw_string_start(line, pos)
+            {
+                state = Carry::DelimitedString(closer);
+                pos += opening_len;
+                continue;
+            }
+            if self.verbatim_strings
+                && let Some(opening) = ["@\"", "@$\""].into_iter().find(|o| rest.starts_with(o))
+            {
+                state = Carry::VerbatimString;
+                pos += opening.len();
+                continue;
+            }
+            if self.swift_raw_strings
+                && let Some((opening_len, closer)) = swift_raw_string_start(rest)
+            {
+                state = Carry::DelimitedString(closer);
+                pos += opening_len;
+                continue;
+            }
+            if quoted_path
+                && c == '<'
+                && let Some(offset) = rest.find('>')
+            {
+                pos += offset + 1;
+                continue;
+            }
+            if self.digit_separators && c == '\'' && in_number(&line[..pos]) {
+                pos += 1;
+                continue;
+            }
+            let block = self
+                .block
+                .iter()
+                .find(|(opener, _)| rest.starts_with(opener))
+                .map(|&(opener, closer)| (pos, opener, closer))
+                .or_else(|| self.docstring_start(line, pos));
+            if let Some((start, opener, closer)) = block {
+                let mut depth = 1;
+                match self.block_end(&rest[opener.len()..], opener, closer, &mut depth) {
+                    Some(offset) => {
+                        let end = pos + opener.len() + offset;
+                        comments.push((start..end, CommentKind::Block));
+                        // Later literals may still be left open for the next line
+                        pos = end;
+                        continue;
+                    }
+                    None => {
+                        comments.push((start..line.len(), CommentKind::Block));
+                        state = Carry::Block {
+                            opener,
+                            closer,
+                            depth,
+                        };
+                        break;
+                    }
+                }
+            }
+            if self.shell_words {
+                if c == '\\' {
+                    pos += 1;
+                    pos += line[pos..].chars().next().map_or(0, char::len_utf8);
+                    continue;
+                }
+                if heredoc.is_none()
+                    && !in_arithmetic(&line[..pos])
+                    && let Some((len, delimiter, indent)) = heredoc_start(rest)
+                {
+                    heredoc = Some(Carry::Heredoc { delimiter, indent });
+                    pos += len;
+                    continue;
+                }
+            }
+            let starts_word = !self.shell_words
+                || line[..pos]
+                    .chars()
+                    .next_back()
+                    .is_none_or(|c| c.is_whitespace() || ";|&()".contains(c));
+            let operator = self.dash_operators
+                && rest
+                    .trim_start_matches('-')
+                    .starts_with(|c: char| "!#$%&*+./<=>?@\\^|~:".contains(c));
+            if starts_word && !operator && self.line.iter().any(|opener| rest.starts_with(opener)) {
+                let comment_end = stops
+                    .iter()
+                    .filter_map(|stop| rest.find(stop))
+                    .min()
+                    .map_or(line.len(), |offset| pos + offset);
+                comments.push((pos..comment_end, CommentKind::Line));
+                pos = comment_end;
+                continue;
+            }
+            if self.vim_comments
+                && c == '"'
+                && (line[..pos].trim_start_matches([' ', '\t', ':']).is_empty()
+                    || !closes_quote(&rest[1..], '"'))
+            {
+                comments.push((pos..line.len(), CommentKind::Line));
+                break;
+            }
+            if self.triple_quotes
+                && let Some(quotes) = TRIPLE_QUOTES.into_iter().find(|q| rest.starts_with(q))
+            {
+                state = Carry::TripleQuote(quotes);
+                pos += quotes.len();
+                continue;
+            }
+            if self.quotes.contains(&c) && !message {
+                state = Carry::Quote(c);
+            }
+            pos += c.len_utf8();
+        }
+        if let Some(heredoc) = heredoc
+            && state == Carry::Code
+        {
+            state = heredoc;
+        }
+        *carry = match state {
+            Carry::Quote(open) if !self.multiline_quotes.contains(&open) => Carry::Code,
+            state => state,
+        };
+        (comments, end)
+    }
+
+    /// If a docstring starts at `pos`, where it starts with its prefix, and
+    /// its quotes. Docstrings are taken for block comments closed by the same
+    /// quotes.
+    fn docstring_start(
+        &self,
+        line: &str,
+        pos: usize,
+    ) -> Option<(usize, &'static str, &'static str)> {
+        if !self.docstrings {
+            return None;
+        }
+        let quotes = TRIPLE_QUOTES
+            .into_iter()
+            .find(|q| line[pos..].starts_with(q))?;
+        // Only strings standing as statements of their own, raw or not
+        let prefix = line[..pos].trim_start();
+        matches!(prefix, "" | "r" | "R" | "u" | "U").then(|| (pos - prefix.len(), quotes, quotes))
+    }
+
+    /// Whether `comment`, starting with its opener, is a doc comment.
+    pub(crate) fn is_doc_comment(&self, comment: &str) -> bool {
+        self.doc.iter().any(|opener| {
+            // Not `////` or `/***`, nor the empty block comment `/**/`
+            comment
+                .strip_prefix(opener)
+                .is_some_and(|rest| !rest.starts_with(['/', '*']))
+        }) || self
+            .embedded
+            .as_ref()
+            .is_some_and(|embedded| embedded.syntax.is_doc_comment(comment))
+    }
+
+    /// Whether `comment`, starting with its opener, is a directive.
+    pub(crate) fn is_directive(&self, comment: &str) -> bool {
+        self.directives
+            .iter()
+            .any(|opener| comment.starts_with(opener))
+            || self
+                .embedded
+                .as_ref()
+                .is_some_and(|embedded| embedded.syntax.is_directive(comment))
+    }
+
+    /// Whether `marker`, a line block delimiter, is at `pos` on `line` and
+    /// placed as the delimiters must be.
+    fn is_line_block_marker(&self, line: &str, pos: usize, marker: &str) -> bool {
+        if self.lone_line_blocks {
+            line[..pos].trim().is_empty() && line.trim() == marker
+        } else {
+            pos == 0 && starts_with_word(line, marker)
+        }
+    }
+
+    /// Where in `text`, inside a block comment `depth` levels deep, the
+    /// comment ends, just past its closer. If it does not end in `text`,
+    /// `depth` is left at the level it reaches.
+    fn block_end(
+        &self,
+        text: &str,
+        opener: &str,
+        closer: &str,
+        depth: &mut usize,
+    ) -> Option<usize> {
+        if !self.nested_blocks {
+            return text.find(closer).map(|offset| offset + closer.len());
+        }
+        let mut pos = 0;
+        while pos < text.len() {
+            let rest = &text[pos..];
+            if rest.starts_with(closer) {
+                pos += closer.len();
+                *depth -= 1;
+                if *depth == 0 {
+                    return Some(pos);
+                }
+            } else if rest.starts_with(opener) {
+                pos += opener.len();
+                *depth += 1;
+            } else {
+                pos += rest.chars().next().map_or(1, char::len_utf8);
+            }
+        }
+        None
+    }
+}
+
+/// The name of the C preprocessor directive on `line`, such as `include`.
+fn preprocessor_directive(line: &str) -> Option<&str> {
+    let rest = line.trim_start().strip_prefix('#')?.trim_start();
+    let len = rest
+        .find(|c: char| !c.is_ascii_alphabetic() && c != '_')
+        .unwrap_or(rest.len());
+    Some(&rest[..len])
+}
+
+/// Whether `before`, the start of a line, ends with a value that an
+/// apostrophe would transpose, such as `a`, `x(1)` or `b.`. A closing quote
+/// is left out, as a following one escapes it.
+fn ends_value(before: &str) -> bool {
+    before
+        .chars()
+        .next_back()
+        .is_some_and(|c| c.is_alphanumeric() || "_)]}.".contains(c))
+}
+
+/// Whether `before`, the start of a line, ends inside a number such as
+/// `0x1F` or `1'000`.
+fn in_number(before: &str) -> bool {
+    let token =
+        before.trim_end_matches(|c: char| c.is_ascii_alphanumeric() || c == '_' || c == '\'');
+    before[token.len()..]
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_digit())
+}
+
+/// If a C++ raw string such as `R"x(` starts at `pos`, optionally after an
+/// encoding prefix, the length of its opening and its closer, like `)x"`.
+fn cpp_raw_string_start(line: &str, pos: usize) -> Option<(usize, String)> {
+    let rest = line[pos..].strip_prefix("R\"")?;
+    let before = &line[..pos];
+    let word = before.trim_end_matches(|c: char| c.is_alphanumeric() || c == '_');
+    if !matches!(&before[word.len()..], "" | "u8" | "u" | "U" | "L") {
+        return None;
+    }
+    let delimiter_len = rest.find('(')?;
+    let delimiter = &rest[..delimiter_len];
+    if delimiter.len() > 16 || delimiter.contains([' ', ')', '\\', '\t']) {
+        return None;
+    }
+    Some((delimiter_len + 3, format!("){}\"", delimiter)))
+}
+
+/// If a Swift raw string such as `#"` or `##"""` starts `text`, the length of
+/// its opening and its closer, like `"#` or `"""##`.
+fn swift_raw_string_start(text: &str) -> Option<(usize, String)> {
+    let rest = text.trim_start_matches('#');
+    let hashes = &text[..text.len() - rest.len()];
+    if hashes.is_empty() {
+        return None;
+    }
+    let quotes = ["\"\"\"", "\""].into_iter().find(|q| rest.starts_with(q))?;
+    Some((hashes.len() + quotes.len(), format!("{}{}", quotes, hashes)))
+}
+
+/// Whether `text`, inside a string opened by `quote`, closes it.
+fn closes_quote(text: &str, quote: char) -> bool {
+    let mut escaped = false;
+    for c in text.chars() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == quote {
+            return true;
+        }
+    }
+    false
+}
+
+/// If `text` starts with a here-document redirection such as `<<EOF`,
+/// `<<-'EOF'` or `<< "EOF"`, its length, the delimiter and what may indent
+/// it: tabs after `<<-`.
+fn heredoc_start(text: &str) -> Option<(usize, String, &'static [char])> {
+    let rest = text.strip_prefix("<<")?;
+    // A here-string
+    if rest.starts_with('<') {
+        return None;
+    }
+    let (indent, rest): (&'static [char], _) = match rest.strip_prefix('-') {
+        Some(rest) => (&['\t'], rest),
+        None => (&[], rest),
+    };
+    let rest = rest.trim_start_matches([' ', '\t']);
+    let word_len = rest
+        .find(|c: char| c.is_whitespace() || ";|&<>()".contains(c))
+        .unwrap_or(rest.len());
+    let delimiter: String = rest[..word_len]
+        .chars()
+        .filter(|c| !matches!(c, '\'' | '"' | '\\'))
+        .collect();
+    if delimiter.is_empty() {
+        return None;
+    }
+    Some((text.len() - rest.len() + word_len, delimiter, indent))
+}
+
+/// If `text` starts a Ruby heredoc such as `<<~SQL`, `<<-EOS` or `<<'EOS'`,
+/// its length, the delimiter and what may indent it: any whitespace after
+/// `<<~` and `<<-`.
+fn ruby_heredoc_start(text: &str) -> Option<(usize, String, &'static [char])> {
+    let rest = text.strip_prefix("<<")?;
+    let (indent, rest): (&'static [char], _) = match rest.strip_prefix(['~', '-']) {
+        Some(rest) => (&[' ', '\t'], rest),
+        None => (&[], rest),
+    };
+    let (delimiter, len) = match rest.chars().next()? {
+        quote @ ('\'' | '"' | '`') => {
+            let end = rest[1..].find(quote)?;
+            (&rest[1..end + 1], end + 2)
+        }
+        // `<<` followed by a lowercase name appends to an array
+        c if c.is_ascii_uppercase()
+            || c == '_'
+            || (!indent.is_empty() && c.is_ascii_alphabetic()) =>
+        {
+            let end = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            (&rest[..end], end)
+        }
+        _ => return None,
+    };
+    Some((text.len() - rest.len() + len, delimiter.to_string(), indent))
+}
+
+/// Whether `before`, the code before a `%` or `/`, ends where an operand is
+/// expected, so that it opens a literal rather than dividing.
+fn expects_operand(before: &str) -> bool {
+    let before = before.trim_end();
+    let word_len = before.len()
+        - before
+            .trim_end_matches(|c: char| c.is_alphanumeric() || c == '_')
+            .len();
+    match before.chars().next_back() {
+        None => true,
+        Some(c) if "([{,=|&!;~?:+-*<>".contains(c) => true,
+        _ => matches!(
+            &before[before.len() - word_len..],
+            "if" | "elsif"
+                | "unless"
+                | "while"
+                | "until"
+                | "when"
+                | "and"
+                | "or"
+                | "not"
+                | "return"
+        ),
+    }
+}
+
+/// If a Ruby percent literal such as `%w[` or `%q(` starts `text`, after
+/// `before`, the length of its opening and its delimiters.
+fn percent_literal_start(before: &str, text: &str) -> Option<(usize, char, char)> {
+    let rest = text.strip_prefix('%')?;
+    // `puts %w[a b]` takes a literal, `a % b` and `a %= b` a remainder
+    let spaced = before.ends_with(char::is_whitespace);
+    if !spaced && !expects_operand(before) {
+        return None;
+    }
+    let rest = rest
+        .strip_prefix(['q', 'Q', 'w', 'W', 'i', 'I', 'r', 's', 'x'])
+        .unwrap_or(rest);
+    let open = rest.chars().next()?;
+    if open.is_alphanumeric() || open.is_whitespace() || open == '=' {
+        return None;
+    }
+    Some((
+        text.len() - rest.len() + open.len_utf8(),
+        open,
+        closing(open),
+    ))
+}
+
+/// If an Elixir sigil such as `~r/` or `~S(` starts `text`, the length of its
+/// opening and its delimiters. Sigils in triple quotes are left to the
+/// triple-quote handling.
+fn sigil_start(text: &str) -> Option<(usize, char, char)> {
+    let rest = text.strip_prefix('~')?;
+    let name_len = match rest.chars().next()? {
+        c if c.is_ascii_lowercase() => 1,
+        c if c.is_ascii_uppercase() => rest
+            .find(|c: char| !c.is_ascii_uppercase() && !c.is_ascii_digit())
+            .unwrap_or(rest.len()),
+        _ => return None,
+    };
+    let rest = &rest[name_len..];
+    let open = rest.chars().next()?;
+    if !"/|\"'([{<".contains(open) || TRIPLE_QUOTES.iter().any(|q| rest.starts_with(q)) {
+        return None;
+    }
+    Some((text.len() - rest.len() + 1, open, closing(open)))
+}
+
+/// The delimiter closing a literal opened by `open`: the matching bracket,
+/// or `open` itself.
+fn closing(open: char) -> char {
+    match open {
+        '(' => ')',
+        '[' => ']',
+        '{' => '}',
+        '<' => '>',
+        other => other,
+    }
+}
+
+/// Whether `line` starts with `word`, followed by whitespace or nothing.
+fn starts_with_word(line: &str, word: &str) -> bool {
+    line.strip_prefix(word)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+}
+
+/// Whether `before`, the start of a line, leaves an arithmetic expression
+/// such as `$(( x` open, where `<<` shifts.
+fn in_arithmetic(before: &str) -> bool {
+    before
+        .rfind("((")
+        .is_some_and(|idx| !before[idx..].contains("))"))
+}
+
+/// The level of the Lua long bracket `text` starts with: the number of `=`
+/// between its brackets, as in `[==[`.
+fn long_bracket_level(text: &str) -> Option<usize> {
+    let rest = text.strip_prefix('[')?;
+    let level = rest.len() - rest.trim_start_matches('=').len();
+    rest[level..].starts_with('[').then_some(level)
+}
+
+/// The closing long bracket of `level`, as in `]==]`.
+fn long_closer(level: usize) -> String {
+    format!("]{}]", "=".repeat(level))
+}
+
+/// If a Rust raw string (`r"`, `r#"`, `br##"`...) starts at `pos`, the length
+/// of its opening and the number of `#` that close it.
+fn raw_string_start(line: &str, pos: usize) -> Option<(usize, usize)> {
+    let rest = &line[pos..];
+    let after_prefix = rest.strip_prefix("br").or_else(|| rest.strip_prefix('r'))?;
+    // Part of an identifier such as `for` or `bar`
+    if line[..pos]
+        .chars()
+        .next_back()
+        .is_some_and(|c| c.is_alphanumeric() || c == '_')
+    {
+        return None;
+    }
+    let hashes = after_prefix.len() - after_prefix.trim_start_matches('#').len();
+    after_prefix[hashes..]
+        .starts_with('"')
+        .then(|| (rest.len() - after_prefix.len() + hashes + 1, hashes))
+}
+
+/// The length of the character literal starting `rest`, or of just the quote
+/// when it starts a lifetime, label or symbol such as `'a` instead.
+fn char_literal_len(rest: &str) -> usize {
+    let mut chars = rest.char_indices().skip(1);
+    match chars.next() {
+        // An escape such as '\'' or '\u{1F600}' runs to the next quote after it
+        Some((_, '\\')) => match chars.next() {
+            Some((idx, escaped)) => {
+                let after = idx + escaped.len_utf8();
+                rest[after..]
+                    .find('\'')
+                    .map_or(1, |offset| after + offset + 1)
+            }
+            None => 1,
+        },
+        Some((_, _)) => match chars.next() {
+            Some((idx, '\'')) => idx + 1,
+            _ => 1,
+        },
+        None => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Markers;
+    use crate::clean::{apply_changes, find_changes};
+
+    #[test]
+    fn char_literals() {
+        assert_eq!(char_literal_len("'a' x"), 3);
+        assert_eq!(char_literal_len("'\\'' x"), 4);
+        assert_eq!(char_literal_len("'\\u{1F600}' x"), 11);
+        assert_eq!(char_literal_len("'é' x"), 4);
+        assert_eq!(char_literal_len("'\\é' x"), 5);
+        assert_eq!(char_literal_len("'\\é"), 1);
+        assert_eq!(char_literal_len("'\\"), 1);
+        assert_eq!(char_literal_len("'a: loop"), 1);
+        assert_eq!(char_literal_len("'"), 1);
+    }
+    fn clean(content: &str, ext: &str) -> String {
+        apply_changes(content, &find_changes(content, ext, &Markers::default()))
+    }
+
+    #[test]
+    fn strings_hide_comment_openers() {
+        assert_eq!(
+            clean("let s = \"// ‼️ keep\"; // ‼️ drop\n", "rs"),
+            "let s = \"// ‼️ keep\";\n"
+        );
+        assert_eq!(
+            clean("let s = \"a \\\" // ‼️ keep\"; // ‼️ drop\n", "rs"),
+            "let s = \"a \\\" // ‼️ keep\";\n"
+        );
+        assert_eq!(
+            clean("s = '# ‼️ keep'  # ‼️ drop\n", "py"),
+            "s = '# ‼️ keep'\n"
+        );
+    }
+
+    #[test]
+    fn js_template_literals() {
+        let content =
+            "const t = `a\n// ‼️ keep ${x}\n`; // ‼️ drop\nconst u = `${y}`; // ‼️ drop\n";
+        assert_eq!(
+            clean(content, "js"),
+            "const t = `a\n// ‼️ keep ${x}\n`;\nconst u = `${y}`;\n"
+        );
+        assert_eq!(
+            clean("const re = /\\/\\/ x/; // ‼️ drop\n", "ts"),
+            "const re = /\\/\\/ x/;\n"
+        );
+    }
+
+    #[test]
+    fn js_block_comments() {
+        assert_eq!(
+            clean("let a = 1; /* see http://x ‼️ */ let b;\n", "ts"),
+            "let a = 1;  let b;\n"
+        );
+        assert_eq!(
+            clean("let a = 1; /* see http://x */ let b;\n", "ts"),
+            "let a = 1; /* see http://x */ let b;\n"
+        );
+        assert_eq!(
+            clean(
+                "/* ‼️ start\n * http://x\n */\nf();\n/** @param ‼️ */\n",
+                "js"
+            ),
+            "\n\n\nf();\n/** @param ‼️ */\n"
+        );
+        assert_eq!(
+            clean("<a>{/* ‼️ */}</a>;\nx(); /* ‼️ */\n", "tsx"),
+            "<a></a>;\nx();\n"
+        );
+    }
+
+    #[test]
+    fn rust_raw_strings_chars_and_lifetimes() {
+        let content = r##"let r = r#"/* ‼️ " */"#; // ‼️ drop
+let c = '"'; // ‼️ drop
+fn f<'a>(x: &'a str) {} // ‼️ drop
+let m = "a
+// ‼️ keep
+"; // ‼️ drop
+"##;
+        assert_eq!(
+            clean(content, "rs"),
+            r##"let r = r#"/* ‼️ " */"#;
+let c = '"';
+fn f<'a>(x: &'a str) {}
+let m = "a
+// ‼️ keep
+";
+"##
+        );
+    }
+
+    #[test]
+    fn rust_nested_blocks() {
+        assert_eq!(
+            clean("/* outer /* inner ‼️ */ still */ x();\n", "rs"),
+            " x();\n"
+        );
+        assert_eq!(clean("/* a /* b */\n‼️ */ x();\n", "rs"), "\n x();\n");
+    }
+
+    #[test]
+    fn doc_comments_are_kept() {
+        let content = "/// doc ‼️\n//! inner ‼️\n//// plain ‼️\n/** doc ‼️ */\n";
+        assert_eq!(
+            clean(content, "rs"),
+            "/// doc ‼️\n//! inner ‼️\n\n/** doc ‼️ */\n"
+        );
+        let syntax = Syntax::for_language("c");
+        assert!(syntax.is_doc_comment("/*! doc */"));
+        assert!(!syntax.is_doc_comment("/***/"));
+        assert!(!syntax.is_doc_comment("/**/"));
+    }
+
+    #[test]
+    fn python_triple_quotes_and_docstrings() {
+        let content = "x = \"\"\"\n# ‼️ keep\n\"\"\"  # ‼️ drop\ndef f():\n    \"\"\"doc ‼️\"\"\"\n    '''\n    # ‼️ keep\n    '''  # ‼️ drop\n";
+        assert_eq!(
+            clean(content, "py"),
+            "x = \"\"\"\n# ‼️ keep\n\"\"\"\ndef f():\n    \"\"\"doc ‼️\"\"\"\n    '''\n    # ‼️ keep\n    '''\n"
+        );
+    }
+
+    #[test]
+    fn lua_long_brackets() {
+        let content = "t = [==[\n-- ‼️ keep ]]\n]==] --[[ ‼️ drop\n]] x()\n--[=[ ‼️ ]] still ]=] y()\n--- @param ‼️\n";
+        assert_eq!(
+            clean(content, "lua"),
+            "t = [==[\n-- ‼️ keep ]]\n]==]\n x()\n y()\n--- @param ‼️\n"
+        );
+    }
+
+    #[test]
+    fn vim_comments() {
+        let content =
+            "let s = \"\\\" ‼️\" \" ‼️ drop\n\" ‼️ drop line\nlet t = 'it''s \" ‼️' \" ‼️ drop\n";
+        assert_eq!(
+            clean(content, "vim"),
+            "let s = \"\\\" ‼️\"\n\nlet t = 'it''s \" ‼️'\n"
+        );
+    }
+
+    #[test]
+    fn shell_words_and_heredocs() {
+        let content = "echo a#b ‼️ keep # ‼️ drop\ncat <<-END\n\t# ‼️ keep\n\tEND\necho $((16#ff)) ${y#‼️} # ‼️ drop\necho 'it''s # ‼️' # ‼️ drop\n";
+        assert_eq!(
+            clean(content, "sh"),
+            "echo a#b ‼️ keep\ncat <<-END\n\t# ‼️ keep\n\tEND\necho $((16#ff)) ${y#‼️}\necho 'it''s # ‼️'\n"
+        );
+    }
+
+    #[test]
+    fn go_directives_and_raw_strings() {
+        let content = "//go:build linux ‼️\n// +build linux ‼️\ns := `// ‼️ keep\n`; c := '\"' // ‼️ drop\n/* ‼️ drop\n*/ x()\n";
+        assert_eq!(
+            clean(content, "go"),
+            "//go:build linux ‼️\n// +build linux ‼️\ns := `// ‼️ keep\n`; c := '\"'\n\n x()\n"
+        );
+    }
+
+    #[test]
+    fn c_preprocessor_and_literals() {
+        let content = r#"#include "a//‼️.h" // ‼️ drop
+#define X "/* ‼️" /* ‼️ drop */
+int n = 1'000; // ‼️ drop
+const char *r = R"x(// ‼️ )" keep)x"; // ‼️ drop
+char q = '"'; /* ‼️ */
+"#;
+        assert_eq!(
+            clean(content, "cpp"),
+            r#"#include "a//‼️.h"
+#define X "/* ‼️"
+int n = 1'000;
+const char *r = R"x(// ‼️ )" keep)x";
+char q = '"';
+"#
+        );
+    }
+
+    #[test]
+    fn java_text_blocks_and_kotlin_nesting() {
+        let content = "String t = \"\"\"\n  // ‼️ keep\n  \"\"\"; // ‼️ drop\n/** doc ‼️ */\n";
+        assert_eq!(
+            clean(content, "java"),
+            "String t = \"\"\"\n  // ‼️ keep\n  \"\"\";\n/** doc ‼️ */\n"
+        );
+        assert_eq!(clean("/* a /* ‼️ */ b */ x()\n", "kt"), " x()\n");
+        assert_eq!(
+            clean("val s = \"\"\"// ‼️ keep\"\"\" // ‼️ drop\n", "kt"),
+            "val s = \"\"\"// ‼️ keep\"\"\"\n"
+        );
+    }
+
+    #[test]
+    fn csharp_verbatim_strings() {
+        let content = r#"var p = @"C:\dir\"" // ‼️ keep"; // ‼️ drop
+/// <summary>‼️</summary>
+var i = $"{a} // ‼️ keep"; // ‼️ drop
+"#;
+        assert_eq!(
+            clean(content, "cs"),
+            r#"var p = @"C:\dir\"" // ‼️ keep";
+/// <summary>‼️</summary>
+var i = $"{a} // ‼️ keep";
+"#
+        );
+    }
+
+    #[test]
+    fn ruby_blocks_percent_literals_and_heredocs() {
+        let content = "=begin\n‼️ drop\n=end\nq = %q(# ‼️ (keep)) # ‼️ drop\nh = <<~EOS # ‼️ drop\n  # ‼️ keep\nEOS\nx = a % b # ‼️ drop\n";
+        assert_eq!(
+            clean(content, "rb"),
+            "\n\n\nq = %q(# ‼️ (keep))\nh = <<~EOS\n  # ‼️ keep\nEOS\nx = a % b\n"
+        );
+    }
+
+    #[test]
+    fn php_regions_in_html() {
+        let content =
+            "<p># ‼️ keep</p>\n<?php $a = \"# ‼️\"; # ‼️ drop\n// ‼️ drop\n?> <!-- ‼️ drop -->\n";
+        assert_eq!(
+            clean(content, "php"),
+            "<p># ‼️ keep</p>\n<?php $a = \"# ‼️\";\n\n?>\n"
+        );
+    }
+
+    #[test]
+    fn swift_raw_and_multi_line_strings() {
+        let content = r##"let r = #"// ‼️ "keep"# // ‼️ drop
+let m = """
+// ‼️ keep
+""" /* a /* ‼️ */ b */
+"##;
+        assert_eq!(
+            clean(content, "swift"),
+            r##"let r = #"// ‼️ "keep"#
+let m = """
+// ‼️ keep
+"""
+"##
+        );
+    }
+
+    #[test]
+    fn sql_comments() {
+        let content = "SELECT '-- ‼️ keep', 'it''s -- ‼️' -- ‼️ drop\n/* ‼️ drop */ FROM t;\n";
+        assert_eq!(
+            clean(content, "sql"),
+            "SELECT '-- ‼️ keep', 'it''s -- ‼️'\n FROM t;\n"
+        );
+    }
+
+    #[test]
+    fn haskell_nesting_pragmas_and_operators() {
+        let content = "{-# LANGUAGE GADTs ‼️ #-}\nx = \"-- ‼️\" -- ‼️ drop\n{- a {- ‼️ -} b -} y\na --> b -- ‼️ drop\n-- | doc ‼️\n";
+        assert_eq!(
+            clean(content, "hs"),
+            "{-# LANGUAGE GADTs ‼️ #-}\nx = \"-- ‼️\"\n y\na --> b\n-- | doc ‼️\n"
+        );
+    }
+
+    #[test]
+    fn elixir_sigils_and_erlang_chars() {
+        let content =
+            "s = ~s(# ‼️ keep) # ‼️ drop\nc = ?# # ‼️ drop\n@doc \"\"\"\n# ‼️ keep\n\"\"\"\n";
+        assert_eq!(
+            clean(content, "ex"),
+            "s = ~s(# ‼️ keep)\nc = ?#\n@doc \"\"\"\n# ‼️ keep\n\"\"\"\n"
+        );
+        assert_eq!(
+            clean("S = \"% ‼️\", % ‼️ drop\nC = $%. % ‼️ drop\n", "erl"),
+            "S = \"% ‼️\",\nC = $%.\n"
+        );
+    }
+
+    #[test]
+    fn ml_nested_comments() {
+        let content =
+            "let s = \"(* ‼️\" (* (* ‼️ *) *) in\nlet c = '\"' (* ‼️ drop *)\n(** doc ‼️ *)\n";
+        assert_eq!(
+            clean(content, "ml"),
+            "let s = \"(* ‼️\"  in\nlet c = '\"'\n(** doc ‼️ *)\n"
+        );
+        let content = "let s = @\"C:\\\" // ‼️ drop\nlet t = \"\"\"// ‼️ keep\"\"\" // ‼️ drop\n(* ‼️ *) x\n/// doc ‼️\n";
+        assert_eq!(
+            clean(content, "fs"),
+            "let s = @\"C:\\\"\nlet t = \"\"\"// ‼️ keep\"\"\"\n x\n/// doc ‼️\n"
+        );
+    }
+
+    #[test]
+    fn matlab_blocks_and_transposes() {
+        let content = "a = b'; % ‼️ drop\ns = 'it''s % ‼️'; % ‼️ drop\n%{\n‼️ drop\n%}\nx = [1 2]'; # ‼️ drop\n";
+        assert_eq!(
+            clean(content, "m"),
+            "a = b';\ns = 'it''s % ‼️';\n\n\n\nx = [1 2]';\n"
+        );
+    }
+}
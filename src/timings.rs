@@ -0,0 +1,94 @@
+//! Time spent in each phase of a run, collected for `--timings`.
+//!
+//! Measurements go to process-wide counters, so the phases can be timed deep
+//! inside file selection and processing without passing a recorder around.
+//! Nothing is measured until `enable` is called.
+
+use std::cmp::Reverse;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy)]
+pub enum Phase {
+    /// Finding the repository root and opening the repositories in it.
+    Discovery,
+    /// Walking directories or listing the files git reports.
+    Traversal,
+    /// Asking git whether paths are ignored or excluded by attributes. Checks
+    /// made while walking count toward traversal as well.
+    IgnoreChecks,
+    /// Reading and decoding files.
+    Reading,
+    /// Finding marked comments, including reading files cleaned line by line.
+    Parsing,
+    /// Writing cleaned files.
+    Writing,
+}
+
+const PHASES: [(Phase, &str); 6] = [
+    (Phase::Discovery, "discovery"),
+    (Phase::Traversal, "traversal"),
+    (Phase::IgnoreChecks, "ignore checks"),
+    (Phase::Reading, "reading"),
+    (Phase::Parsing, "parsing"),
+    (Phase::Writing, "writing"),
+];
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static TOTALS: [AtomicU64; 6] = [const { AtomicU64::new(0) }; 6];
+static FILES: Mutex<Vec<(String, Duration)>> = Mutex::new(Vec::new());
+
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Runs `f`, adding the time it takes to `phase`.
+pub fn measure<T>(phase: Phase, f: impl FnOnce() -> T) -> T {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return f();
+    }
+    let started = Instant::now();
+    let result = f();
+    let nanos = u64::try_from(started.elapsed().as_nanos()).unwrap_or(u64::MAX);
+    TOTALS[phase as usize].fetch_add(nanos, Ordering::Relaxed);
+    result
+}
+
+/// Runs `f`, which processes the file shown as `label`, recording its time
+/// for the list of slowest files.
+pub fn measure_file<T>(label: &str, f: impl FnOnce() -> T) -> T {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return f();
+    }
+    let started = Instant::now();
+    let result = f();
+    let elapsed = started.elapsed();
+    if let Ok(mut files) = FILES.lock() {
+        files.push((label.to_string(), elapsed));
+    }
+    result
+}
+
+/// Prints the time of each phase and the `slowest` files that took longest.
+pub fn report(slowest: usize) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    eprintln!("Timings:");
+    for (phase, name) in PHASES {
+        let total = Duration::from_nanos(TOTALS[phase as usize].load(Ordering::Relaxed));
+        eprintln!("  {:<14} {:>10.2}ms", name, total.as_secs_f64() * 1000.0);
+    }
+    let Ok(mut files) = FILES.lock() else {
+        return;
+    };
+    if files.is_empty() {
+        return;
+    }
+    files.sort_by_key(|&(_, elapsed)| Reverse(elapsed));
+    eprintln!("Slowest files:");
+    for (label, elapsed) in files.iter().take(slowest) {
+        eprintln!("  {:>10.2}ms  {}", elapsed.as_secs_f64() * 1000.0, label);
+    }
+}
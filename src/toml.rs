@@ -0,0 +1,392 @@
+//! A parser for the subset of TOML used by `.emoji-remover.toml`: `[table]`
+//! headers, `key = value` pairs, basic and literal strings, booleans, integers
+//! and (possibly multi-line) arrays. Comments start with `#` outside strings.
+
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::Integer(_) => "integer",
+            Value::Boolean(_) => "boolean",
+            Value::Array(_) => "array",
+        }
+    }
+}
+
+/// Keys of the top-level table live under the empty table name.
+pub type Document = BTreeMap<String, BTreeMap<String, Value>>;
+
+#[derive(Debug)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+pub fn parse(text: &str) -> Result<Document, ParseError> {
+    let mut parser = Parser {
+        chars: text.chars().collect(),
+        pos: 0,
+        line: 1,
+    };
+    let mut document = Document::new();
+    let mut table = String::new();
+    document.insert(table.clone(), BTreeMap::new());
+
+    loop {
+        parser.skip_trivia();
+        let Some(c) = parser.peek() else { break };
+        if c == '[' {
+            parser.pos += 1;
+            parser.skip_spaces();
+            table = parser.parse_key()?;
+            parser.skip_spaces();
+            parser.expect(']')?;
+            if document.contains_key(&table) {
+                return Err(parser.error(format!("table [{}] defined twice", table)));
+            }
+            document.insert(table.clone(), BTreeMap::new());
+        } else {
+            let key = parser.parse_key()?;
+            parser.skip_spaces();
+            parser.expect('=')?;
+            parser.skip_spaces();
+            let value = parser.parse_value()?;
+            let entries = document.get_mut(&table).expect("current table exists");
+            if entries.insert(key.clone(), value).is_some() {
+                return Err(parser.error(format!("key '{}' defined twice", key)));
+            }
+        }
+        parser.end_of_line()?;
+    }
+    Ok(document)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+
+impl Parser {
+    fn error(&self, message: String) -> ParseError {
+        ParseError {
+            line: self.line,
+            message,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ParseError> {
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(c) => Err(self.error(format!("expected '{}', found '{}'", expected, c))),
+            None => Err(self.error(format!("expected '{}', found end of file", expected))),
+        }
+    }
+
+    fn skip_spaces(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.pos += 1;
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        if self.peek() == Some('#') {
+            while !matches!(self.peek(), Some('\n') | None) {
+                self.pos += 1;
+            }
+        }
+    }
+
+    /// Skips whitespace, newlines and comments.
+    fn skip_trivia(&mut self) {
+        loop {
+            self.skip_spaces();
+            self.skip_comment();
+            match self.peek() {
+                Some('\n') => {
+                    self.line += 1;
+                    self.pos += 1;
+                }
+                Some('\r') => self.pos += 1,
+                _ => break,
+            }
+        }
+    }
+
+    fn end_of_line(&mut self) -> Result<(), ParseError> {
+        self.skip_spaces();
+        self.skip_comment();
+        match self.peek() {
+            None | Some('\n') | Some('\r') => Ok(()),
+            Some(c) => Err(self.error(format!("unexpected '{}' after value", c))),
+        }
+    }
+
+    /// Parses a bare or quoted key. Dotted keys are returned as written.
+    fn parse_key(&mut self) -> Result<String, ParseError> {
+        match self.peek() {
+            Some('"') => self.parse_basic_string(),
+            Some('\'') => self.parse_literal_string(),
+            _ => {
+                let start = self.pos;
+                while self
+                    .peek()
+                    .is_some_and(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+                {
+                    self.pos += 1;
+                }
+                if start == self.pos {
+                    return Err(self.error("expected a key".to_string()));
+                }
+                Ok(self.chars[start..self.pos].iter().collect())
+            }
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Value, ParseError> {
+        match self.peek() {
+            Some('"') => self.parse_basic_string().map(Value::String),
+            Some('\'') => self.parse_literal_string().map(Value::String),
+            Some('[') => self.parse_array(),
+            Some(c) if c == 't' || c == 'f' => {
+                for (word, value) in [("true", true), ("false", false)] {
+                    let end = self.pos + word.len();
+                    if self
+                        .chars
+                        .get(self.pos..end)
+                        .is_some_and(|w| w.iter().copied().eq(word.chars()))
+                    {
+                        self.pos = end;
+                        return Ok(Value::Boolean(value));
+                    }
+                }
+                Err(self.error("expected a value".to_string()))
+            }
+            Some(c) if c.is_ascii_digit() || c == '-' || c == '+' => {
+                let start = self.pos;
+                self.pos += 1;
+                while self.peek().is_some_and(|c| c.is_ascii_digit() || c == '_') {
+                    self.pos += 1;
+                }
+                let digits: String = self.chars[start..self.pos]
+                    .iter()
+                    .filter(|&&c| c != '_')
+                    .collect();
+                digits
+                    .parse()
+                    .map(Value::Integer)
+                    .map_err(|_| self.error(format!("invalid integer '{}'", digits)))
+            }
+            Some(c) => Err(self.error(format!("unexpected '{}' where a value was expected", c))),
+            None => Err(self.error("expected a value, found end of file".to_string())),
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<Value, ParseError> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        loop {
+            self.skip_trivia();
+            if self.peek() == Some(']') {
+                self.pos += 1;
+                return Ok(Value::Array(items));
+            }
+            items.push(self.parse_value()?);
+            self.skip_trivia();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some(']') => {}
+                _ => return Err(self.error("expected ',' or ']' in array".to_string())),
+            }
+        }
+    }
+
+    fn parse_basic_string(&mut self) -> Result<String, ParseError> {
+        self.expect('"')?;
+        let mut out = String::new();
+        loop {
+            match self.peek() {
+                None | Some('\n') => return Err(self.error("unterminated string".to_string())),
+                Some('"') => {
+                    self.pos += 1;
+                    return Ok(out);
+                }
+                Some('\\') => {
+                    self.pos += 1;
+                    let escaped = match self.peek() {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some(c @ ('u' | 'U')) => {
+                            let len = if c == 'u' { 4 } else { 8 };
+                            let hex: String = self
+                                .chars
+                                .get(self.pos + 1..self.pos + 1 + len)
+                                .unwrap_or_default()
+                                .iter()
+                                .collect();
+                            let decoded = u32::from_str_radix(&hex, 16)
+                                .ok()
+                                .filter(|_| {
+                                    hex.len() == len && hex.chars().all(|c| c.is_ascii_hexdigit())
+                                })
+                                .and_then(char::from_u32)
+                                .ok_or_else(|| {
+                                    self.error(format!("invalid unicode escape '\\{}{}'", c, hex))
+                                })?;
+                            self.pos += len;
+                            decoded
+                        }
+                        Some(c) => return Err(self.error(format!("invalid escape '\\{}'", c))),
+                        None => return Err(self.error("unterminated string".to_string())),
+                    };
+                    out.push(escaped);
+                    self.pos += 1;
+                }
+                Some(c) => {
+                    out.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+
+    fn parse_literal_string(&mut self) -> Result<String, ParseError> {
+        self.expect('\'')?;
+        let start = self.pos;
+        loop {
+            match self.peek() {
+                None | Some('\n') => return Err(self.error("unterminated string".to_string())),
+                Some('\'') => {
+                    let out = self.chars[start..self.pos].iter().collect();
+                    self.pos += 1;
+                    return Ok(out);
+                }
+                Some(_) => self.pos += 1,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(text: &str) -> (usize, String) {
+        let err = parse(text).unwrap_err();
+        (err.line, err.message)
+    }
+
+    #[test]
+    fn tables_and_values() {
+        let document = parse(
+            "# config\n\
+             include = [\"*.rs\", 'src/**'] # trailing\n\
+             all_emojis = true\n\
+             depth = -1_000\n\
+             \n\
+             [languages]\n\
+             \"h\" = 'c'\n",
+        )
+        .unwrap();
+        let top = &document[""];
+        assert_eq!(
+            top["include"],
+            Value::Array(vec![
+                Value::String("*.rs".to_string()),
+                Value::String("src/**".to_string()),
+            ])
+        );
+        assert_eq!(top["all_emojis"], Value::Boolean(true));
+        assert_eq!(top["depth"], Value::Integer(-1000));
+        assert_eq!(document["languages"]["h"], Value::String("c".to_string()));
+    }
+
+    #[test]
+    fn multi_line_arrays() {
+        let document =
+            parse("marker = [\r\n  \"a\", # first\r\n  \"b\",\r\n]\r\nx = 1\r\n").unwrap();
+        assert_eq!(
+            document[""]["marker"],
+            Value::Array(vec![
+                Value::String("a".to_string()),
+                Value::String("b".to_string()),
+            ])
+        );
+        assert_eq!(document[""]["x"], Value::Integer(1));
+    }
+
+    #[test]
+    fn string_escapes() {
+        let document = parse(r#"a = "tab\tquote\" \u00e9 \U0001F600""#).unwrap();
+        assert_eq!(
+            document[""]["a"],
+            Value::String("tab\tquote\" é 😀".to_string())
+        );
+        let document = parse(r"a = 'C:\dir\# not a comment'").unwrap();
+        assert_eq!(
+            document[""]["a"],
+            Value::String(r"C:\dir\# not a comment".to_string())
+        );
+    }
+
+    #[test]
+    fn errors_carry_the_line() {
+        assert_eq!(
+            error("a = 1\nb = \"open\n"),
+            (2, "unterminated string".to_string())
+        );
+        assert_eq!(
+            error("a = 1\na = 2"),
+            (2, "key 'a' defined twice".to_string())
+        );
+        assert_eq!(
+            error("[t]\n[t]"),
+            (2, "table [t] defined twice".to_string())
+        );
+        assert_eq!(
+            error("a = 1 2"),
+            (1, "unexpected '2' after value".to_string())
+        );
+        assert_eq!(error("a = tru"), (1, "expected a value".to_string()));
+        assert_eq!(
+            error("a = [1 2]"),
+            (1, "expected ',' or ']' in array".to_string())
+        );
+        assert_eq!(
+            error(r#"a = "\q""#),
+            (1, r"invalid escape '\q'".to_string())
+        );
+        assert_eq!(
+            error(r#"a = "\u+041""#),
+            (1, r"invalid unicode escape '\u+041'".to_string())
+        );
+        assert_eq!(
+            error(r#"a = "\uD800""#).1,
+            r"invalid unicode escape '\uD800'"
+        );
+        assert_eq!(
+            error("a = 99999999999999999999").1,
+            "invalid integer '99999999999999999999'"
+        );
+    }
+}
@@ -0,0 +1,64 @@
+//! Writes cleaned content back to disk.
+
+use crate::AppError;
+use emoji_remover::write_atomic_with;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Where `--backup` keeps the original of each rewritten file.
+#[derive(Debug, Clone)]
+pub struct Backup {
+    /// Appended to the file name, e.g. `main.rs` becomes `main.rs.bak`.
+    pub suffix: String,
+    /// Backups go under this directory, mirroring the repository layout,
+    /// instead of next to the original.
+    pub dir: Option<PathBuf>,
+}
+
+impl Backup {
+    fn path_for(&self, path: &Path, label: &str) -> PathBuf {
+        let base = match &self.dir {
+            Some(dir) => dir.join(label),
+            None => path.to_path_buf(),
+        };
+        let mut name = base.file_name().unwrap_or_default().to_os_string();
+        name.push(&self.suffix);
+        base.with_file_name(name)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct FileWriter {
+    pub backup: Option<Backup>,
+    /// Give rewritten files their original modification time back.
+    pub keep_mtime: bool,
+}
+
+impl FileWriter {
+    /// Replaces the contents of `path` (shown as `label`) with `output`, keeping
+    /// a backup of the original first when requested.
+    pub fn write(&self, path: &Path, label: &str, output: &[u8]) -> Result<(), AppError> {
+        self.write_with(path, label, |out| out.write_all(output))
+    }
+
+    /// Like `write`, with the new contents produced by `fill` so they need not
+    /// be held in memory.
+    pub fn write_with(
+        &self,
+        path: &Path,
+        label: &str,
+        fill: impl FnOnce(&mut dyn Write) -> io::Result<()>,
+    ) -> Result<(), AppError> {
+        if let Some(backup) = &self.backup {
+            let backup_path = backup.path_for(path, label);
+            if let Some(dir) = backup_path.parent() {
+                fs::create_dir_all(dir).map_err(|e| AppError::FileWrite(dir.to_path_buf(), e))?;
+            }
+            fs::copy(path, &backup_path)
+                .map_err(|e| AppError::FileWrite(backup_path.clone(), e))?;
+        }
+        write_atomic_with(path, self.keep_mtime, fill)
+            .map_err(|e| AppError::FileWrite(path.to_path_buf(), e))
+    }
+}